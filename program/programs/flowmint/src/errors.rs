@@ -78,4 +78,88 @@ pub enum FlowMintError {
     /// Jupiter CPI call failed
     #[msg("Jupiter swap execution failed")]
     JupiterSwapFailed,
+
+    /// The oracle account is missing, malformed, or not fully verified
+    #[msg("Invalid oracle price account")]
+    InvalidOracle,
+
+    /// The oracle price is too old to be used
+    #[msg("Oracle price is stale")]
+    StaleOraclePrice,
+
+    /// The same account was passed for roles that must be distinct
+    #[msg("Duplicate account passed where distinct accounts are required")]
+    DuplicateAccount,
+
+    /// A FeeVault for the fee mint is required but was not provided
+    #[msg("Fee vault account is required for this mint")]
+    MissingFeeVault,
+
+    /// Swaps are currently paused
+    #[msg("Swaps are currently paused")]
+    SwapsPaused,
+
+    /// Payments are currently paused
+    #[msg("Payments are currently paused")]
+    PaymentsPaused,
+
+    /// The swap consumed more input than the route quoted
+    #[msg("Swap consumed more input than quoted")]
+    InputOverspend,
+
+    /// The AMM allowlist is enabled but the account was not provided
+    #[msg("AMM allowlist account is required")]
+    MissingAmmAllowlist,
+
+    /// A timelocked change was applied before its ETA
+    #[msg("Timelock has not elapsed")]
+    TimelockNotElapsed,
+
+    /// No treasury change has been proposed
+    #[msg("No pending treasury change")]
+    NoPendingTreasury,
+
+    /// The route has more hops than protected mode allows
+    #[msg("Route has too many hops for protected mode")]
+    TooManyHops,
+
+    /// Route fees imply an impact above the protected-mode maximum
+    #[msg("Route fee impact exceeds maximum threshold")]
+    FeeImpactTooHigh,
+
+    /// The token account is not the owner's canonical associated token account
+    #[msg("Token account is not the canonical associated token account")]
+    NonCanonicalTokenAccount,
+
+    /// The temp USDC account is not the payer's canonical temp PDA
+    #[msg("Temp USDC account is not the canonical PDA for this payer")]
+    InvalidTempAccount,
+
+    /// The temp USDC balance decreased across the swap
+    #[msg("Temp USDC balance decreased during the swap")]
+    TempBalanceDecreased,
+
+    /// The receipt is still within its retention window
+    #[msg("Receipt is too recent to close")]
+    ReceiptTooRecent,
+
+    /// The account already has the current layout
+    #[msg("Account is already at its current size")]
+    AccountAlreadyMigrated,
+
+    /// The oracle price's confidence interval is too wide to rely on
+    #[msg("Oracle confidence interval is too wide")]
+    OracleConfidenceTooWide,
+
+    /// A swap named its own user as the referrer
+    #[msg("User cannot refer their own swap")]
+    SelfReferral,
+
+    /// A protected swap didn't pass its pair's TWAP account
+    #[msg("Protected swaps require the pair's TWAP account")]
+    MissingPriceTwap,
+
+    /// A payment's change is implausibly large for the input it swapped
+    #[msg("Refund exceeds the allowed share of the swapped input")]
+    RefundExceedsInput,
 }
@@ -3,13 +3,18 @@
 //! Administrative functions for protocol management.
 
 use anchor_lang::prelude::*;
-use anchor_spl::token::{self, Token, TokenAccount, Transfer};
+use anchor_lang::solana_program::hash::hash;
+use anchor_spl::token_interface;
 
 use crate::errors::FlowMintError;
-use crate::state::ProtocolConfig;
-
-/// Maximum allowed slippage in basis points
-pub const MAX_SLIPPAGE_BPS: u16 = 5000;
+use crate::instructions::initialize::{validate_treasury, MAX_PROTOCOL_FEE_BPS, MAX_SLIPPAGE_BPS};
+use crate::state::{
+    AmmAllowlist, FeePolicy, FeeTier, MemoPolicy, OracleFeed, PausableFeature, PendingConfig,
+    ProtocolConfig, SlippageTier, UserStats, MAX_DIRECT_SETTLEMENT_MINTS, MAX_FEE_TIERS,
+    MAX_OPERATORS, MAX_ORACLE_FEEDS, MAX_SLIPPAGE_TIERS, MAX_STAGED_CONFIG_LEN,
+    MIN_TREASURY_TIMELOCK_SECONDS,
+};
+use crate::token_transfer::transfer_tokens;
 
 /// Accounts for admin configuration updates
 #[derive(Accounts)]
@@ -29,77 +34,498 @@ pub struct UpdateConfig<'info> {
     pub config: Account<'info, ProtocolConfig>,
 }
 
+/// Accounts for lower-risk actions available to operators and the authority
+#[derive(Accounts)]
+pub struct OperatorAction<'info> {
+    /// The protocol authority or a registered operator
+    #[account(
+        constraint = config.can_operate(&operator.key()) @ FlowMintError::Unauthorized
+    )]
+    pub operator: Signer<'info>,
+
+    /// Protocol configuration
+    #[account(
+        mut,
+        seeds = [b"config"],
+        bump = config.bump
+    )]
+    pub config: Account<'info, ProtocolConfig>,
+}
+
+/// Optional configuration changes applied by `update_config`
+///
+/// Fields left as `None` are unchanged.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Debug, Default)]
+pub struct UpdateConfigParams {
+    /// New default slippage
+    pub default_slippage_bps: Option<u16>,
+    /// New protected slippage
+    pub protected_slippage_bps: Option<u16>,
+    /// New max price impact
+    pub max_price_impact_bps: Option<u16>,
+    /// New protocol fee
+    pub protocol_fee_bps: Option<u16>,
+    /// New cap on output above quote (0 = unbounded)
+    pub max_positive_slippage_bps: Option<u16>,
+    /// New maximum quote TTL in seconds
+    pub max_quote_ttl_seconds: Option<i64>,
+    /// New referrer share of the protocol fee
+    pub referral_share_bps: Option<u16>,
+    /// New volume-based fee tiers
+    pub fee_tiers: Option<[FeeTier; MAX_FEE_TIERS]>,
+    /// New quote expiration grace window in seconds
+    pub quote_grace_seconds: Option<i64>,
+    /// New per-payment USDC cap (0 = unlimited)
+    pub max_payment_usdc: Option<u64>,
+    /// Who bears the protocol fee on payments
+    pub fee_policy: Option<FeePolicy>,
+    /// New slippage tiers, with thresholds in USD notional (1e6 scale)
+    pub slippage_tiers: Option<[SlippageTier; MAX_SLIPPAGE_TIERS]>,
+    /// New delay for treasury changes (at least `MIN_TREASURY_TIMELOCK_SECONDS`)
+    pub treasury_timelock_seconds: Option<i64>,
+    /// New maximum route hops in protected mode (0 = unlimited)
+    pub max_hops_protected: Option<u8>,
+    /// New inactivity window for resetting user volume (0 = never)
+    pub stats_max_age_seconds: Option<i64>,
+    /// New alternate mints accepted for direct payments
+    pub direct_settlement_mints: Option<[Pubkey; MAX_DIRECT_SETTLEMENT_MINTS]>,
+    /// Require merchant USDC accounts to be canonical ATAs
+    pub require_canonical_ata: Option<bool>,
+    /// New maximum payment change in bps of the USDC received (0 = unlimited)
+    pub max_refund_bps: Option<u16>,
+    /// New maximum quote TTL for payments (0 = same as swaps)
+    pub payment_max_quote_ttl_seconds: Option<i64>,
+    /// New payment memo policy
+    pub memo_policy: Option<MemoPolicy>,
+    /// New cap on remaining accounts (0 = unlimited)
+    pub max_remaining_accounts: Option<u16>,
+    /// New maximum decimals-adjusted route rate (0 = disabled)
+    pub max_route_rate_ratio: Option<u64>,
+}
+
 /// Update protocol configuration
 ///
 /// # Arguments
 ///
 /// * `ctx` - UpdateConfig context
-/// * `new_default_slippage_bps` - New default slippage (optional)
-/// * `new_protected_slippage_bps` - New protected slippage (optional)
-/// * `new_max_price_impact_bps` - New max price impact (optional)
+/// * `params` - Configuration changes to apply
 ///
 /// # Returns
 ///
 /// * `Result<()>` - Success or error
-pub fn update_config_handler(
-    ctx: Context<UpdateConfig>,
-    new_default_slippage_bps: Option<u16>,
-    new_protected_slippage_bps: Option<u16>,
-    new_max_price_impact_bps: Option<u16>,
-    new_protocol_fee_bps: Option<u16>,
-    new_treasury: Option<Pubkey>,
-) -> Result<()> {
+pub fn update_config_handler(ctx: Context<UpdateConfig>, params: UpdateConfigParams) -> Result<()> {
     let config = &mut ctx.accounts.config;
 
+    apply_config_params(config, &params)?;
+
+    let sequence = config.next_sequence()?;
+
+    let event = ConfigUpdated {
+        authority: ctx.accounts.authority.key(),
+        default_slippage_bps: config.default_slippage_bps,
+        protected_slippage_bps: config.protected_slippage_bps,
+        max_price_impact_bps: config.max_price_impact_bps,
+        sequence,
+    };
+    emit_admin_action(ctx.accounts.authority.key(), AdminActionKind::UpdateConfig, &params)?;
+    emit!(event);
+
+    Ok(())
+}
+
+/// Apply a set of config changes and re-validate the invariants
+///
+/// Shared by `update_config` and `apply_staged_config` so staged changes go
+/// through exactly the same checks as direct updates.
+pub fn apply_config_params(config: &mut ProtocolConfig, params: &UpdateConfigParams) -> Result<()> {
     // Update default slippage if provided
-    if let Some(slippage) = new_default_slippage_bps {
+    if let Some(slippage) = params.default_slippage_bps {
         require!(slippage <= MAX_SLIPPAGE_BPS, FlowMintError::InvalidConfiguration);
         config.default_slippage_bps = slippage;
         msg!("Updated default_slippage_bps to {}", slippage);
     }
 
     // Update protected slippage if provided
-    if let Some(slippage) = new_protected_slippage_bps {
+    if let Some(slippage) = params.protected_slippage_bps {
         require!(slippage <= MAX_SLIPPAGE_BPS, FlowMintError::InvalidConfiguration);
-        require!(
-            slippage <= config.default_slippage_bps,
-            FlowMintError::InvalidConfiguration
-        );
         config.protected_slippage_bps = slippage;
         msg!("Updated protected_slippage_bps to {}", slippage);
     }
 
     // Update max price impact if provided
-    if let Some(impact) = new_max_price_impact_bps {
+    if let Some(impact) = params.max_price_impact_bps {
         require!(impact <= MAX_SLIPPAGE_BPS, FlowMintError::InvalidConfiguration);
         config.max_price_impact_bps = impact;
         msg!("Updated max_price_impact_bps to {}", impact);
     }
 
     // Update protocol fee bps if provided
-    if let Some(fee_bps) = new_protocol_fee_bps {
-        require!(fee_bps <= 10_000, FlowMintError::InvalidConfiguration);
+    if let Some(fee_bps) = params.protocol_fee_bps {
+        require!(fee_bps <= MAX_PROTOCOL_FEE_BPS, FlowMintError::InvalidConfiguration);
         config.protocol_fee_bps = fee_bps;
         msg!("Updated protocol_fee_bps to {}", fee_bps);
     }
 
-    // Update treasury if provided
-    if let Some(treasury) = new_treasury {
-        config.treasury = treasury;
-        msg!("Updated treasury to {}", treasury);
+    // Update max positive slippage if provided
+    if let Some(bps) = params.max_positive_slippage_bps {
+        require!(bps <= 10_000, FlowMintError::InvalidConfiguration);
+        config.max_positive_slippage_bps = bps;
+        msg!("Updated max_positive_slippage_bps to {}", bps);
+    }
+
+    // Update max quote TTL if provided
+    if let Some(ttl) = params.max_quote_ttl_seconds {
+        require!(ttl > 0, FlowMintError::InvalidConfiguration);
+        config.max_quote_ttl_seconds = ttl;
+        msg!("Updated max_quote_ttl_seconds to {}", ttl);
+    }
+
+    // Update referral share if provided
+    if let Some(share_bps) = params.referral_share_bps {
+        require!(share_bps <= 10_000, FlowMintError::InvalidConfiguration);
+        config.referral_share_bps = share_bps;
+        msg!("Updated referral_share_bps to {}", share_bps);
+    }
+
+    // Update fee tiers if provided
+    if let Some(fee_tiers) = params.fee_tiers {
+        config.fee_tiers = fee_tiers;
+        msg!("Updated fee_tiers");
+    }
+
+    // Update quote grace window if provided (bounds checked by invariants)
+    if let Some(grace) = params.quote_grace_seconds {
+        config.quote_grace_seconds = grace;
+        msg!("Updated quote_grace_seconds to {}", grace);
+    }
+
+    // Update payment cap if provided
+    if let Some(max_payment_usdc) = params.max_payment_usdc {
+        config.max_payment_usdc = max_payment_usdc;
+        msg!("Updated max_payment_usdc to {}", max_payment_usdc);
+    }
+
+    if let Some(fee_policy) = params.fee_policy {
+        config.fee_policy = fee_policy;
+        msg!("Updated fee_policy to {:?}", fee_policy);
+    }
+
+    // Update slippage tiers if provided
+    if let Some(slippage_tiers) = params.slippage_tiers {
+        config.slippage_tiers = slippage_tiers;
+        msg!("Updated slippage_tiers");
+    }
+
+    // Applies to future treasury proposals only. A floor keeps an update from
+    // collapsing the timelock and swapping the treasury in one transaction.
+    if let Some(timelock) = params.treasury_timelock_seconds {
+        require!(
+            timelock >= MIN_TREASURY_TIMELOCK_SECONDS,
+            FlowMintError::InvalidConfiguration
+        );
+        config.treasury_timelock_seconds = timelock;
+        msg!("Updated treasury_timelock_seconds to {}", timelock);
+    }
+
+    if let Some(max_hops) = params.max_hops_protected {
+        config.max_hops_protected = max_hops;
+        msg!("Updated max_hops_protected to {}", max_hops);
+    }
+
+    if let Some(max_age) = params.stats_max_age_seconds {
+        config.stats_max_age_seconds = max_age;
+        msg!("Updated stats_max_age_seconds to {}", max_age);
+    }
+
+    if let Some(mints) = params.direct_settlement_mints {
+        config.direct_settlement_mints = mints;
+        msg!("Updated direct_settlement_mints");
+    }
+
+    if let Some(required) = params.require_canonical_ata {
+        config.require_canonical_ata = required;
+        msg!("Updated require_canonical_ata to {}", required);
+    }
+
+    if let Some(max_refund) = params.max_refund_bps {
+        config.max_refund_bps = max_refund;
+        msg!("Updated max_refund_bps to {}", max_refund);
+    }
+
+    if let Some(ttl) = params.payment_max_quote_ttl_seconds {
+        config.payment_max_quote_ttl_seconds = ttl;
+        msg!("Updated payment_max_quote_ttl_seconds to {}", ttl);
+    }
+
+    if let Some(memo_policy) = params.memo_policy {
+        config.memo_policy = memo_policy;
+        msg!("Updated memo_policy to {:?}", memo_policy);
+    }
+
+    if let Some(max_accounts) = params.max_remaining_accounts {
+        config.max_remaining_accounts = max_accounts;
+        msg!("Updated max_remaining_accounts to {}", max_accounts);
+    }
+
+    if let Some(max_ratio) = params.max_route_rate_ratio {
+        config.max_route_rate_ratio = max_ratio;
+        msg!("Updated max_route_rate_ratio to {}", max_ratio);
     }
 
-    emit!(ConfigUpdated {
+    // Re-validate cross-field invariants regardless of which fields changed
+    config.validate_invariants()?;
+
+    Ok(())
+}
+
+/// Accounts for staging config changes for later review
+#[derive(Accounts)]
+pub struct StageConfig<'info> {
+    /// The protocol authority (pays for the staging account)
+    #[account(
+        mut,
+        constraint = authority.key() == config.authority @ FlowMintError::Unauthorized
+    )]
+    pub authority: Signer<'info>,
+
+    /// Protocol configuration
+    #[account(
+        mut,
+        seeds = [b"config"],
+        bump = config.bump
+    )]
+    pub config: Account<'info, ProtocolConfig>,
+
+    /// Staged config changes (PDA); restaging replaces the previous set
+    #[account(
+        init_if_needed,
+        payer = authority,
+        space = PendingConfig::SIZE,
+        seeds = [b"pending_config"],
+        bump
+    )]
+    pub pending_config: Account<'info, PendingConfig>,
+
+    /// System program
+    pub system_program: Program<'info, System>,
+}
+
+/// Stage a set of config changes without applying them (authority only)
+///
+/// The changes are checked against the current config so obviously invalid
+/// sets are rejected early, but invariants are validated again at apply time.
+pub fn stage_config_handler(ctx: Context<StageConfig>, params: UpdateConfigParams) -> Result<()> {
+    let staged_at = Clock::get()?.unix_timestamp;
+    stage_config_params(
+        &mut ctx.accounts.pending_config,
+        &ctx.accounts.config,
+        &params,
+        ctx.accounts.authority.key(),
+        staged_at,
+    )?;
+    ctx.accounts.pending_config.bump = ctx.bumps.pending_config;
+
+    let sequence = ctx.accounts.config.next_sequence()?;
+
+    msg!("Config changes staged by {}", ctx.accounts.authority.key());
+
+    let event = ConfigStaged {
+        authority: ctx.accounts.authority.key(),
+        staged_at,
+        sequence,
+    };
+    emit_admin_action(ctx.accounts.authority.key(), AdminActionKind::StageConfig, &params)?;
+    emit!(event);
+
+    Ok(())
+}
+
+/// Write a change set into the staging account after a dry run
+pub fn stage_config_params(
+    pending: &mut PendingConfig,
+    config: &ProtocolConfig,
+    params: &UpdateConfigParams,
+    staged_by: Pubkey,
+    staged_at: i64,
+) -> Result<()> {
+    let bytes = params.try_to_vec()?;
+    require!(
+        bytes.len() <= MAX_STAGED_CONFIG_LEN,
+        FlowMintError::InvalidInstructionData
+    );
+    preview_config_params(config, params)?;
+
+    pending.staged_by = staged_by;
+    pending.staged_at = staged_at;
+    pending.params = bytes;
+    Ok(())
+}
+
+/// Decode the change set held by a staging account
+pub fn staged_config_params(pending: &PendingConfig) -> Result<UpdateConfigParams> {
+    UpdateConfigParams::try_from_slice(&pending.params)
+        .map_err(|_| FlowMintError::InvalidInstructionData.into())
+}
+
+/// The config as it would look after applying `params`, without mutating it
+pub fn preview_config_params(
+    config: &ProtocolConfig,
+    params: &UpdateConfigParams,
+) -> Result<ProtocolConfig> {
+    let mut preview = config.clone();
+    apply_config_params(&mut preview, params)?;
+    Ok(preview)
+}
+
+/// Accounts for previewing staged config changes (read-only)
+#[derive(Accounts)]
+pub struct PreviewStagedConfig<'info> {
+    /// Protocol configuration
+    #[account(
+        seeds = [b"config"],
+        bump = config.bump
+    )]
+    pub config: Account<'info, ProtocolConfig>,
+
+    /// Staged config changes (PDA)
+    #[account(
+        seeds = [b"pending_config"],
+        bump = pending_config.bump
+    )]
+    pub pending_config: Account<'info, PendingConfig>,
+}
+
+/// Emit the `ConfigSnapshot` the staged changes would produce (read-only)
+pub fn preview_staged_config_handler(ctx: Context<PreviewStagedConfig>) -> Result<()> {
+    let params = staged_config_params(&ctx.accounts.pending_config)?;
+    let preview = preview_config_params(&ctx.accounts.config, &params)?;
+    emit!(config_snapshot(&preview));
+    Ok(())
+}
+
+/// Accounts for applying staged config changes
+#[derive(Accounts)]
+pub struct ApplyStagedConfig<'info> {
+    /// The protocol authority (receives the staging account rent)
+    #[account(
+        mut,
+        constraint = authority.key() == config.authority @ FlowMintError::Unauthorized
+    )]
+    pub authority: Signer<'info>,
+
+    /// Protocol configuration
+    #[account(
+        mut,
+        seeds = [b"config"],
+        bump = config.bump
+    )]
+    pub config: Account<'info, ProtocolConfig>,
+
+    /// Staged config changes (PDA), closed once applied
+    #[account(
+        mut,
+        close = authority,
+        seeds = [b"pending_config"],
+        bump = pending_config.bump
+    )]
+    pub pending_config: Account<'info, PendingConfig>,
+}
+
+/// Apply the staged config changes in one step (authority only)
+///
+/// All staged fields are applied together and the invariants validated on
+/// the result, so a change set can never leave the config half-updated.
+pub fn apply_staged_config_handler(ctx: Context<ApplyStagedConfig>) -> Result<()> {
+    let params = staged_config_params(&ctx.accounts.pending_config)?;
+
+    let config = &mut ctx.accounts.config;
+    apply_config_params(config, &params)?;
+
+    let sequence = config.next_sequence()?;
+
+    let event = ConfigUpdated {
         authority: ctx.accounts.authority.key(),
         default_slippage_bps: config.default_slippage_bps,
         protected_slippage_bps: config.protected_slippage_bps,
         max_price_impact_bps: config.max_price_impact_bps,
-    });
+        sequence,
+    };
+    emit_admin_action(
+        ctx.accounts.authority.key(),
+        AdminActionKind::ApplyStagedConfig,
+        &params,
+    )?;
+    emit!(event);
+
+    Ok(())
+}
+
+/// Accounts for creating a protocol FeeVault for a given mint
+#[derive(Accounts)]
+pub struct InitFeeVault<'info> {
+    /// The protocol authority
+    #[account(
+        mut,
+        constraint = authority.key() == config.authority @ FlowMintError::Unauthorized
+    )]
+    pub authority: Signer<'info>,
+
+    /// Protocol configuration PDA (token authority for the FeeVault)
+    #[account(
+        mut,
+        seeds = [b"config"],
+        bump = config.bump
+    )]
+    pub config: Account<'info, ProtocolConfig>,
+
+    /// Mint the vault will collect fees in (SPL Token or Token-2022)
+    pub mint: InterfaceAccount<'info, token_interface::Mint>,
+
+    /// Protocol FeeVault token account (PDA owned by the config PDA)
+    #[account(
+        init,
+        payer = authority,
+        token::mint = mint,
+        token::authority = config,
+        token::token_program = token_program,
+        seeds = [b"fee_vault", mint.key().as_ref()],
+        bump,
+    )]
+    pub fee_vault: InterfaceAccount<'info, token_interface::TokenAccount>,
+
+    /// Token program owning `mint`
+    pub token_program: Interface<'info, token_interface::TokenInterface>,
+
+    /// System program
+    pub system_program: Program<'info, System>,
+
+    /// Rent sysvar (required for token account init)
+    pub rent: Sysvar<'info, Rent>,
+}
+
+/// Create the FeeVault token account for a mint
+pub fn init_fee_vault_handler(ctx: Context<InitFeeVault>) -> Result<()> {
+    let sequence = ctx.accounts.config.next_sequence()?;
+
+    msg!(
+        "Initialized fee vault {} for mint {}",
+        ctx.accounts.fee_vault.key(),
+        ctx.accounts.mint.key()
+    );
+
+    let event = FeeVaultInitialized {
+        authority: ctx.accounts.authority.key(),
+        mint: ctx.accounts.mint.key(),
+        fee_vault: ctx.accounts.fee_vault.key(),
+        sequence,
+    };
+    emit_admin_action(ctx.accounts.authority.key(), AdminActionKind::InitFeeVault, &event)?;
+    emit!(event);
 
     Ok(())
 }
 
-/// Accounts for withdrawing protocol fees from the USDC FeeVault
+/// Accounts for withdrawing protocol fees from a FeeVault
 #[derive(Accounts)]
 pub struct WithdrawFees<'info> {
     /// The protocol authority
@@ -110,40 +536,44 @@ pub struct WithdrawFees<'info> {
 
     /// Protocol configuration PDA (also token authority for FeeVault)
     #[account(
+        mut,
         seeds = [b"config"],
         bump = config.bump
     )]
     pub config: Account<'info, ProtocolConfig>,
 
-    /// USDC mint (must match vault + destination)
-    /// CHECK: Validated by token account constraints
-    pub usdc_mint: AccountInfo<'info>,
+    /// Fee mint (must match vault + destination)
+    pub mint: InterfaceAccount<'info, token_interface::Mint>,
 
-    /// Protocol FeeVault token account (USDC)
+    /// Protocol FeeVault token account for `mint`
     #[account(
         mut,
-        constraint = fee_vault_usdc_account.mint == usdc_mint.key() @ FlowMintError::InvalidMint,
-        constraint = fee_vault_usdc_account.owner == config.key() @ FlowMintError::InvalidOwner,
-        seeds = [b"fee_vault", usdc_mint.key().as_ref()],
+        constraint = fee_vault.mint == mint.key() @ FlowMintError::InvalidMint,
+        constraint = fee_vault.owner == config.key() @ FlowMintError::InvalidOwner,
+        seeds = [b"fee_vault", mint.key().as_ref()],
         bump,
     )]
-    pub fee_vault_usdc_account: Account<'info, TokenAccount>,
+    pub fee_vault: InterfaceAccount<'info, token_interface::TokenAccount>,
 
-    /// Treasury USDC token account (owned by config.treasury)
+    /// Treasury token account for `mint` (owned by config.treasury, or the
+    /// treasury itself when it is a token account)
     #[account(
         mut,
-        constraint = treasury_usdc_account.mint == usdc_mint.key() @ FlowMintError::InvalidMint,
-        constraint = treasury_usdc_account.owner == config.treasury @ FlowMintError::InvalidOwner,
+        constraint = treasury_token_account.mint == mint.key() @ FlowMintError::InvalidMint,
+        constraint = treasury_token_account.owner == config.treasury
+            || treasury_token_account.key() == config.treasury @ FlowMintError::InvalidOwner,
     )]
-    pub treasury_usdc_account: Account<'info, TokenAccount>,
+    pub treasury_token_account: InterfaceAccount<'info, token_interface::TokenAccount>,
 
-    /// Token program
-    pub token_program: Program<'info, Token>,
+    /// Token program (SPL Token or Token-2022)
+    pub token_program: Interface<'info, token_interface::TokenInterface>,
 }
 
-/// Withdraw all accumulated USDC fees to the treasury
-pub fn withdraw_fees_handler(ctx: Context<WithdrawFees>) -> Result<()> {
-    let amount = ctx.accounts.fee_vault_usdc_account.amount;
+/// Withdraw all accumulated fees for a mint to the treasury
+pub fn withdraw_fees_handler<'info>(
+    ctx: Context<'_, '_, 'info, 'info, WithdrawFees<'info>>,
+) -> Result<()> {
+    let amount = ctx.accounts.fee_vault.amount;
     if amount == 0 {
         return Ok(());
     }
@@ -151,64 +581,1525 @@ pub fn withdraw_fees_handler(ctx: Context<WithdrawFees>) -> Result<()> {
     let config_seeds = &[b"config".as_ref(), &[ctx.accounts.config.bump]];
     let signer_seeds = &[&config_seeds[..]];
 
-    let cpi_ctx = CpiContext::new_with_signer(
-        ctx.accounts.token_program.to_account_info(),
-        Transfer {
-            from: ctx.accounts.fee_vault_usdc_account.to_account_info(),
-            to: ctx.accounts.treasury_usdc_account.to_account_info(),
-            authority: ctx.accounts.config.to_account_info(),
-        },
+    transfer_tokens(
+        &ctx.accounts.token_program.to_account_info(),
+        &ctx.accounts.fee_vault.to_account_info(),
+        &ctx.accounts.mint.to_account_info(),
+        &ctx.accounts.treasury_token_account.to_account_info(),
+        &ctx.accounts.config.to_account_info(),
+        ctx.remaining_accounts,
+        amount,
+        ctx.accounts.mint.decimals,
         signer_seeds,
-    );
+    )?;
+
+    let sequence = ctx.accounts.config.next_sequence()?;
 
-    token::transfer(cpi_ctx, amount)?;
+    msg!("Withdrew {} fees of mint {} to treasury", amount, ctx.accounts.mint.key());
+
+    let event = FeesWithdrawn {
+        authority: ctx.accounts.authority.key(),
+        mint: ctx.accounts.mint.key(),
+        amount,
+        sequence,
+    };
+    emit_admin_action(ctx.accounts.authority.key(), AdminActionKind::WithdrawFees, &event)?;
+    emit!(event);
 
-    msg!("Withdrew {} USDC fees to treasury", amount);
     Ok(())
 }
 
-/// Toggle protected mode globally
-///
-/// # Arguments
-///
-/// * `ctx` - UpdateConfig context
-/// * `enabled` - Whether to enable protected mode
-///
-/// # Returns
+/// Accounts for sweeping residue left in a payer's temp USDC PDA
+#[derive(Accounts)]
+pub struct SweepStrandedTokens<'info> {
+    /// The protocol authority
+    #[account(
+        constraint = authority.key() == config.authority @ FlowMintError::Unauthorized
+    )]
+    pub authority: Signer<'info>,
+
+    /// Protocol configuration PDA (token authority for the temp account)
+    #[account(
+        mut,
+        seeds = [b"config"],
+        bump = config.bump
+    )]
+    pub config: Account<'info, ProtocolConfig>,
+
+    /// Payer whose temp USDC PDA holds the residue
+    /// CHECK: Only used for PDA derivation
+    pub payer: AccountInfo<'info>,
+
+    /// Mint of the stranded tokens
+    pub mint: InterfaceAccount<'info, token_interface::Mint>,
+
+    /// Payer's temp USDC PDA
+    #[account(
+        mut,
+        constraint = temp_usdc_account.mint == mint.key() @ FlowMintError::InvalidMint,
+        constraint = temp_usdc_account.owner == config.key() @ FlowMintError::InvalidOwner,
+        seeds = [b"temp_usdc", payer.key().as_ref()],
+        bump,
+    )]
+    pub temp_usdc_account: InterfaceAccount<'info, token_interface::TokenAccount>,
+
+    /// Destination owned by the treasury or the original payer, or the
+    /// treasury itself when it is a token account
+    #[account(
+        mut,
+        constraint = destination.mint == mint.key() @ FlowMintError::InvalidMint,
+    )]
+    pub destination: InterfaceAccount<'info, token_interface::TokenAccount>,
+
+    /// Token program (SPL Token or Token-2022)
+    pub token_program: Interface<'info, token_interface::TokenInterface>,
+}
+
+/// Ensure stranded tokens only go back to the treasury or the original payer
+pub fn validate_sweep_destination(
+    destination: &Pubkey,
+    destination_owner: &Pubkey,
+    treasury: &Pubkey,
+    payer: &Pubkey,
+) -> Result<()> {
+    require!(
+        destination_owner == treasury || destination == treasury || destination_owner == payer,
+        FlowMintError::InvalidOwner
+    );
+    Ok(())
+}
+
+/// Sweep any balance left in a payer's temp USDC PDA
 ///
-/// * `Result<()>` - Success or error
-pub fn toggle_protected_mode_handler(ctx: Context<UpdateConfig>, enabled: bool) -> Result<()> {
-    let config = &mut ctx.accounts.config;
-    config.protected_mode_enabled = enabled;
+/// Payments move everything out of the temp account within the same
+/// instruction, so a balance observed here can never belong to an
+/// in-flight payment.
+pub fn sweep_stranded_tokens_handler<'info>(
+    ctx: Context<'_, '_, 'info, 'info, SweepStrandedTokens<'info>>,
+) -> Result<()> {
+    validate_sweep_destination(
+        &ctx.accounts.destination.key(),
+        &ctx.accounts.destination.owner,
+        &ctx.accounts.config.treasury,
+        &ctx.accounts.payer.key(),
+    )?;
 
-    msg!("Protected mode {}", if enabled { "enabled" } else { "disabled" });
+    let amount = ctx.accounts.temp_usdc_account.amount;
+    if amount == 0 {
+        return Ok(());
+    }
+
+    let config_seeds = &[b"config".as_ref(), &[ctx.accounts.config.bump]];
+    let signer_seeds = &[&config_seeds[..]];
+
+    transfer_tokens(
+        &ctx.accounts.token_program.to_account_info(),
+        &ctx.accounts.temp_usdc_account.to_account_info(),
+        &ctx.accounts.mint.to_account_info(),
+        &ctx.accounts.destination.to_account_info(),
+        &ctx.accounts.config.to_account_info(),
+        ctx.remaining_accounts,
+        amount,
+        ctx.accounts.mint.decimals,
+        signer_seeds,
+    )?;
+
+    let sequence = ctx.accounts.config.next_sequence()?;
 
-    emit!(ProtectedModeToggled {
+    msg!(
+        "Swept {} stranded tokens from temp account of {} to {}",
+        amount,
+        ctx.accounts.payer.key(),
+        ctx.accounts.destination.key()
+    );
+
+    let event = StrandedTokensSwept {
         authority: ctx.accounts.authority.key(),
-        enabled,
-    });
+        payer: ctx.accounts.payer.key(),
+        mint: ctx.accounts.mint.key(),
+        destination: ctx.accounts.destination.key(),
+        amount,
+        sequence,
+    };
+    emit_admin_action(ctx.accounts.authority.key(), AdminActionKind::SweepStrandedTokens, &event)?;
+    emit!(event);
 
     Ok(())
 }
 
-/// Event emitted when configuration is updated
-#[event]
-pub struct ConfigUpdated {
-    /// Authority that made the change
-    pub authority: Pubkey,
+/// Accounts for rescuing tokens sent to a config-owned account by mistake
+#[derive(Accounts)]
+pub struct RescueTokens<'info> {
+    /// The protocol authority
+    #[account(
+        constraint = authority.key() == config.authority @ FlowMintError::Unauthorized
+    )]
+    pub authority: Signer<'info>,
+
+    /// Protocol configuration PDA (token authority for the source)
+    #[account(
+        mut,
+        seeds = [b"config"],
+        bump = config.bump
+    )]
+    pub config: Account<'info, ProtocolConfig>,
+
+    /// Mint of the stranded tokens
+    pub mint: InterfaceAccount<'info, token_interface::Mint>,
+
+    /// Config-owned token account holding the stranded tokens (never a fee vault)
+    #[account(
+        mut,
+        constraint = source.mint == mint.key() @ FlowMintError::InvalidMint,
+        constraint = source.owner == config.key() @ FlowMintError::InvalidOwner,
+    )]
+    pub source: InterfaceAccount<'info, token_interface::TokenAccount>,
+
+    /// Treasury token account receiving the rescued tokens
+    #[account(
+        mut,
+        constraint = destination.mint == mint.key() @ FlowMintError::InvalidMint,
+    )]
+    pub destination: InterfaceAccount<'info, token_interface::TokenAccount>,
+
+    /// Token program (SPL Token or Token-2022)
+    pub token_program: Interface<'info, token_interface::TokenInterface>,
+}
+
+/// FeeVault PDA collecting protocol fees in `mint`
+pub fn fee_vault_address(mint: &Pubkey) -> Pubkey {
+    Pubkey::find_program_address(&[b"fee_vault", mint.as_ref()], &crate::ID).0
+}
+
+/// Ensure a rescue neither drains a fee vault nor pays anyone but the treasury
+///
+/// Fee vault balances are accounted fees and leave only via `withdraw_fees`.
+/// The destination must be owned by the treasury, or be the treasury itself
+/// when the treasury is a token account.
+pub fn validate_rescue(
+    source: &Pubkey,
+    mint: &Pubkey,
+    destination: &Pubkey,
+    destination_owner: &Pubkey,
+    treasury: &Pubkey,
+) -> Result<()> {
+    require_keys_neq!(*source, fee_vault_address(mint), FlowMintError::InvalidConfiguration);
+    require!(
+        destination_owner == treasury || destination == treasury,
+        FlowMintError::InvalidOwner
+    );
+    Ok(())
+}
+
+/// Transfer the full balance of a config-owned token account to the treasury
+pub fn rescue_tokens_handler<'info>(
+    ctx: Context<'_, '_, 'info, 'info, RescueTokens<'info>>,
+) -> Result<()> {
+    validate_rescue(
+        &ctx.accounts.source.key(),
+        &ctx.accounts.mint.key(),
+        &ctx.accounts.destination.key(),
+        &ctx.accounts.destination.owner,
+        &ctx.accounts.config.treasury,
+    )?;
+
+    let amount = ctx.accounts.source.amount;
+    if amount == 0 {
+        return Ok(());
+    }
+
+    let config_seeds = &[b"config".as_ref(), &[ctx.accounts.config.bump]];
+    let signer_seeds = &[&config_seeds[..]];
+
+    transfer_tokens(
+        &ctx.accounts.token_program.to_account_info(),
+        &ctx.accounts.source.to_account_info(),
+        &ctx.accounts.mint.to_account_info(),
+        &ctx.accounts.destination.to_account_info(),
+        &ctx.accounts.config.to_account_info(),
+        ctx.remaining_accounts,
+        amount,
+        ctx.accounts.mint.decimals,
+        signer_seeds,
+    )?;
+
+    let sequence = ctx.accounts.config.next_sequence()?;
+
+    msg!(
+        "Rescued {} tokens from {} to {}",
+        amount,
+        ctx.accounts.source.key(),
+        ctx.accounts.destination.key()
+    );
+
+    let event = TokensRescued {
+        authority: ctx.accounts.authority.key(),
+        mint: ctx.accounts.mint.key(),
+        source: ctx.accounts.source.key(),
+        destination: ctx.accounts.destination.key(),
+        amount,
+        sequence,
+    };
+    emit_admin_action(ctx.accounts.authority.key(), AdminActionKind::RescueTokens, &event)?;
+    emit!(event);
+
+    Ok(())
+}
+
+/// Accounts for proposing a new treasury
+#[derive(Accounts)]
+pub struct ProposeTreasury<'info> {
+    /// The protocol authority
+    #[account(
+        constraint = authority.key() == config.authority @ FlowMintError::Unauthorized
+    )]
+    pub authority: Signer<'info>,
+
+    /// Protocol configuration
+    #[account(
+        mut,
+        seeds = [b"config"],
+        bump = config.bump
+    )]
+    pub config: Account<'info, ProtocolConfig>,
+
+    /// Proposed treasury (system wallet or settlement-mint token account)
+    /// CHECK: Validated by `validate_treasury`
+    pub treasury: AccountInfo<'info>,
+}
+
+/// Propose a treasury change that can be applied after the timelock
+pub fn propose_treasury_handler(ctx: Context<ProposeTreasury>) -> Result<()> {
+    let treasury = &ctx.accounts.treasury;
+    validate_treasury(
+        &treasury.key(),
+        treasury.owner,
+        &treasury.try_borrow_data()?,
+        &ctx.accounts.config.key(),
+        &ctx.accounts.config,
+    )?;
+
+    let clock = Clock::get()?;
+    let config = &mut ctx.accounts.config;
+    let eta = config.propose_treasury(treasury.key(), clock.unix_timestamp)?;
+    let sequence = config.next_sequence()?;
+
+    msg!("Proposed treasury {} (applicable at {})", treasury.key(), eta);
+
+    let event = TreasuryChangeProposed {
+        authority: ctx.accounts.authority.key(),
+        current_treasury: config.treasury,
+        pending_treasury: treasury.key(),
+        eta,
+        sequence,
+    };
+    emit_admin_action(ctx.accounts.authority.key(), AdminActionKind::ProposeTreasury, &event)?;
+    emit!(event);
+
+    Ok(())
+}
+
+/// Apply the pending treasury change once its timelock has elapsed
+pub fn apply_treasury_handler(ctx: Context<UpdateConfig>) -> Result<()> {
+    let clock = Clock::get()?;
+    let config = &mut ctx.accounts.config;
+    let previous_treasury = config.treasury;
+    let treasury = config.apply_pending_treasury(clock.unix_timestamp)?;
+    let sequence = config.next_sequence()?;
+
+    msg!("Treasury changed from {} to {}", previous_treasury, treasury);
+
+    let event = TreasuryUpdated {
+        authority: ctx.accounts.authority.key(),
+        previous_treasury,
+        treasury,
+        sequence,
+    };
+    emit_admin_action(ctx.accounts.authority.key(), AdminActionKind::ApplyTreasury, &event)?;
+    emit!(event);
+
+    Ok(())
+}
+
+/// Version of the `ConfigSnapshot` event shape
+///
+/// Bumped whenever a field is added to, removed from or changed in the snapshot.
+pub const CONFIG_SNAPSHOT_VERSION: u16 = 12;
+
+/// Accounts for the read-only config export
+#[derive(Accounts)]
+pub struct ExportConfig<'info> {
+    /// Protocol configuration
+    #[account(
+        seeds = [b"config"],
+        bump = config.bump
+    )]
+    pub config: Account<'info, ProtocolConfig>,
+}
+
+/// Emit every config field as a versioned `ConfigSnapshot` (read-only)
+pub fn export_config_handler(ctx: Context<ExportConfig>) -> Result<()> {
+    emit!(config_snapshot(&ctx.accounts.config));
+    Ok(())
+}
+
+/// Build a snapshot of the config, independent of the account layout
+pub fn config_snapshot(config: &ProtocolConfig) -> ConfigSnapshot {
+    ConfigSnapshot {
+        snapshot_version: CONFIG_SNAPSHOT_VERSION,
+        authority: config.authority,
+        operators: config.operators,
+        default_slippage_bps: config.default_slippage_bps,
+        protected_slippage_bps: config.protected_slippage_bps,
+        max_price_impact_bps: config.max_price_impact_bps,
+        max_positive_slippage_bps: config.max_positive_slippage_bps,
+        max_quote_ttl_seconds: config.max_quote_ttl_seconds,
+        protected_mode_enabled: config.protected_mode_enabled,
+        protocol_fee_bps: config.protocol_fee_bps,
+        treasury: config.treasury,
+        total_swaps: config.total_swaps,
+        total_volume_usd: config.total_volume_usd,
+        sequence: config.sequence,
+        referral_share_bps: config.referral_share_bps,
+        fee_tiers: config.fee_tiers,
+        swaps_paused: config.swaps_paused,
+        payments_paused: config.payments_paused,
+        quote_grace_seconds: config.quote_grace_seconds,
+        max_payment_usdc: config.max_payment_usdc,
+        settlement_mint: config.settlement_mint,
+        amm_allowlist_enabled: config.amm_allowlist_enabled,
+        fee_policy: config.fee_policy,
+        slippage_tiers: config.slippage_tiers,
+        treasury_timelock_seconds: config.treasury_timelock_seconds,
+        pending_treasury: config.pending_treasury,
+        treasury_change_eta: config.treasury_change_eta,
+        max_hops_protected: config.max_hops_protected,
+        stats_max_age_seconds: config.stats_max_age_seconds,
+        direct_settlement_mints: config.direct_settlement_mints,
+        require_canonical_ata: config.require_canonical_ata,
+        total_fees_collected: config.total_fees_collected,
+        max_refund_bps: config.max_refund_bps,
+        payment_max_quote_ttl_seconds: config.payment_max_quote_ttl_seconds,
+        memo_policy: config.memo_policy,
+        max_remaining_accounts: config.max_remaining_accounts,
+        max_route_rate_ratio: config.max_route_rate_ratio,
+        oracle_feeds: config.oracle_feeds,
+    }
+}
+
+/// Accounts for granting or revoking a user's fee exemption
+#[derive(Accounts)]
+pub struct SetFeeExempt<'info> {
+    /// The protocol authority (pays for the stats account if needed)
+    #[account(
+        mut,
+        constraint = authority.key() == config.authority @ FlowMintError::Unauthorized
+    )]
+    pub authority: Signer<'info>,
+
+    /// Protocol configuration
+    #[account(
+        mut,
+        seeds = [b"config"],
+        bump = config.bump
+    )]
+    pub config: Account<'info, ProtocolConfig>,
+
+    /// User being exempted
+    /// CHECK: Only used for PDA derivation
+    pub user: AccountInfo<'info>,
+
+    /// User stats account (PDA)
+    #[account(
+        init_if_needed,
+        payer = authority,
+        space = UserStats::SIZE,
+        seeds = [b"user_stats", user.key().as_ref()],
+        bump
+    )]
+    pub user_stats: Account<'info, UserStats>,
+
+    /// System program
+    pub system_program: Program<'info, System>,
+}
+
+/// Grant or revoke a user's protocol fee exemption
+pub fn set_fee_exempt_handler(ctx: Context<SetFeeExempt>, exempt: bool) -> Result<()> {
+    let user_stats = &mut ctx.accounts.user_stats;
+    if user_stats.user == Pubkey::default() {
+        user_stats.user = ctx.accounts.user.key();
+        user_stats.bump = ctx.bumps.user_stats;
+    }
+    user_stats.fee_exempt = exempt;
+
+    let sequence = ctx.accounts.config.next_sequence()?;
+
+    msg!(
+        "Fee exemption {} for {}",
+        if exempt { "granted" } else { "revoked" },
+        ctx.accounts.user.key()
+    );
+
+    let event = FeeExemptionUpdated {
+        authority: ctx.accounts.authority.key(),
+        user: ctx.accounts.user.key(),
+        exempt,
+        sequence,
+    };
+    emit_admin_action(ctx.accounts.authority.key(), AdminActionKind::SetFeeExempt, &event)?;
+    emit!(event);
+
+    Ok(())
+}
+
+/// Accounts for changing the settlement mint
+#[derive(Accounts)]
+pub struct SetSettlementMint<'info> {
+    /// The protocol authority
+    #[account(
+        constraint = authority.key() == config.authority @ FlowMintError::Unauthorized
+    )]
+    pub authority: Signer<'info>,
+
+    /// Protocol configuration
+    #[account(
+        mut,
+        seeds = [b"config"],
+        bump = config.bump
+    )]
+    pub config: Account<'info, ProtocolConfig>,
+
+    /// New settlement mint (must be an initialized mint)
+    pub mint: InterfaceAccount<'info, token_interface::Mint>,
+}
+
+/// Set the mint payments settle in (authority only)
+pub fn set_settlement_mint_handler(ctx: Context<SetSettlementMint>) -> Result<()> {
+    let mint = ctx.accounts.mint.key();
+    let config = &mut ctx.accounts.config;
+    let previous_mint = config.settlement_mint;
+    config.settlement_mint = mint;
+    let sequence = config.next_sequence()?;
+
+    msg!("Updated settlement mint to {}", mint);
+
+    let event = SettlementMintUpdated {
+        authority: ctx.accounts.authority.key(),
+        previous_mint,
+        mint,
+        sequence,
+    };
+    emit_admin_action(ctx.accounts.authority.key(), AdminActionKind::SetSettlementMint, &event)?;
+    emit!(event);
+
+    Ok(())
+}
+
+/// Accounts for managing the AMM allowlist
+#[derive(Accounts)]
+pub struct SetAmmAllowlist<'info> {
+    /// The protocol authority
+    #[account(
+        mut,
+        constraint = authority.key() == config.authority @ FlowMintError::Unauthorized
+    )]
+    pub authority: Signer<'info>,
+
+    /// Protocol configuration
+    #[account(
+        mut,
+        seeds = [b"config"],
+        bump = config.bump
+    )]
+    pub config: Account<'info, ProtocolConfig>,
+
+    /// AMM allowlist (PDA)
+    #[account(
+        init_if_needed,
+        payer = authority,
+        space = AmmAllowlist::SIZE,
+        seeds = [b"amm_allowlist"],
+        bump
+    )]
+    pub amm_allowlist: Account<'info, AmmAllowlist>,
+
+    /// System program
+    pub system_program: Program<'info, System>,
+}
+
+/// Replace the AMM allowlist and toggle its enforcement (authority only)
+///
+/// # Arguments
+///
+/// * `ctx` - SetAmmAllowlist context
+/// * `programs` - Allowed AMM program IDs
+/// * `enabled` - Whether route steps must use allowlisted AMMs
+///
+/// # Returns
+///
+/// * `Result<()>` - Success or error
+pub fn set_amm_allowlist_handler(
+    ctx: Context<SetAmmAllowlist>,
+    programs: Vec<Pubkey>,
+    enabled: bool,
+) -> Result<()> {
+    let amm_allowlist = &mut ctx.accounts.amm_allowlist;
+    amm_allowlist.set_programs(&programs)?;
+    amm_allowlist.bump = ctx.bumps.amm_allowlist;
+
+    let config = &mut ctx.accounts.config;
+    config.amm_allowlist_enabled = enabled;
+    let sequence = config.next_sequence()?;
+
+    msg!(
+        "AMM allowlist set to {} programs (enforced: {})",
+        programs.len(),
+        enabled
+    );
+
+    let event = AmmAllowlistUpdated {
+        authority: ctx.accounts.authority.key(),
+        program_count: programs.len() as u8,
+        enabled,
+        sequence,
+    };
+    emit_admin_action(ctx.accounts.authority.key(), AdminActionKind::SetAmmAllowlist, &event)?;
+    emit!(event);
+
+    Ok(())
+}
+
+/// Register an operator (authority only)
+pub fn add_operator_handler(ctx: Context<UpdateConfig>, operator: Pubkey) -> Result<()> {
+    let config = &mut ctx.accounts.config;
+    config.add_operator(operator)?;
+    let sequence = config.next_sequence()?;
+
+    msg!("Added operator {}", operator);
+
+    let event = OperatorUpdated {
+        authority: ctx.accounts.authority.key(),
+        operator,
+        added: true,
+        sequence,
+    };
+    emit_admin_action(ctx.accounts.authority.key(), AdminActionKind::AddOperator, &event)?;
+    emit!(event);
+
+    Ok(())
+}
+
+/// Remove an operator (authority only)
+pub fn remove_operator_handler(ctx: Context<UpdateConfig>, operator: Pubkey) -> Result<()> {
+    let config = &mut ctx.accounts.config;
+    config.remove_operator(&operator)?;
+    let sequence = config.next_sequence()?;
+
+    msg!("Removed operator {}", operator);
+
+    let event = OperatorUpdated {
+        authority: ctx.accounts.authority.key(),
+        operator,
+        added: false,
+        sequence,
+    };
+    emit_admin_action(ctx.accounts.authority.key(), AdminActionKind::RemoveOperator, &event)?;
+    emit!(event);
+
+    Ok(())
+}
+
+/// Register or replace the Pyth feed expected for a mint (authority only)
+pub fn set_oracle_feed_handler(
+    ctx: Context<UpdateConfig>,
+    mint: Pubkey,
+    feed_id: [u8; 32],
+) -> Result<()> {
+    let config = &mut ctx.accounts.config;
+    config.set_oracle_feed(mint, feed_id)?;
+    let sequence = config.next_sequence()?;
+
+    msg!("Set oracle feed for mint {}", mint);
+
+    let event = OracleFeedUpdated {
+        authority: ctx.accounts.authority.key(),
+        mint,
+        feed_id,
+        sequence,
+    };
+    emit_admin_action(ctx.accounts.authority.key(), AdminActionKind::SetOracleFeed, &event)?;
+    emit!(event);
+
+    Ok(())
+}
+
+/// Remove a mint's registered Pyth feed (authority only)
+pub fn remove_oracle_feed_handler(ctx: Context<UpdateConfig>, mint: Pubkey) -> Result<()> {
+    let config = &mut ctx.accounts.config;
+    config.remove_oracle_feed(&mint)?;
+    let sequence = config.next_sequence()?;
+
+    msg!("Removed oracle feed for mint {}", mint);
+
+    let event = OracleFeedUpdated {
+        authority: ctx.accounts.authority.key(),
+        mint,
+        feed_id: [0u8; 32],
+        sequence,
+    };
+    emit_admin_action(ctx.accounts.authority.key(), AdminActionKind::RemoveOracleFeed, &event)?;
+    emit!(event);
+
+    Ok(())
+}
+
+/// Toggle protected mode globally
+///
+/// Operators may enable protected mode; disabling it loosens protection
+/// and is reserved for the authority.
+///
+/// # Arguments
+///
+/// * `ctx` - OperatorAction context
+/// * `enabled` - Whether to enable protected mode
+///
+/// # Returns
+///
+/// * `Result<()>` - Success or error
+pub fn toggle_protected_mode_handler(ctx: Context<OperatorAction>, enabled: bool) -> Result<()> {
+    let config = &mut ctx.accounts.config;
+    require!(
+        enabled || ctx.accounts.operator.key() == config.authority,
+        FlowMintError::Unauthorized
+    );
+    config.protected_mode_enabled = enabled;
+
+    let sequence = config.next_sequence()?;
+
+    msg!("Protected mode {}", if enabled { "enabled" } else { "disabled" });
+
+    let event = ProtectedModeToggled {
+        authority: ctx.accounts.operator.key(),
+        enabled,
+        sequence,
+    };
+    emit_admin_action(ctx.accounts.operator.key(), AdminActionKind::ToggleProtectedMode, &event)?;
+    emit!(event);
+
+    Ok(())
+}
+
+/// Pause or resume a single protocol feature
+///
+/// Operators may pause during an incident; resuming is reserved for the
+/// authority.
+///
+/// # Arguments
+///
+/// * `ctx` - OperatorAction context
+/// * `feature` - Feature to pause or resume
+/// * `paused` - Whether the feature should be paused
+///
+/// # Returns
+///
+/// * `Result<()>` - Success or error
+pub fn set_feature_paused_handler(
+    ctx: Context<OperatorAction>,
+    feature: PausableFeature,
+    paused: bool,
+) -> Result<()> {
+    let config = &mut ctx.accounts.config;
+    require!(
+        paused || ctx.accounts.operator.key() == config.authority,
+        FlowMintError::Unauthorized
+    );
+    config.set_feature_paused(feature, paused);
+
+    let sequence = config.next_sequence()?;
+
+    msg!("{:?} {}", feature, if paused { "paused" } else { "resumed" });
+
+    let event = FeaturePauseToggled {
+        authority: ctx.accounts.operator.key(),
+        feature,
+        paused,
+        sequence,
+    };
+    emit_admin_action(ctx.accounts.operator.key(), AdminActionKind::SetFeaturePaused, &event)?;
+    emit!(event);
+
+    Ok(())
+}
+
+/// Pause or resume both swaps and payments
+pub fn set_paused_handler(ctx: Context<OperatorAction>, paused: bool) -> Result<()> {
+    let config = &mut ctx.accounts.config;
+    require!(
+        paused || ctx.accounts.operator.key() == config.authority,
+        FlowMintError::Unauthorized
+    );
+    config.set_feature_paused(PausableFeature::Swaps, paused);
+    config.set_feature_paused(PausableFeature::Payments, paused);
+
+    // One instruction, one sequence number, shared by both toggle events
+    let sequence = config.next_sequence()?;
+
+    msg!("Protocol {}", if paused { "paused" } else { "resumed" });
+
+    emit_admin_action(ctx.accounts.operator.key(), AdminActionKind::SetPaused, &paused)?;
+    for feature in [PausableFeature::Swaps, PausableFeature::Payments] {
+        emit!(FeaturePauseToggled {
+            authority: ctx.accounts.operator.key(),
+            feature,
+            paused,
+            sequence,
+        });
+    }
+
+    Ok(())
+}
+
+/// Kind of privileged action recorded in the `AdminAction` audit log
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum AdminActionKind {
+    /// `update_config`
+    UpdateConfig,
+    /// `init_fee_vault`
+    InitFeeVault,
+    /// `withdraw_fees`
+    WithdrawFees,
+    /// `sweep_stranded_tokens`
+    SweepStrandedTokens,
+    /// `rescue_tokens`
+    RescueTokens,
+    /// `propose_treasury`
+    ProposeTreasury,
+    /// `apply_treasury`
+    ApplyTreasury,
+    /// `set_fee_exempt`
+    SetFeeExempt,
+    /// `set_settlement_mint`
+    SetSettlementMint,
+    /// `set_amm_allowlist`
+    SetAmmAllowlist,
+    /// `add_operator`
+    AddOperator,
+    /// `remove_operator`
+    RemoveOperator,
+    /// `toggle_protected_mode`
+    ToggleProtectedMode,
+    /// `set_feature_paused`
+    SetFeaturePaused,
+    /// `set_paused`
+    SetPaused,
+    /// `close_config` (devnet teardown builds only)
+    CloseConfig,
+    /// `stage_config`
+    StageConfig,
+    /// `apply_staged_config`
+    ApplyStagedConfig,
+    /// `migrate_config`
+    MigrateConfig,
+    /// `set_oracle_feed`
+    SetOracleFeed,
+    /// `remove_oracle_feed`
+    RemoveOracleFeed,
+}
+
+/// Build the audit-log entry for a privileged action
+///
+/// `detail_hash` is the SHA-256 of the Borsh-serialized `detail` (the
+/// action's specific event or arguments), so monitors can match the entry to
+/// the detailed event without decoding every event type.
+pub fn admin_action<T: AnchorSerialize>(
+    authority: Pubkey,
+    action_kind: AdminActionKind,
+    timestamp: i64,
+    detail: &T,
+) -> Result<AdminAction> {
+    let detail_bytes = detail.try_to_vec()?;
+    Ok(AdminAction {
+        authority,
+        action_kind,
+        timestamp,
+        detail_hash: hash(&detail_bytes).to_bytes(),
+    })
+}
+
+/// Emit an `AdminAction` audit-log entry stamped with the current time
+pub fn emit_admin_action<T: AnchorSerialize>(
+    authority: Pubkey,
+    action_kind: AdminActionKind,
+    detail: &T,
+) -> Result<()> {
+    let clock = Clock::get()?;
+    emit!(admin_action(authority, action_kind, clock.unix_timestamp, detail)?);
+    Ok(())
+}
+
+/// Uniform audit-log event emitted by every privileged instruction
+#[event]
+pub struct AdminAction {
+    /// Authority or operator that performed the action
+    pub authority: Pubkey,
+    /// Which action was performed
+    pub action_kind: AdminActionKind,
+    /// Unix timestamp of the action
+    pub timestamp: i64,
+    /// SHA-256 of the action's Borsh-serialized detail
+    pub detail_hash: [u8; 32],
+}
+
+/// Event emitted when configuration is updated
+#[event]
+pub struct ConfigUpdated {
+    /// Authority that made the change
+    pub authority: Pubkey,
     /// New default slippage
     pub default_slippage_bps: u16,
     /// New protected slippage
     pub protected_slippage_bps: u16,
     /// New max price impact
     pub max_price_impact_bps: u16,
+    /// Protocol event sequence number
+    pub sequence: u64,
 }
 
 /// Event emitted when protected mode is toggled
 #[event]
 pub struct ProtectedModeToggled {
-    /// Authority that made the change
+    /// Authority or operator that made the change
     pub authority: Pubkey,
     /// New protected mode state
     pub enabled: bool,
+    /// Protocol event sequence number
+    pub sequence: u64,
+}
+
+/// Event emitted when a feature is paused or resumed
+#[event]
+pub struct FeaturePauseToggled {
+    /// Authority or operator that made the change
+    pub authority: Pubkey,
+    /// Feature that changed
+    pub feature: PausableFeature,
+    /// New paused state
+    pub paused: bool,
+    /// Protocol event sequence number
+    pub sequence: u64,
+}
+
+/// Event emitted when an operator is added or removed
+#[event]
+pub struct OperatorUpdated {
+    /// Authority that made the change
+    pub authority: Pubkey,
+    /// Operator pubkey
+    pub operator: Pubkey,
+    /// Whether the operator was added (true) or removed (false)
+    pub added: bool,
+    /// Protocol event sequence number
+    pub sequence: u64,
+}
+
+/// Event emitted when a mint's oracle feed is set or removed
+#[event]
+pub struct OracleFeedUpdated {
+    /// Authority that made the change
+    pub authority: Pubkey,
+    /// Mint whose feed changed
+    pub mint: Pubkey,
+    /// New feed ID (zeroes when removed)
+    pub feed_id: [u8; 32],
+    /// Protocol event sequence number
+    pub sequence: u64,
+}
+
+/// Event emitted when a FeeVault is created
+#[event]
+pub struct FeeVaultInitialized {
+    /// Authority that created the vault
+    pub authority: Pubkey,
+    /// Mint the vault holds
+    pub mint: Pubkey,
+    /// FeeVault token account
+    pub fee_vault: Pubkey,
+    /// Protocol event sequence number
+    pub sequence: u64,
+}
+
+/// Event emitted when protocol fees are withdrawn to the treasury
+#[event]
+pub struct FeesWithdrawn {
+    /// Authority that made the withdrawal
+    pub authority: Pubkey,
+    /// Mint of the withdrawn fees
+    pub mint: Pubkey,
+    /// Amount withdrawn
+    pub amount: u64,
+    /// Protocol event sequence number
+    pub sequence: u64,
+}
+
+/// Event emitted when the settlement mint changes
+#[event]
+pub struct SettlementMintUpdated {
+    /// Authority that made the change
+    pub authority: Pubkey,
+    /// Previous settlement mint (default pubkey if unset)
+    pub previous_mint: Pubkey,
+    /// New settlement mint
+    pub mint: Pubkey,
+    /// Protocol event sequence number
+    pub sequence: u64,
+}
+
+/// Event emitted when the AMM allowlist changes
+#[event]
+pub struct AmmAllowlistUpdated {
+    /// Authority that made the change
+    pub authority: Pubkey,
+    /// Number of allowlisted programs
+    pub program_count: u8,
+    /// Whether the allowlist is enforced
+    pub enabled: bool,
+    /// Protocol event sequence number
+    pub sequence: u64,
+}
+
+/// Event emitted when stranded temp-account tokens are swept
+#[event]
+pub struct StrandedTokensSwept {
+    /// Authority that made the sweep
+    pub authority: Pubkey,
+    /// Payer whose temp account was swept
+    pub payer: Pubkey,
+    /// Mint of the swept tokens
+    pub mint: Pubkey,
+    /// Destination token account
+    pub destination: Pubkey,
+    /// Amount swept
+    pub amount: u64,
+    /// Protocol event sequence number
+    pub sequence: u64,
+}
+
+/// Event carrying every current config field
+#[event]
+pub struct ConfigSnapshot {
+    /// Snapshot shape version (`CONFIG_SNAPSHOT_VERSION`)
+    pub snapshot_version: u16,
+    /// The authority that can update the configuration
+    pub authority: Pubkey,
+    /// Operators allowed to perform lower-risk actions (unused slots are default)
+    pub operators: [Pubkey; MAX_OPERATORS],
+    /// Default maximum slippage in basis points (100 = 1%)
+    pub default_slippage_bps: u16,
+    /// Protected mode maximum slippage in basis points
+    pub protected_slippage_bps: u16,
+    /// Maximum allowed price impact in basis points
+    pub max_price_impact_bps: u16,
+    /// Maximum allowed output above the quote in basis points (0 = unbounded)
+    pub max_positive_slippage_bps: u16,
+    /// Maximum quote TTL (route `quote_expiration_seconds`) accepted
+    pub max_quote_ttl_seconds: i64,
+    /// Whether protected mode is globally enforced
+    pub protected_mode_enabled: bool,
+    /// Protocol fee in basis points (paid to protocol treasury)
+    pub protocol_fee_bps: u16,
+    /// Treasury account to receive protocol fees
+    pub treasury: Pubkey,
+    /// Total number of swaps executed
+    pub total_swaps: u64,
+    /// Total volume in USD (scaled by 1e6)
+    pub total_volume_usd: u64,
+    /// Monotonic event sequence number, incremented once per state-mutating instruction
+    pub sequence: u64,
+    /// Share of the protocol fee paid to swap referrers, in basis points
+    pub referral_share_bps: u16,
+    /// Volume-based protocol fee discount tiers
+    pub fee_tiers: [FeeTier; MAX_FEE_TIERS],
+    /// Whether swaps are paused
+    pub swaps_paused: bool,
+    /// Whether payments are paused
+    pub payments_paused: bool,
+    /// Grace window past quote expiration to absorb clock skew, in seconds
+    pub quote_grace_seconds: i64,
+    /// Maximum USDC per payment (0 = unlimited)
+    pub max_payment_usdc: u64,
+    /// Mint payments settle in (default = known USDC mints)
+    pub settlement_mint: Pubkey,
+    /// Whether route steps must use allowlisted AMM programs
+    pub amm_allowlist_enabled: bool,
+    /// Who bears the protocol fee on payments
+    pub fee_policy: FeePolicy,
+    /// Notional-scaled slippage tiers (all unset = flat limit)
+    pub slippage_tiers: [SlippageTier; MAX_SLIPPAGE_TIERS],
+    /// Delay between proposing and applying a treasury change
+    pub treasury_timelock_seconds: i64,
+    /// Proposed treasury (default pubkey = none pending)
+    pub pending_treasury: Pubkey,
+    /// Earliest time the pending treasury can be applied
+    pub treasury_change_eta: i64,
+    /// Maximum route hops in protected mode (0 = unlimited)
+    pub max_hops_protected: u8,
+    /// Inactivity after which volume-derived user stats reset (0 = never)
+    pub stats_max_age_seconds: i64,
+    /// Alternate stable mints paid directly 1:1 without a swap
+    pub direct_settlement_mints: [Pubkey; MAX_DIRECT_SETTLEMENT_MINTS],
+    /// Require the merchant USDC account to be the canonical ATA
+    pub require_canonical_ata: bool,
+    /// Total protocol fees collected into FeeVaults
+    pub total_fees_collected: u64,
+    /// Maximum payment change as a share of the USDC received, in bps
+    pub max_refund_bps: u16,
+    /// Maximum quote TTL for payments (0 = same as swaps)
+    pub payment_max_quote_ttl_seconds: i64,
+    /// Validation applied to payment memos
+    pub memo_policy: MemoPolicy,
+    /// Maximum remaining accounts accepted by swaps and payments (0 = unlimited)
+    pub max_remaining_accounts: u16,
+    /// Maximum decimals-adjusted route rate (0 = disabled)
+    pub max_route_rate_ratio: u64,
+    /// Expected Pyth feed per mint
+    pub oracle_feeds: [OracleFeed; MAX_ORACLE_FEEDS],
+}
+
+/// Event emitted when config changes are staged
+#[event]
+pub struct ConfigStaged {
+    /// Authority that staged the changes
+    pub authority: Pubkey,
+    /// Unix timestamp the changes were staged at
+    pub staged_at: i64,
+    /// Protocol event sequence number
+    pub sequence: u64,
+}
+
+/// Event emitted when a user's fee exemption changes
+#[event]
+pub struct FeeExemptionUpdated {
+    /// Authority that made the change
+    pub authority: Pubkey,
+    /// Affected user
+    pub user: Pubkey,
+    /// Whether the user is now exempt
+    pub exempt: bool,
+    /// Protocol event sequence number
+    pub sequence: u64,
+}
+
+/// Event emitted when a treasury change is proposed
+#[event]
+pub struct TreasuryChangeProposed {
+    /// Authority that proposed the change
+    pub authority: Pubkey,
+    /// Treasury in effect until the change is applied
+    pub current_treasury: Pubkey,
+    /// Proposed treasury
+    pub pending_treasury: Pubkey,
+    /// Earliest time the change can be applied
+    pub eta: i64,
+    /// Protocol event sequence number
+    pub sequence: u64,
+}
+
+/// Event emitted when a pending treasury change is applied
+#[event]
+pub struct TreasuryUpdated {
+    /// Authority that applied the change
+    pub authority: Pubkey,
+    /// Previous treasury
+    pub previous_treasury: Pubkey,
+    /// New treasury
+    pub treasury: Pubkey,
+    /// Protocol event sequence number
+    pub sequence: u64,
+}
+
+/// Event emitted when tokens are rescued from a config-owned account
+#[event]
+pub struct TokensRescued {
+    /// Authority that made the rescue
+    pub authority: Pubkey,
+    /// Mint of the rescued tokens
+    pub mint: Pubkey,
+    /// Config-owned source token account
+    pub source: Pubkey,
+    /// Treasury destination token account
+    pub destination: Pubkey,
+    /// Amount rescued
+    pub amount: u64,
+    /// Protocol event sequence number
+    pub sequence: u64,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_runtime::RuntimeAccount;
+    use anchor_lang::{system_program, Bumps};
+    use anchor_spl::token;
+    use std::collections::BTreeSet;
+
+    fn run_withdraw_fees<'info>(accounts: &'info [AccountInfo<'info>]) -> Result<()> {
+        let mut bumps = <WithdrawFees as Bumps>::Bumps::default();
+        let mut remaining = accounts;
+        let mut withdraw = WithdrawFees::try_accounts(
+            &crate::ID,
+            &mut remaining,
+            &[],
+            &mut bumps,
+            &mut BTreeSet::new(),
+        )?;
+        withdraw_fees_handler(Context::new(&crate::ID, &mut withdraw, remaining, bumps))?;
+        withdraw.exit(&crate::ID)
+    }
+
+    /// Authority and config accounts for a protocol paying fees to `treasury`
+    fn fee_authority(treasury: Pubkey) -> (RuntimeAccount, RuntimeAccount) {
+        let authority_key = Pubkey::new_unique();
+        let (config_key, bump) = Pubkey::find_program_address(&[b"config"], &crate::ID);
+        let mut data = Vec::new();
+        ProtocolConfig {
+            authority: authority_key,
+            treasury,
+            bump,
+            ..Default::default()
+        }
+        .try_serialize(&mut data)
+        .unwrap();
+        (
+            RuntimeAccount::new(authority_key, system_program::ID, 1, &[], 0),
+            RuntimeAccount::new(config_key, crate::ID, 1, &data, data.len()),
+        )
+    }
+
+    #[test]
+    fn test_protocol_fee_ceiling() {
+        let fee = |fee_bps| UpdateConfigParams {
+            protocol_fee_bps: Some(fee_bps),
+            ..Default::default()
+        };
+        let mut config = ProtocolConfig::default();
+
+        assert!(apply_config_params(&mut config, &fee(MAX_PROTOCOL_FEE_BPS - 1)).is_ok());
+        assert!(apply_config_params(&mut config, &fee(MAX_PROTOCOL_FEE_BPS)).is_ok());
+        assert_eq!(config.protocol_fee_bps, MAX_PROTOCOL_FEE_BPS);
+
+        assert_eq!(
+            apply_config_params(&mut config, &fee(MAX_PROTOCOL_FEE_BPS + 1)).unwrap_err(),
+            FlowMintError::InvalidConfiguration.into()
+        );
+        assert!(apply_config_params(&mut config, &fee(10_000)).is_err());
+    }
+
+    #[test]
+    fn test_treasury_timelock_floor() {
+        let timelock = |seconds| UpdateConfigParams {
+            treasury_timelock_seconds: Some(seconds),
+            ..Default::default()
+        };
+        let mut config = ProtocolConfig {
+            treasury_timelock_seconds: 86_400,
+            ..Default::default()
+        };
+
+        // Can't be collapsed to propose and apply a treasury at once
+        for seconds in [0, MIN_TREASURY_TIMELOCK_SECONDS - 1] {
+            assert_eq!(
+                apply_config_params(&mut config, &timelock(seconds)).unwrap_err(),
+                FlowMintError::InvalidConfiguration.into()
+            );
+        }
+        assert_eq!(config.treasury_timelock_seconds, 86_400);
+
+        apply_config_params(&mut config, &timelock(MIN_TREASURY_TIMELOCK_SECONDS)).unwrap();
+        assert_eq!(config.treasury_timelock_seconds, MIN_TREASURY_TIMELOCK_SECONDS);
+    }
+
+    #[test]
+    fn test_stage_preview_and_apply_config() {
+        let config = ProtocolConfig {
+            default_slippage_bps: 100,
+            protected_slippage_bps: 50,
+            max_price_impact_bps: 300,
+            ..Default::default()
+        };
+        let authority = Pubkey::new_unique();
+
+        // Stage several changes at once
+        let params = UpdateConfigParams {
+            default_slippage_bps: Some(200),
+            protected_slippage_bps: Some(150),
+            max_refund_bps: Some(500),
+            ..Default::default()
+        };
+        let mut pending = PendingConfig {
+            staged_by: Pubkey::default(),
+            staged_at: 0,
+            params: vec![],
+            bump: 0,
+        };
+        stage_config_params(&mut pending, &config, &params, authority, 1_000).unwrap();
+        assert_eq!(pending.staged_by, authority);
+        assert_eq!(pending.staged_at, 1_000);
+
+        // Preview shows the result without touching the live config
+        let staged = staged_config_params(&pending).unwrap();
+        let preview = preview_config_params(&config, &staged).unwrap();
+        assert_eq!(preview.default_slippage_bps, 200);
+        assert_eq!(preview.protected_slippage_bps, 150);
+        assert_eq!(preview.max_refund_bps, 500);
+        assert_eq!(config.default_slippage_bps, 100);
+
+        // Applying produces exactly the previewed config
+        let mut applied = config.clone();
+        apply_config_params(&mut applied, &staged).unwrap();
+        assert_eq!(
+            config_snapshot(&applied).try_to_vec().unwrap(),
+            config_snapshot(&preview).try_to_vec().unwrap()
+        );
+
+        // Changes that are only valid together still apply: raising protected
+        // above the old default alone would break the invariant
+        let alone = UpdateConfigParams {
+            protected_slippage_bps: Some(150),
+            ..Default::default()
+        };
+        assert!(preview_config_params(&config, &alone).is_err());
+
+        // An invalid set is rejected and leaves the staged set untouched
+        let invalid = UpdateConfigParams {
+            max_refund_bps: Some(10_001),
+            ..Default::default()
+        };
+        assert!(stage_config_params(&mut pending, &config, &invalid, authority, 2_000).is_err());
+        assert_eq!(pending.staged_at, 1_000);
+    }
+
+    #[test]
+    fn test_full_config_params_fit_staging() {
+        let params = UpdateConfigParams {
+            default_slippage_bps: Some(0),
+            protected_slippage_bps: Some(0),
+            max_price_impact_bps: Some(0),
+            protocol_fee_bps: Some(0),
+            max_positive_slippage_bps: Some(0),
+            max_quote_ttl_seconds: Some(0),
+            referral_share_bps: Some(0),
+            fee_tiers: Some([FeeTier::default(); MAX_FEE_TIERS]),
+            quote_grace_seconds: Some(0),
+            max_payment_usdc: Some(0),
+            fee_policy: Some(FeePolicy::PayerPays),
+            slippage_tiers: Some([SlippageTier::default(); MAX_SLIPPAGE_TIERS]),
+            treasury_timelock_seconds: Some(0),
+            max_hops_protected: Some(0),
+            stats_max_age_seconds: Some(0),
+            direct_settlement_mints: Some([Pubkey::default(); MAX_DIRECT_SETTLEMENT_MINTS]),
+            require_canonical_ata: Some(false),
+            max_refund_bps: Some(0),
+            payment_max_quote_ttl_seconds: Some(0),
+            memo_policy: Some(MemoPolicy::Truncate),
+            max_remaining_accounts: Some(0),
+            max_route_rate_ratio: Some(0),
+        };
+        assert!(params.try_to_vec().unwrap().len() <= MAX_STAGED_CONFIG_LEN);
+    }
+
+    #[test]
+    fn test_admin_action_entry() {
+        let authority = Pubkey::new_unique();
+        let event = OperatorUpdated {
+            authority,
+            operator: Pubkey::new_unique(),
+            added: true,
+            sequence: 7,
+        };
+
+        let action = admin_action(authority, AdminActionKind::AddOperator, 1_000, &event).unwrap();
+        assert_eq!(action.authority, authority);
+        assert_eq!(action.action_kind, AdminActionKind::AddOperator);
+        assert_eq!(action.timestamp, 1_000);
+        assert_eq!(action.detail_hash, hash(&event.try_to_vec().unwrap()).to_bytes());
+
+        // A different detail yields a different hash
+        let removed = OperatorUpdated { added: false, ..event };
+        let other =
+            admin_action(authority, AdminActionKind::RemoveOperator, 1_000, &removed).unwrap();
+        assert_eq!(other.action_kind, AdminActionKind::RemoveOperator);
+        assert_ne!(other.detail_hash, action.detail_hash);
+    }
+
+    #[test]
+    fn test_config_snapshot_matches_config() {
+        let mut config = ProtocolConfig {
+            authority: Pubkey::new_unique(),
+            default_slippage_bps: 100,
+            protected_slippage_bps: 50,
+            max_quote_ttl_seconds: 30,
+            protocol_fee_bps: 20,
+            treasury: Pubkey::new_unique(),
+            total_swaps: 7,
+            sequence: 42,
+            payments_paused: true,
+            ..Default::default()
+        };
+        config.operators[0] = Pubkey::new_unique();
+
+        let snapshot = config_snapshot(&config);
+        assert_eq!(snapshot.snapshot_version, CONFIG_SNAPSHOT_VERSION);
+        assert_eq!(snapshot.authority, config.authority);
+        assert_eq!(snapshot.operators, config.operators);
+        assert_eq!(snapshot.default_slippage_bps, config.default_slippage_bps);
+        assert_eq!(snapshot.protected_slippage_bps, config.protected_slippage_bps);
+        assert_eq!(snapshot.max_price_impact_bps, config.max_price_impact_bps);
+        assert_eq!(snapshot.max_positive_slippage_bps, config.max_positive_slippage_bps);
+        assert_eq!(snapshot.max_quote_ttl_seconds, config.max_quote_ttl_seconds);
+        assert_eq!(snapshot.protected_mode_enabled, config.protected_mode_enabled);
+        assert_eq!(snapshot.protocol_fee_bps, config.protocol_fee_bps);
+        assert_eq!(snapshot.treasury, config.treasury);
+        assert_eq!(snapshot.total_swaps, config.total_swaps);
+        assert_eq!(snapshot.total_volume_usd, config.total_volume_usd);
+        assert_eq!(snapshot.sequence, config.sequence);
+        assert_eq!(snapshot.referral_share_bps, config.referral_share_bps);
+        assert_eq!(snapshot.fee_tiers, config.fee_tiers);
+        assert_eq!(snapshot.swaps_paused, config.swaps_paused);
+        assert_eq!(snapshot.payments_paused, config.payments_paused);
+        assert_eq!(snapshot.quote_grace_seconds, config.quote_grace_seconds);
+        assert_eq!(snapshot.max_payment_usdc, config.max_payment_usdc);
+        assert_eq!(snapshot.settlement_mint, config.settlement_mint);
+        assert_eq!(snapshot.amm_allowlist_enabled, config.amm_allowlist_enabled);
+        assert_eq!(snapshot.fee_policy, config.fee_policy);
+        assert_eq!(snapshot.slippage_tiers, config.slippage_tiers);
+        assert_eq!(snapshot.treasury_timelock_seconds, config.treasury_timelock_seconds);
+        assert_eq!(snapshot.pending_treasury, config.pending_treasury);
+        assert_eq!(snapshot.treasury_change_eta, config.treasury_change_eta);
+        assert_eq!(snapshot.max_hops_protected, config.max_hops_protected);
+        assert_eq!(snapshot.stats_max_age_seconds, config.stats_max_age_seconds);
+        assert_eq!(snapshot.direct_settlement_mints, config.direct_settlement_mints);
+        assert_eq!(snapshot.require_canonical_ata, config.require_canonical_ata);
+        assert_eq!(snapshot.total_fees_collected, config.total_fees_collected);
+        assert_eq!(snapshot.max_refund_bps, config.max_refund_bps);
+        assert_eq!(snapshot.payment_max_quote_ttl_seconds, config.payment_max_quote_ttl_seconds);
+        assert_eq!(snapshot.memo_policy, config.memo_policy);
+        assert_eq!(snapshot.max_remaining_accounts, config.max_remaining_accounts);
+        assert_eq!(snapshot.max_route_rate_ratio, config.max_route_rate_ratio);
+        assert_eq!(snapshot.oracle_feeds, config.oracle_feeds);
+    }
+
+    #[test]
+    fn test_rescue_validation() {
+        let mint = Pubkey::new_unique();
+        let treasury = Pubkey::new_unique();
+        let stray = Pubkey::new_unique();
+        let treasury_ata = Pubkey::new_unique();
+        let fee_vault = fee_vault_address(&mint);
+
+        // Stray config-owned account -> treasury-owned account or treasury itself
+        assert!(validate_rescue(&stray, &mint, &treasury_ata, &treasury, &treasury).is_ok());
+        assert!(validate_rescue(&stray, &mint, &treasury, &stray, &treasury).is_ok());
+
+        // Fee vaults are never rescued
+        assert!(validate_rescue(&fee_vault, &mint, &treasury_ata, &treasury, &treasury).is_err());
+
+        // Destination must belong to the treasury
+        let other = Pubkey::new_unique();
+        assert!(validate_rescue(&stray, &mint, &other, &other, &treasury).is_err());
+    }
+
+    #[test]
+    fn test_withdraw_fees_to_token_account_treasury() {
+        crate::test_runtime::install();
+
+        // The treasury was registered as a token account, not a wallet
+        let treasury_key = Pubkey::new_unique();
+        let (mut authority, mut config) = fee_authority(treasury_key);
+        let mint_key = Pubkey::new_unique();
+        let mut mint = RuntimeAccount::mint(mint_key, 6);
+        let vault_key = fee_vault_address(&mint_key);
+        let mut vault = RuntimeAccount::token_account(vault_key, mint_key, config.key, 1_500);
+        let mut treasury =
+            RuntimeAccount::token_account(treasury_key, mint_key, Pubkey::new_unique(), 0);
+        let mut stranger =
+            RuntimeAccount::token_account(Pubkey::new_unique(), mint_key, Pubkey::new_unique(), 0);
+        let mut token_program = RuntimeAccount::program(token::ID);
+
+        // An account that neither is nor belongs to the treasury is rejected
+        {
+            let accounts = [
+                authority.info(true, false),
+                config.info(false, false),
+                mint.info(false, false),
+                vault.info(false, false),
+                stranger.info(false, false),
+                token_program.info(false, true),
+            ];
+            assert_eq!(
+                run_withdraw_fees(&accounts).unwrap_err(),
+                FlowMintError::InvalidOwner.into()
+            );
+        }
+
+        {
+            let accounts = [
+                authority.info(true, false),
+                config.info(false, false),
+                mint.info(false, false),
+                vault.info(false, false),
+                treasury.info(false, false),
+                token_program.info(false, true),
+            ];
+            run_withdraw_fees(&accounts).unwrap();
+        }
+        assert_eq!(vault.token_amount(), 0);
+        assert_eq!(treasury.token_amount(), 1_500);
+    }
+
+    #[test]
+    fn test_withdraw_fees_for_two_mints() {
+        crate::test_runtime::install();
+
+        let treasury_key = Pubkey::new_unique();
+        let (mut authority, mut config) = fee_authority(treasury_key);
+        let mut token_program = RuntimeAccount::program(token::ID);
+
+        for (amount, decimals) in [(3_000, 6), (7_000, 9)] {
+            let mint_key = Pubkey::new_unique();
+            let mut mint = RuntimeAccount::mint(mint_key, decimals);
+            let vault_key = fee_vault_address(&mint_key);
+            let mut vault = RuntimeAccount::token_account(vault_key, mint_key, config.key, amount);
+            let mut treasury_ata =
+                RuntimeAccount::token_account(Pubkey::new_unique(), mint_key, treasury_key, 0);
+
+            // Withdrawing twice drains the vault once; the second is a no-op
+            for _ in 0..2 {
+                let accounts = [
+                    authority.info(true, false),
+                    config.info(false, false),
+                    mint.info(false, false),
+                    vault.info(false, false),
+                    treasury_ata.info(false, false),
+                    token_program.info(false, true),
+                ];
+                run_withdraw_fees(&accounts).unwrap();
+            }
+            assert_eq!(vault.token_amount(), 0);
+            assert_eq!(treasury_ata.token_amount(), amount);
+        }
+    }
+
+    #[test]
+    fn test_sweep_destination() {
+        let treasury = Pubkey::new_unique();
+        let payer = Pubkey::new_unique();
+
+        let destination = Pubkey::new_unique();
+        let other = Pubkey::new_unique();
+
+        assert!(validate_sweep_destination(&destination, &treasury, &treasury, &payer).is_ok());
+        assert!(validate_sweep_destination(&destination, &payer, &treasury, &payer).is_ok());
+        assert!(validate_sweep_destination(&treasury, &other, &treasury, &payer).is_ok());
+        assert!(validate_sweep_destination(&destination, &other, &treasury, &payer).is_err());
+    }
 }
@@ -3,13 +3,22 @@
 //! Initializes the FlowMint protocol configuration.
 
 use anchor_lang::prelude::*;
+use anchor_lang::system_program;
+use anchor_spl::{token, token_2022, token_interface};
 
 use crate::errors::FlowMintError;
-use crate::state::ProtocolConfig;
+use crate::state::{
+    FeePolicy, FeeTier, MemoPolicy, OracleFeed, ProtocolConfig, SlippageTier,
+    DEFAULT_MAX_REMAINING_ACCOUNTS, DEFAULT_TREASURY_TIMELOCK_SECONDS, MAX_DIRECT_SETTLEMENT_MINTS,
+    MAX_FEE_TIERS, MAX_OPERATORS, MAX_ORACLE_FEEDS, MAX_SLIPPAGE_TIERS,
+};
 
 /// Maximum allowed slippage in basis points (50%)
 pub const MAX_SLIPPAGE_BPS: u16 = 5000;
 
+/// Maximum protocol fee in basis points (1%), bounding a compromised admin
+pub const MAX_PROTOCOL_FEE_BPS: u16 = 100;
+
 /// Accounts for the Initialize instruction
 #[derive(Accounts)]
 pub struct Initialize<'info> {
@@ -28,7 +37,7 @@ pub struct Initialize<'info> {
     pub config: Account<'info, ProtocolConfig>,
 
     /// Treasury account to receive protocol fees
-    /// CHECK: This is just a destination address
+    /// CHECK: Validated by `validate_treasury`
     pub treasury: AccountInfo<'info>,
 
     /// System program
@@ -43,6 +52,7 @@ pub struct Initialize<'info> {
 /// * `default_slippage_bps` - Default maximum slippage
 /// * `protected_slippage_bps` - Protected mode slippage
 /// * `max_price_impact_bps` - Maximum price impact
+/// * `max_quote_ttl_seconds` - Maximum quote TTL accepted from routes
 ///
 /// # Returns
 ///
@@ -52,34 +62,68 @@ pub fn handler(
     default_slippage_bps: u16,
     protected_slippage_bps: u16,
     max_price_impact_bps: u16,
+    max_quote_ttl_seconds: i64,
 ) -> Result<()> {
     // Validate parameters
-    require!(
-        default_slippage_bps <= MAX_SLIPPAGE_BPS,
-        FlowMintError::InvalidConfiguration
-    );
-    require!(
-        protected_slippage_bps <= default_slippage_bps,
-        FlowMintError::InvalidConfiguration
-    );
-    require!(
-        max_price_impact_bps <= MAX_SLIPPAGE_BPS,
-        FlowMintError::InvalidConfiguration
-    );
+    validate_initialize_params(
+        default_slippage_bps,
+        protected_slippage_bps,
+        max_price_impact_bps,
+        max_quote_ttl_seconds,
+    )?;
+
+    let treasury = &ctx.accounts.treasury;
+    validate_treasury(
+        &treasury.key(),
+        treasury.owner,
+        &treasury.try_borrow_data()?,
+        &ctx.accounts.config.key(),
+        &ctx.accounts.config,
+    )?;
+
+    ensure_config_uninitialized(&ctx.accounts.config)?;
 
     let config = &mut ctx.accounts.config;
 
     config.authority = ctx.accounts.authority.key();
+    config.operators = [Pubkey::default(); MAX_OPERATORS];
     config.default_slippage_bps = default_slippage_bps;
     config.protected_slippage_bps = protected_slippage_bps;
     config.max_price_impact_bps = max_price_impact_bps;
+    config.max_positive_slippage_bps = 0;
+    config.max_quote_ttl_seconds = max_quote_ttl_seconds;
     config.protected_mode_enabled = false;
     config.protocol_fee_bps = 0; // No protocol fee by default
     config.treasury = ctx.accounts.treasury.key();
     config.total_swaps = 0;
     config.total_volume_usd = 0;
+    config.sequence = 0;
+    config.referral_share_bps = 0;
+    config.fee_tiers = [FeeTier::default(); MAX_FEE_TIERS];
+    config.swaps_paused = false;
+    config.payments_paused = false;
+    config.quote_grace_seconds = 0;
+    config.max_payment_usdc = 0;
+    config.settlement_mint = Pubkey::default();
+    config.amm_allowlist_enabled = false;
+    config.fee_policy = FeePolicy::PayerPays;
+    config.slippage_tiers = [SlippageTier::default(); MAX_SLIPPAGE_TIERS];
+    config.treasury_timelock_seconds = DEFAULT_TREASURY_TIMELOCK_SECONDS;
+    config.pending_treasury = Pubkey::default();
+    config.treasury_change_eta = 0;
+    config.max_hops_protected = 0;
+    config.stats_max_age_seconds = 0;
+    config.direct_settlement_mints = [Pubkey::default(); MAX_DIRECT_SETTLEMENT_MINTS];
+    config.require_canonical_ata = false;
+    config.total_fees_collected = 0;
+    config.max_refund_bps = 0;
+    config.payment_max_quote_ttl_seconds = max_quote_ttl_seconds;
+    config.memo_policy = MemoPolicy::Truncate;
+    config.max_remaining_accounts = DEFAULT_MAX_REMAINING_ACCOUNTS;
+    config.max_route_rate_ratio = 0;
     config.bump = ctx.bumps.config;
     config._reserved = [0u8; 64];
+    config.oracle_feeds = [OracleFeed::default(); MAX_ORACLE_FEEDS];
 
     msg!(
         "FlowMint initialized with default_slippage={} bps, protected_slippage={} bps",
@@ -89,3 +133,290 @@ pub fn handler(
 
     Ok(())
 }
+
+/// Defensive guard against re-running `initialize` on an existing config
+///
+/// Unreachable while `config` uses `init`: the system program refuses to
+/// allocate the existing PDA, so a second `initialize` fails before the
+/// handler runs. Kept so the handler stays safe if the account constraint is
+/// ever relaxed (e.g. to `init_if_needed` for a migration).
+pub fn ensure_config_uninitialized(config: &ProtocolConfig) -> Result<()> {
+    if config.authority != Pubkey::default() {
+        msg!("Config already initialized with authority {}", config.authority);
+        return Err(FlowMintError::InvalidConfiguration.into());
+    }
+    Ok(())
+}
+
+/// Validate the initial protocol parameters
+///
+/// Both slippage limits must be nonzero: a zero protected slippage would
+/// reject every protected swap, and a zero default would reject every
+/// unprotected one.
+pub fn validate_initialize_params(
+    default_slippage_bps: u16,
+    protected_slippage_bps: u16,
+    max_price_impact_bps: u16,
+    max_quote_ttl_seconds: i64,
+) -> Result<()> {
+    require!(
+        default_slippage_bps <= MAX_SLIPPAGE_BPS,
+        FlowMintError::InvalidConfiguration
+    );
+    require!(protected_slippage_bps > 0, FlowMintError::InvalidConfiguration);
+    require!(
+        protected_slippage_bps <= default_slippage_bps,
+        FlowMintError::InvalidConfiguration
+    );
+    require!(
+        max_price_impact_bps <= MAX_SLIPPAGE_BPS,
+        FlowMintError::InvalidConfiguration
+    );
+    require!(max_quote_ttl_seconds > 0, FlowMintError::InvalidConfiguration);
+    Ok(())
+}
+
+/// Validate the treasury fees will be routed to
+///
+/// The treasury must be a system-owned wallet or an initialized SPL Token
+/// or Token-2022 account (extensions allowed) holding the settlement mint,
+/// and can never be the config PDA itself.
+///
+/// # Arguments
+///
+/// * `treasury` - Treasury address
+/// * `owner` - Program owning the treasury account
+/// * `data` - Treasury account data
+/// * `config_key` - Config PDA address
+/// * `config` - Protocol configuration, for the settlement mint
+pub fn validate_treasury(
+    treasury: &Pubkey,
+    owner: &Pubkey,
+    data: &[u8],
+    config_key: &Pubkey,
+    config: &ProtocolConfig,
+) -> Result<()> {
+    require_keys_neq!(*treasury, *config_key, FlowMintError::InvalidConfiguration);
+
+    if *owner == system_program::ID {
+        return Ok(());
+    }
+
+    require!(
+        *owner == token::ID || *owner == token_2022::ID,
+        FlowMintError::InvalidConfiguration
+    );
+    let account = token_interface::TokenAccount::try_deserialize(&mut &data[..])
+        .map_err(|_| FlowMintError::InvalidConfiguration)?;
+    require!(
+        config.is_settlement_mint(&account.mint),
+        FlowMintError::InvalidMint
+    );
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_runtime::RuntimeAccount;
+    use anchor_lang::solana_program::program_pack::Pack;
+    use anchor_lang::Bumps;
+    use anchor_spl::token::spl_token;
+    use std::collections::BTreeSet;
+
+    fn run_initialize<'info>(accounts: &'info [AccountInfo<'info>]) -> Result<()> {
+        let mut bumps = <Initialize as Bumps>::Bumps::default();
+        let mut remaining = accounts;
+        let mut init = Initialize::try_accounts(
+            &crate::ID,
+            &mut remaining,
+            &[],
+            &mut bumps,
+            &mut BTreeSet::new(),
+        )?;
+        handler(Context::new(&crate::ID, &mut init, remaining, bumps), 100, 50, 300, 30)?;
+        init.exit(&crate::ID)
+    }
+
+    #[test]
+    fn test_reinitialize_rejected() {
+        let mut config = ProtocolConfig::default();
+        assert!(ensure_config_uninitialized(&config).is_ok());
+
+        config.authority = Pubkey::new_unique();
+        assert_eq!(
+            ensure_config_uninitialized(&config).unwrap_err(),
+            FlowMintError::InvalidConfiguration.into()
+        );
+    }
+
+    #[test]
+    fn test_second_initialize_fails() {
+        crate::test_runtime::install();
+
+        let (config_key, _) = Pubkey::find_program_address(&[b"config"], &crate::ID);
+        let mut config =
+            RuntimeAccount::new(config_key, system_program::ID, 0, &[], ProtocolConfig::SIZE);
+        let mut treasury = RuntimeAccount::new(Pubkey::new_unique(), system_program::ID, 0, &[], 0);
+        let mut system = RuntimeAccount::program(system_program::ID);
+        let wallet = || {
+            RuntimeAccount::new(Pubkey::new_unique(), system_program::ID, 10_000_000_000, &[], 0)
+        };
+        let (mut first, mut second) = (wallet(), wallet());
+
+        {
+            let accounts = [
+                first.info(true, false),
+                config.info(false, false),
+                treasury.info(false, false),
+                system.info(false, true),
+            ];
+            run_initialize(&accounts).unwrap();
+        }
+
+        // The system program refuses to allocate the existing PDA
+        {
+            let accounts = [
+                second.info(true, false),
+                config.info(false, false),
+                treasury.info(false, false),
+                system.info(false, true),
+            ];
+            assert_eq!(
+                run_initialize(&accounts).unwrap_err(),
+                ProgramError::Custom(0).into()
+            );
+        }
+        let stored = ProtocolConfig::try_deserialize(&mut &config.bytes()[..]).unwrap();
+        assert_eq!(stored.authority, first.key);
+        assert_eq!(second.lamports, 10_000_000_000);
+    }
+
+    #[test]
+    fn test_initialize_params() {
+        assert!(validate_initialize_params(100, 50, 300, 30).is_ok());
+        assert!(validate_initialize_params(100, 100, 300, 30).is_ok());
+
+        // Zero protected slippage would brick protected mode
+        assert!(validate_initialize_params(100, 0, 300, 30).is_err());
+        // Zero default implies zero protected
+        assert!(validate_initialize_params(0, 0, 300, 30).is_err());
+
+        assert!(validate_initialize_params(100, 150, 300, 30).is_err());
+        assert!(validate_initialize_params(MAX_SLIPPAGE_BPS + 1, 50, 300, 30).is_err());
+        assert!(validate_initialize_params(100, 50, 300, 0).is_err());
+    }
+
+    fn token_account_data(mint: Pubkey) -> Vec<u8> {
+        let mut data = vec![0u8; spl_token::state::Account::LEN];
+        spl_token::state::Account {
+            mint,
+            owner: Pubkey::new_unique(),
+            state: spl_token::state::AccountState::Initialized,
+            ..Default::default()
+        }
+        .pack_into_slice(&mut data);
+        data
+    }
+
+    /// Token-2022 account carrying the `ImmutableOwner` extension
+    fn token_2022_account_data(mint: Pubkey) -> Vec<u8> {
+        use anchor_spl::token_2022::spl_token_2022::extension::{
+            immutable_owner::ImmutableOwner, BaseStateWithExtensionsMut, ExtensionType,
+            StateWithExtensionsMut,
+        };
+        use anchor_spl::token_2022::spl_token_2022::state::{Account, AccountState};
+
+        let len =
+            ExtensionType::try_calculate_account_len::<Account>(&[ExtensionType::ImmutableOwner])
+                .unwrap();
+        let mut data = vec![0u8; len];
+        let mut state = StateWithExtensionsMut::<Account>::unpack_uninitialized(&mut data).unwrap();
+        state.base = Account {
+            mint,
+            owner: Pubkey::new_unique(),
+            state: AccountState::Initialized,
+            ..Default::default()
+        };
+        state.pack_base();
+        state.init_extension::<ImmutableOwner>(true).unwrap();
+        state.init_account_type().unwrap();
+        data
+    }
+
+    fn settlement_config(settlement_mint: Pubkey) -> ProtocolConfig {
+        ProtocolConfig {
+            settlement_mint,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_valid_treasury() {
+        let config_key = Pubkey::new_unique();
+        let settlement_mint = Pubkey::new_unique();
+        let config = settlement_config(settlement_mint);
+        let treasury = Pubkey::new_unique();
+
+        // System-owned wallet
+        assert!(
+            validate_treasury(&treasury, &system_program::ID, &[], &config_key, &config).is_ok()
+        );
+
+        // Initialized token account for the settlement mint
+        let data = token_account_data(settlement_mint);
+        assert!(validate_treasury(&treasury, &token::ID, &data, &config_key, &config).is_ok());
+
+        // Token-2022 account with extensions
+        let data = token_2022_account_data(settlement_mint);
+        assert!(
+            validate_treasury(&treasury, &token_2022::ID, &data, &config_key, &config).is_ok()
+        );
+
+        // Without a settlement mint, USDC token accounts are accepted
+        let usdc: Pubkey = crate::instructions::payment::USDC_MINT_MAINNET.parse().unwrap();
+        let data = token_account_data(usdc);
+        assert!(validate_treasury(
+            &treasury,
+            &token::ID,
+            &data,
+            &config_key,
+            &ProtocolConfig::default()
+        )
+        .is_ok());
+    }
+
+    #[test]
+    fn test_invalid_treasury() {
+        let config_key = Pubkey::new_unique();
+        let settlement_mint = Pubkey::new_unique();
+        let config = settlement_config(settlement_mint);
+        let treasury = Pubkey::new_unique();
+
+        // The config PDA itself
+        assert!(
+            validate_treasury(&config_key, &system_program::ID, &[], &config_key, &config).is_err()
+        );
+
+        // Account owned by an arbitrary program
+        assert!(validate_treasury(&treasury, &crate::ID, &[], &config_key, &config).is_err());
+
+        // Uninitialized token account
+        let data = vec![0u8; spl_token::state::Account::LEN];
+        assert!(validate_treasury(&treasury, &token::ID, &data, &config_key, &config).is_err());
+
+        // Token accounts for another mint
+        let data = token_account_data(Pubkey::new_unique());
+        assert_eq!(
+            validate_treasury(&treasury, &token::ID, &data, &config_key, &config).unwrap_err(),
+            FlowMintError::InvalidMint.into()
+        );
+        let data = token_2022_account_data(Pubkey::new_unique());
+        assert_eq!(
+            validate_treasury(&treasury, &token_2022::ID, &data, &config_key, &config)
+                .unwrap_err(),
+            FlowMintError::InvalidMint.into()
+        );
+    }
+}
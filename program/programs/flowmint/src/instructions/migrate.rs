@@ -0,0 +1,357 @@
+//! Migrate Instructions
+//!
+//! Grow accounts created under an older layout to their current size.
+//!
+//! ## Layout
+//!
+//! Fields added to `ProtocolConfig`, `SwapReceipt` and `UserStats` after
+//! deployment are appended at the end of each struct, so an account written
+//! by an older program version is a prefix of the current layout. Migrating
+//! reallocates it to the current `SIZE`; the appended fields start zeroed,
+//! and the config's non-zero defaults are then filled in.
+
+use anchor_lang::prelude::*;
+use anchor_lang::system_program::{self, Transfer};
+use anchor_lang::Discriminator;
+
+use crate::errors::FlowMintError;
+use crate::instructions::admin::{emit_admin_action, AdminActionKind};
+use crate::state::{
+    ProtocolConfig, SwapReceipt, UserStats, DEFAULT_MAX_REMAINING_ACCOUNTS,
+    DEFAULT_TREASURY_TIMELOCK_SECONDS,
+};
+
+/// Accounts for migrating the protocol config to the current layout
+#[derive(Accounts)]
+pub struct MigrateConfig<'info> {
+    /// The protocol authority (pays for the added space)
+    #[account(mut)]
+    pub authority: Signer<'info>,
+
+    /// Protocol configuration PDA, possibly in an older layout
+    /// CHECK: Owner, discriminator and authority are checked by the handler
+    #[account(mut, seeds = [b"config"], bump)]
+    pub config: UncheckedAccount<'info>,
+
+    /// System program
+    pub system_program: Program<'info, System>,
+}
+
+/// Grow the config to `ProtocolConfig::SIZE` and default the appended fields
+///
+/// # Arguments
+///
+/// * `ctx` - MigrateConfig context
+/// * `max_quote_ttl_seconds` - Quote TTL for swaps and payments, as passed to `initialize`
+pub fn migrate_config_handler(
+    ctx: Context<MigrateConfig>,
+    max_quote_ttl_seconds: i64,
+) -> Result<()> {
+    require!(max_quote_ttl_seconds > 0, FlowMintError::InvalidConfiguration);
+
+    let config_info = ctx.accounts.config.to_account_info();
+    require_keys_eq!(
+        leading_pubkey(&config_info.try_borrow_data()?)?,
+        ctx.accounts.authority.key(),
+        FlowMintError::Unauthorized
+    );
+
+    let old_size = grow_account(
+        &config_info,
+        ProtocolConfig::DISCRIMINATOR,
+        ProtocolConfig::SIZE,
+        &ctx.accounts.authority.to_account_info(),
+        &ctx.accounts.system_program.to_account_info(),
+    )?;
+
+    let mut config = ProtocolConfig::try_deserialize(&mut &config_info.try_borrow_data()?[..])?;
+    config.max_quote_ttl_seconds = max_quote_ttl_seconds;
+    config.payment_max_quote_ttl_seconds = max_quote_ttl_seconds;
+    config.treasury_timelock_seconds = DEFAULT_TREASURY_TIMELOCK_SECONDS;
+    config.max_remaining_accounts = DEFAULT_MAX_REMAINING_ACCOUNTS;
+    let sequence = config.next_sequence()?;
+    config.try_serialize(&mut &mut config_info.try_borrow_mut_data()?[..])?;
+
+    msg!("Migrated config from {} to {} bytes", old_size, ProtocolConfig::SIZE);
+
+    let event = AccountMigrated {
+        account: config_info.key(),
+        old_size: old_size as u32,
+        new_size: ProtocolConfig::SIZE as u32,
+        sequence,
+    };
+    emit_admin_action(ctx.accounts.authority.key(), AdminActionKind::MigrateConfig, &event)?;
+    emit!(event);
+
+    Ok(())
+}
+
+/// Accounts for migrating a user's stats account to the current layout
+#[derive(Accounts)]
+pub struct MigrateUserStats<'info> {
+    /// The user who owns the stats account (pays for the added space)
+    #[account(mut)]
+    pub user: Signer<'info>,
+
+    /// Protocol configuration
+    #[account(
+        mut,
+        seeds = [b"config"],
+        bump = config.bump
+    )]
+    pub config: Account<'info, ProtocolConfig>,
+
+    /// User stats PDA, possibly in an older layout
+    /// CHECK: Address checked by seeds; owner and discriminator by the handler
+    #[account(mut, seeds = [b"user_stats", user.key().as_ref()], bump)]
+    pub user_stats: UncheckedAccount<'info>,
+
+    /// System program
+    pub system_program: Program<'info, System>,
+}
+
+/// Grow the user's stats account to `UserStats::SIZE`
+pub fn migrate_user_stats_handler(ctx: Context<MigrateUserStats>) -> Result<()> {
+    let user_stats_info = ctx.accounts.user_stats.to_account_info();
+    let old_size = grow_account(
+        &user_stats_info,
+        UserStats::DISCRIMINATOR,
+        UserStats::SIZE,
+        &ctx.accounts.user.to_account_info(),
+        &ctx.accounts.system_program.to_account_info(),
+    )?;
+    let sequence = ctx.accounts.config.next_sequence()?;
+
+    msg!("Migrated user stats for {}", ctx.accounts.user.key());
+
+    emit!(AccountMigrated {
+        account: user_stats_info.key(),
+        old_size: old_size as u32,
+        new_size: UserStats::SIZE as u32,
+        sequence,
+    });
+
+    Ok(())
+}
+
+/// Accounts for migrating one of the user's swap receipts to the current layout
+#[derive(Accounts)]
+pub struct MigrateReceipt<'info> {
+    /// The user who owns the receipt (pays for the added space)
+    #[account(mut)]
+    pub user: Signer<'info>,
+
+    /// Protocol configuration
+    #[account(
+        mut,
+        seeds = [b"config"],
+        bump = config.bump
+    )]
+    pub config: Account<'info, ProtocolConfig>,
+
+    /// Swap receipt, possibly in an older layout
+    /// CHECK: Owner, discriminator and user are checked by the handler
+    #[account(mut)]
+    pub receipt: UncheckedAccount<'info>,
+
+    /// System program
+    pub system_program: Program<'info, System>,
+}
+
+/// Grow one of the user's swap receipts to `SwapReceipt::SIZE`
+///
+/// Receipts must be migrated before `close_receipts_batch` can read them.
+pub fn migrate_receipt_handler(ctx: Context<MigrateReceipt>) -> Result<()> {
+    let receipt_info = ctx.accounts.receipt.to_account_info();
+    require_keys_eq!(
+        leading_pubkey(&receipt_info.try_borrow_data()?)?,
+        ctx.accounts.user.key(),
+        FlowMintError::Unauthorized
+    );
+
+    let old_size = grow_account(
+        &receipt_info,
+        SwapReceipt::DISCRIMINATOR,
+        SwapReceipt::SIZE,
+        &ctx.accounts.user.to_account_info(),
+        &ctx.accounts.system_program.to_account_info(),
+    )?;
+    let sequence = ctx.accounts.config.next_sequence()?;
+
+    msg!("Migrated receipt {}", receipt_info.key());
+
+    emit!(AccountMigrated {
+        account: receipt_info.key(),
+        old_size: old_size as u32,
+        new_size: SwapReceipt::SIZE as u32,
+        sequence,
+    });
+
+    Ok(())
+}
+
+/// Reallocate a program account to `new_size`, topping up its rent from `payer`
+///
+/// Returns the account's previous size.
+fn grow_account<'info>(
+    account: &AccountInfo<'info>,
+    discriminator: &[u8],
+    new_size: usize,
+    payer: &AccountInfo<'info>,
+    system_program: &AccountInfo<'info>,
+) -> Result<usize> {
+    require_keys_eq!(*account.owner, crate::ID, FlowMintError::InvalidOwner);
+    let old_size = account.data_len();
+    check_migratable(&account.try_borrow_data()?, discriminator, new_size)?;
+
+    let rent_due = Rent::get()?
+        .minimum_balance(new_size)
+        .saturating_sub(account.lamports());
+    if rent_due > 0 {
+        let cpi_ctx = CpiContext::new(
+            system_program.clone(),
+            Transfer {
+                from: payer.clone(),
+                to: account.clone(),
+            },
+        );
+        system_program::transfer(cpi_ctx, rent_due)?;
+    }
+
+    account.resize(new_size)?;
+    Ok(old_size)
+}
+
+/// Ensure account data holds `discriminator` and is smaller than `new_size`
+pub fn check_migratable(data: &[u8], discriminator: &[u8], new_size: usize) -> Result<()> {
+    require!(
+        data.len() >= 8 && data[..8] == *discriminator,
+        FlowMintError::InvalidInstructionData
+    );
+    require!(data.len() < new_size, FlowMintError::AccountAlreadyMigrated);
+    Ok(())
+}
+
+/// First field of an account's data: the config authority or the receipt's user
+pub fn leading_pubkey(data: &[u8]) -> Result<Pubkey> {
+    data.get(8..40)
+        .and_then(|bytes| <[u8; 32]>::try_from(bytes).ok())
+        .map(Pubkey::new_from_array)
+        .ok_or(FlowMintError::InvalidInstructionData.into())
+}
+
+/// Event emitted when an account is grown to its current layout
+#[event]
+pub struct AccountMigrated {
+    /// Migrated account
+    pub account: Pubkey,
+    /// Size before migration, in bytes
+    pub old_size: u32,
+    /// Size after migration, in bytes
+    pub new_size: u32,
+    /// Protocol event sequence number
+    pub sequence: u64,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// `ProtocolConfig` as originally deployed: discriminator through `_reserved`
+    fn legacy_config_bytes(authority: Pubkey, treasury: Pubkey) -> Vec<u8> {
+        let mut data = ProtocolConfig::DISCRIMINATOR.to_vec();
+        data.extend(authority.to_bytes());
+        data.extend(100u16.to_le_bytes()); // default_slippage_bps
+        data.extend(50u16.to_le_bytes()); // protected_slippage_bps
+        data.extend(300u16.to_le_bytes()); // max_price_impact_bps
+        data.push(1); // protected_mode_enabled
+        data.extend(25u16.to_le_bytes()); // protocol_fee_bps
+        data.extend(treasury.to_bytes());
+        data.extend(7u64.to_le_bytes()); // total_swaps
+        data.extend(9_000_000u64.to_le_bytes()); // total_volume_usd
+        data.push(254); // bump
+        data.extend([0u8; 64]); // _reserved
+        data
+    }
+
+    #[test]
+    fn test_legacy_config_reads_after_growing() {
+        let authority = Pubkey::new_unique();
+        let treasury = Pubkey::new_unique();
+        let mut data = legacy_config_bytes(authority, treasury);
+        assert!(
+            check_migratable(&data, ProtocolConfig::DISCRIMINATOR, ProtocolConfig::SIZE).is_ok()
+        );
+        assert_eq!(leading_pubkey(&data).unwrap(), authority);
+
+        // Growing zero-fills the tail; every original field keeps its value
+        data.resize(ProtocolConfig::SIZE, 0);
+        let config = ProtocolConfig::try_deserialize(&mut &data[..]).unwrap();
+        assert_eq!(config.authority, authority);
+        assert_eq!(config.default_slippage_bps, 100);
+        assert_eq!(config.protected_slippage_bps, 50);
+        assert_eq!(config.max_price_impact_bps, 300);
+        assert!(config.protected_mode_enabled);
+        assert_eq!(config.protocol_fee_bps, 25);
+        assert_eq!(config.treasury, treasury);
+        assert_eq!(config.total_swaps, 7);
+        assert_eq!(config.total_volume_usd, 9_000_000);
+        assert_eq!(config.bump, 254);
+        assert_eq!(config.sequence, 0);
+
+        // Already at the current size
+        assert_eq!(
+            check_migratable(&data, ProtocolConfig::DISCRIMINATOR, ProtocolConfig::SIZE)
+                .unwrap_err(),
+            FlowMintError::AccountAlreadyMigrated.into()
+        );
+    }
+
+    #[test]
+    fn test_legacy_user_stats_and_receipt_read_after_growing() {
+        let user = Pubkey::new_unique();
+
+        let mut stats = UserStats::DISCRIMINATOR.to_vec();
+        stats.extend(user.to_bytes());
+        for value in [3u64, 5_000_000, 2, 0, 0] {
+            stats.extend(value.to_le_bytes());
+        }
+        stats.extend(1_700_000_000i64.to_le_bytes()); // last_activity
+        stats.push(253); // bump
+        assert!(check_migratable(&stats, UserStats::DISCRIMINATOR, UserStats::SIZE).is_ok());
+        stats.resize(UserStats::SIZE, 0);
+        let stats = UserStats::try_deserialize(&mut &stats[..]).unwrap();
+        assert_eq!(stats.user, user);
+        assert_eq!(stats.total_swaps, 3);
+        assert_eq!(stats.total_volume_usd, 5_000_000);
+        assert_eq!(stats.total_payments, 2);
+        assert_eq!(stats.last_activity, 1_700_000_000);
+        assert_eq!(stats.bump, 253);
+        assert!(!stats.always_protected && !stats.fee_exempt);
+
+        let mut receipt = SwapReceipt::DISCRIMINATOR.to_vec();
+        receipt.extend(user.to_bytes());
+        receipt.extend([0u8; 64]); // input_mint, output_mint
+        receipt.extend(1_000u64.to_le_bytes()); // amount_in
+        receipt.extend(990u64.to_le_bytes()); // amount_out
+        receipt.extend(50u16.to_le_bytes()); // slippage_bps
+        receipt.push(0); // protected_mode
+        receipt.extend(1_700_000_000i64.to_le_bytes()); // timestamp
+        receipt.extend([0u8; 32]); // tx_signature
+        receipt.push(252); // bump
+        assert_eq!(leading_pubkey(&receipt).unwrap(), user);
+        assert!(
+            check_migratable(&receipt, SwapReceipt::DISCRIMINATOR, SwapReceipt::SIZE).is_ok()
+        );
+        receipt.resize(SwapReceipt::SIZE, 0);
+        let receipt = SwapReceipt::try_deserialize(&mut &receipt[..]).unwrap();
+        assert_eq!(receipt.user, user);
+        assert_eq!(receipt.amount_out, 990);
+        assert_eq!(receipt.timestamp, 1_700_000_000);
+        assert_eq!(receipt.bump, 252);
+
+        // Accounts of another type are never grown
+        let config = legacy_config_bytes(user, user);
+        assert!(check_migratable(&config, UserStats::DISCRIMINATOR, UserStats::SIZE).is_err());
+    }
+}
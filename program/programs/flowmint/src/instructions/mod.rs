@@ -4,10 +4,22 @@
 
 pub mod admin;
 pub mod initialize;
+pub mod migrate;
 pub mod payment;
 pub mod swap;
+pub mod swap_exact_out;
+#[cfg(feature = "devnet-teardown")]
+pub mod teardown;
+pub mod user;
+pub mod validate_route;
 
 pub use admin::*;
 pub use initialize::*;
+pub use migrate::*;
 pub use payment::*;
 pub use swap::*;
+pub use swap_exact_out::*;
+#[cfg(feature = "devnet-teardown")]
+pub use teardown::*;
+pub use user::*;
+pub use validate_route::*;
@@ -11,13 +11,24 @@
 //! 5. Record payment on-chain
 
 use anchor_lang::prelude::*;
-use anchor_spl::token::{self, Token, TokenAccount, Transfer};
+use anchor_lang::solana_program::pubkey;
+use anchor_spl::associated_token::{get_associated_token_address_with_program_id, AssociatedToken};
+use anchor_spl::token_2022::spl_token_2022::state::AccountState;
+use anchor_spl::token_interface::{Mint, TokenAccount, TokenInterface};
 
 use crate::errors::FlowMintError;
+use crate::instructions::swap::{
+    compute_amount_spent, enforce_amm_allowlist, ensure_impact_within_limit, ImpactSource,
+};
 use crate::jupiter::{
-    JupiterRoute, execute_jupiter_swap, deserialize_route, verify_swap_output
+    check_hop_token_programs, check_swap_destination, execute_jupiter_swap, ensure_quote_fresh,
+    load_route,
+};
+use crate::state::{
+    normalize_usd_volume, AmmAllowlist, MerchantStats, PaymentRecord, PaymentReference,
+    ProtocolConfig, UserStats,
 };
-use crate::state::{PaymentRecord, ProtocolConfig, UserStats};
+use crate::token_transfer::transfer_tokens;
 
 /// USDC mint address on mainnet
 pub const USDC_MINT_MAINNET: &str = "EPjFWdd5AufqSSqeM2qN1xzybapC8G4wEGGkZwyTDt1v";
@@ -25,11 +36,29 @@ pub const USDC_MINT_MAINNET: &str = "EPjFWdd5AufqSSqeM2qN1xzybapC8G4wEGGkZwyTDt1
 /// USDC mint address on devnet
 pub const USDC_MINT_DEVNET: &str = "4zMMC9srt5Ri5X14GAgXhaHii3GnPAEERYPJgZJDncDU";
 
+/// Known USDC mints (mainnet, devnet)
+const USDC_MINTS: [Pubkey; 2] = [
+    pubkey!("EPjFWdd5AufqSSqeM2qN1xzybapC8G4wEGGkZwyTDt1v"),
+    pubkey!("4zMMC9srt5Ri5X14GAgXhaHii3GnPAEERYPJgZJDncDU"),
+];
+
+/// Whether `mint` is a known USDC mint
+pub fn is_usdc_mint(mint: &Pubkey) -> bool {
+    USDC_MINTS.contains(mint)
+}
+
 /// Maximum memo length
 pub const MAX_MEMO_LENGTH: usize = 64;
 
 /// Accounts for the PayAnyToken instruction
 #[derive(Accounts)]
+#[instruction(
+    amount_in: u64,
+    exact_usdc_out: u64,
+    memo: Option<String>,
+    route_bytes: Option<Vec<u8>>,
+    reference: Option<[u8; 32]>,
+)]
 pub struct PayAnyToken<'info> {
     /// The payer
     #[account(mut)]
@@ -49,7 +78,7 @@ pub struct PayAnyToken<'info> {
         constraint = payer_input_account.owner == payer.key() @ FlowMintError::InvalidOwner,
         constraint = payer_input_account.mint == input_mint.key() @ FlowMintError::InvalidMint
     )]
-    pub payer_input_account: Account<'info, TokenAccount>,
+    pub payer_input_account: InterfaceAccount<'info, TokenAccount>,
 
     /// Payer's USDC account (for receiving change if any)
     #[account(
@@ -57,27 +86,54 @@ pub struct PayAnyToken<'info> {
         constraint = payer_usdc_account.owner == payer.key() @ FlowMintError::InvalidOwner,
         constraint = payer_usdc_account.mint == usdc_mint.key() @ FlowMintError::InvalidMint
     )]
-    pub payer_usdc_account: Account<'info, TokenAccount>,
+    pub payer_usdc_account: InterfaceAccount<'info, TokenAccount>,
+
+    /// Alternate USDC account receiving any change (defaults to `payer_usdc_account`)
+    #[account(
+        mut,
+        constraint = refund_account.mint == usdc_mint.key() @ FlowMintError::InvalidMint
+    )]
+    pub refund_account: Option<InterfaceAccount<'info, TokenAccount>>,
 
     /// Input token mint
     /// CHECK: Validated by token account constraints
     pub input_mint: AccountInfo<'info>,
 
-    /// Merchant's USDC account (destination)
+    /// Merchant's existing USDC account (destination); omit to use `merchant_usdc_ata`
     #[account(
         mut,
         constraint = merchant_usdc_account.owner == merchant.key() @ FlowMintError::InvalidOwner,
         constraint = merchant_usdc_account.mint == usdc_mint.key() @ FlowMintError::InvalidMint
     )]
-    pub merchant_usdc_account: Account<'info, TokenAccount>,
+    pub merchant_usdc_account: Option<InterfaceAccount<'info, TokenAccount>>,
 
     /// Merchant pubkey
     /// CHECK: Just receiving payment
     pub merchant: AccountInfo<'info>,
 
-    /// USDC mint
-    /// CHECK: Validated by token account constraints
-    pub usdc_mint: AccountInfo<'info>,
+    /// Merchant's input-mint account (required for direct alternate-mint payments)
+    #[account(
+        mut,
+        constraint = merchant_input_account.owner == merchant.key() @ FlowMintError::InvalidOwner,
+        constraint = merchant_input_account.mint == input_mint.key() @ FlowMintError::InvalidMint
+    )]
+    pub merchant_input_account: Option<InterfaceAccount<'info, TokenAccount>>,
+
+    /// FeeVault for the input mint (required for direct alternate-mint payments with a fee)
+    #[account(
+        mut,
+        constraint = fee_vault_input_account.mint == input_mint.key() @ FlowMintError::InvalidMint,
+        constraint = fee_vault_input_account.owner == config.key() @ FlowMintError::InvalidOwner,
+        seeds = [b"fee_vault", input_mint.key().as_ref()],
+        bump,
+    )]
+    pub fee_vault_input_account: Option<InterfaceAccount<'info, TokenAccount>>,
+
+    /// Settlement (USDC) mint
+    #[account(
+        constraint = config.is_settlement_mint(&usdc_mint.key()) @ FlowMintError::InvalidMint
+    )]
+    pub usdc_mint: InterfaceAccount<'info, Mint>,
 
     /// Protocol fee vault (USDC) - PDA token account owned by the config PDA
     #[account(
@@ -88,7 +144,7 @@ pub struct PayAnyToken<'info> {
         seeds = [b"fee_vault", usdc_mint.key().as_ref()],
         bump,
     )]
-    pub fee_vault_usdc_account: Account<'info, TokenAccount>,
+    pub fee_vault_usdc_account: InterfaceAccount<'info, TokenAccount>,
 
     /// Temporary PDA USDC account to receive swap output
     #[account(
@@ -99,7 +155,7 @@ pub struct PayAnyToken<'info> {
         seeds = [b"temp_usdc", payer.key().as_ref()],
         bump,
     )]
-    pub temp_usdc_account: Account<'info, TokenAccount>,
+    pub temp_usdc_account: InterfaceAccount<'info, TokenAccount>,
 
     /// Payment record account (PDA)
     #[account(
@@ -126,26 +182,137 @@ pub struct PayAnyToken<'info> {
     )]
     pub payer_stats: Account<'info, UserStats>,
 
+    /// Merchant's cumulative stats account (PDA)
+    #[account(
+        init_if_needed,
+        payer = payer,
+        space = MerchantStats::SIZE,
+        seeds = [b"merchant_stats", merchant.key().as_ref()],
+        bump
+    )]
+    pub merchant_stats: Account<'info, MerchantStats>,
+
+    /// AMM allowlist (required while the allowlist is enabled)
+    #[account(seeds = [b"amm_allowlist"], bump = amm_allowlist.bump)]
+    pub amm_allowlist: Option<Account<'info, AmmAllowlist>>,
+
+    /// Reference index for the payment (required when `reference` is given)
+    #[account(
+        init,
+        payer = payer,
+        space = PaymentReference::SIZE,
+        seeds = [
+            b"payment_ref",
+            merchant.key().as_ref(),
+            reference.unwrap_or_default().as_ref()
+        ],
+        bump
+    )]
+    pub payment_reference: Option<Account<'info, PaymentReference>>,
+
+    /// Merchant's USDC ATA, created (payer funds rent) if it doesn't exist yet;
+    /// used as the destination when `merchant_usdc_account` is omitted
+    #[account(
+        init_if_needed,
+        payer = payer,
+        associated_token::mint = usdc_mint,
+        associated_token::authority = merchant,
+        associated_token::token_program = token_program
+    )]
+    pub merchant_usdc_ata: Option<InterfaceAccount<'info, TokenAccount>>,
+
     /// Jupiter program
     /// CHECK: Validated against known Jupiter program ID
     pub jupiter_program: AccountInfo<'info>,
 
-    /// Token program
-    pub token_program: Program<'info, Token>,
+    /// Token program (SPL Token or Token-2022)
+    pub token_program: Interface<'info, TokenInterface>,
 
     /// System program
     pub system_program: Program<'info, System>,
 
     /// Rent sysvar (required for token account init)
     pub rent: Sysvar<'info, Rent>,
+
+    /// Associated token program (required with `merchant_usdc_ata`)
+    pub associated_token_program: Option<Program<'info, AssociatedToken>>,
+}
+
+/// Accounts for jointly reclaiming a payment record
+///
+/// Both the payer and the merchant must sign, so neither party can remove
+/// the record unilaterally.
+#[derive(Accounts)]
+pub struct ReclaimPaymentRecord<'info> {
+    /// The payer (receives the rent)
+    #[account(mut)]
+    pub payer: Signer<'info>,
+
+    /// The merchant (co-signer)
+    pub merchant: Signer<'info>,
+
+    /// Protocol configuration
+    #[account(
+        mut,
+        seeds = [b"config"],
+        bump = config.bump
+    )]
+    pub config: Account<'info, ProtocolConfig>,
+
+    /// Payment record account (PDA)
+    #[account(
+        mut,
+        close = payer,
+        seeds = [
+            b"payment",
+            payer.key().as_ref(),
+            merchant.key().as_ref(),
+            &payment_record.timestamp.to_le_bytes()
+        ],
+        bump = payment_record.bump,
+        constraint = payment_record.is_between(&payer.key(), &merchant.key())
+            @ FlowMintError::Unauthorized
+    )]
+    pub payment_record: Account<'info, PaymentRecord>,
+}
+
+/// Ensure the merchant's USDC account can receive the payment
+///
+/// A frozen or uninitialized destination makes the token transfer fail with
+/// an opaque error; reject it up front with a clear one instead.
+pub fn check_merchant_account_receivable(state: AccountState) -> Result<()> {
+    match state {
+        AccountState::Initialized => Ok(()),
+        AccountState::Frozen => {
+            msg!("Merchant USDC account is frozen");
+            Err(FlowMintError::PaymentFailed.into())
+        }
+        AccountState::Uninitialized => {
+            msg!("Merchant USDC account is not initialized");
+            Err(FlowMintError::PaymentFailed.into())
+        }
+    }
 }
 
-fn compute_protocol_fee(exact_usdc_out: u64, protocol_fee_bps: u16) -> Result<u64> {
+/// Ensure an alternate direct-settlement mint can be transferred 1:1
+///
+/// Amounts are denominated in the settlement mint, so the alternate mint
+/// must use the same decimals.
+pub fn check_direct_settlement_decimals(input_decimals: u8, settlement_decimals: u8) -> Result<()> {
+    require!(
+        input_decimals == settlement_decimals,
+        FlowMintError::InvalidMint
+    );
+    Ok(())
+}
+
+/// Compute the protocol fee owed on `amount` at `protocol_fee_bps`
+pub fn compute_protocol_fee(amount: u64, protocol_fee_bps: u16) -> Result<u64> {
     if protocol_fee_bps == 0 {
         return Ok(0);
     }
 
-    let fee = (exact_usdc_out as u128)
+    let fee = (amount as u128)
         .checked_mul(protocol_fee_bps as u128)
         .ok_or(FlowMintError::MathOverflow)?
         .checked_div(10_000)
@@ -154,6 +321,197 @@ fn compute_protocol_fee(exact_usdc_out: u64, protocol_fee_bps: u16) -> Result<u6
     Ok(fee as u64)
 }
 
+/// Step of a payment whose failure is reported as `PaymentFailed`
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PaymentStep {
+    /// Jupiter swap into USDC
+    Swap,
+    /// Protocol fee transfer to the FeeVault
+    FeeTransfer,
+    /// USDC transfer to the merchant
+    MerchantTransfer,
+    /// Excess USDC refund to the payer or refund account
+    Refund,
+}
+
+/// Map a CPI failure during a payment to `PaymentFailed`
+///
+/// The underlying error and the failing step are logged so clients get a
+/// consistent top-level error without losing the cause.
+pub fn payment_step_failed(step: PaymentStep, err: Error) -> Error {
+    msg!("Payment failed at {:?} step: {}", step, err);
+    FlowMintError::PaymentFailed.into()
+}
+
+/// USDC the swap delivered into the temp account
+///
+/// Only the delta counts, so residue present at entry is never attributed to
+/// this payment. A balance that shrank means something other than the swap
+/// moved funds out of the temp account, which is rejected explicitly.
+pub fn compute_temp_usdc_received(balance_before: u64, balance_after: u64) -> Result<u64> {
+    if balance_after < balance_before {
+        msg!(
+            "Temp USDC balance fell from {} to {}",
+            balance_before,
+            balance_after
+        );
+        return Err(FlowMintError::TempBalanceDecreased.into());
+    }
+    Ok(balance_after - balance_before)
+}
+
+/// Excess USDC to refund to the payer after settling a payment
+///
+/// Receiving less than `required_usdc_out` is rejected earlier, so an
+/// underflow here indicates an accounting bug and fails loudly.
+pub fn compute_payment_refund(actual_usdc_received: u64, required_usdc_out: u64) -> Result<u64> {
+    actual_usdc_received
+        .checked_sub(required_usdc_out)
+        .ok_or(FlowMintError::MathOverflow.into())
+}
+
+/// Ensure a swap did not consume more input than the route quoted
+///
+/// # Arguments
+/// * `actual_amount_in` - Input actually consumed by the swap
+/// * `quoted_amount_in` - Input quoted by the route
+/// * `tolerance_bps` - Allowed overspend relative to the quote
+pub fn check_input_within_quote(
+    actual_amount_in: u64,
+    quoted_amount_in: u64,
+    tolerance_bps: u16,
+) -> Result<()> {
+    let max_amount_in = (quoted_amount_in as u128)
+        .checked_mul(10_000 + tolerance_bps as u128)
+        .ok_or(FlowMintError::MathOverflow)?
+        / 10_000;
+
+    require!(
+        actual_amount_in as u128 <= max_amount_in,
+        FlowMintError::InputOverspend
+    );
+
+    Ok(())
+}
+
+/// Ensure `token_account` is the canonical ATA for `(owner, mint)` when required
+pub fn check_canonical_ata(
+    required: bool,
+    token_account: &Pubkey,
+    owner: &Pubkey,
+    mint: &Pubkey,
+    token_program: &Pubkey,
+) -> Result<()> {
+    if !required {
+        return Ok(());
+    }
+
+    let canonical = get_associated_token_address_with_program_id(owner, mint, token_program);
+    require_keys_eq!(
+        *token_account,
+        canonical,
+        FlowMintError::NonCanonicalTokenAccount
+    );
+    Ok(())
+}
+
+/// Ensure the temp USDC account is the payer's canonical `[b"temp_usdc", payer]` PDA
+///
+/// The seeds constraint already enforces this; checking explicitly gives a
+/// clear error instead of a later, opaque signing or transfer failure.
+pub fn check_temp_usdc_account(
+    temp_usdc_account: &Pubkey,
+    payer: &Pubkey,
+    bump: u8,
+    program_id: &Pubkey,
+) -> Result<()> {
+    let (expected, canonical_bump) =
+        Pubkey::find_program_address(&[b"temp_usdc", payer.as_ref()], program_id);
+    require_keys_eq!(
+        *temp_usdc_account,
+        expected,
+        FlowMintError::InvalidTempAccount
+    );
+    require!(bump == canonical_bump, FlowMintError::InvalidTempAccount);
+    Ok(())
+}
+
+/// Resolve a token account supplied either as an existing account or as an ATA
+///
+/// Used for the merchant's USDC destination and the swapper's output account.
+/// Exactly one of the existing account or the (possibly just created) ATA
+/// must be supplied.
+pub fn existing_or_created_account<T>(existing: Option<T>, created_ata: Option<T>) -> Result<T> {
+    match (existing, created_ata) {
+        (Some(account), None) | (None, Some(account)) => Ok(account),
+        _ => Err(FlowMintError::InvalidInstructionData.into()),
+    }
+}
+
+/// USDC account that receives a payment's change
+///
+/// Defaults to the payer's USDC account; an alternate `refund_account`
+/// (e.g. a smart wallet's sweep account) may not be the temp account.
+pub fn refund_destination(
+    payer_usdc_account: Pubkey,
+    refund_account: Option<Pubkey>,
+    temp_usdc_account: &Pubkey,
+) -> Result<Pubkey> {
+    let destination = refund_account.unwrap_or(payer_usdc_account);
+    require_keys_neq!(destination, *temp_usdc_account, FlowMintError::DuplicateAccount);
+    Ok(destination)
+}
+
+/// Reject implausibly large change, which indicates a mis-sized `amount_in`
+///
+/// `excess_usdc` may be at most `max_refund_bps` of the USDC received
+/// (0 = unlimited).
+pub fn check_refund_plausible(
+    excess_usdc: u64,
+    actual_usdc_received: u64,
+    max_refund_bps: u16,
+) -> Result<()> {
+    if max_refund_bps == 0 {
+        return Ok(());
+    }
+
+    let max_refund = actual_usdc_received as u128 * max_refund_bps as u128 / 10_000;
+    if excess_usdc as u128 > max_refund {
+        msg!(
+            "Refund {} exceeds {} bps of {} received",
+            excess_usdc,
+            max_refund_bps,
+            actual_usdc_received
+        );
+        return Err(FlowMintError::RefundExceedsInput.into());
+    }
+    Ok(())
+}
+
+/// Ensure the payer change, merchant destination, and temp accounts are all distinct
+pub fn ensure_distinct_usdc_accounts(
+    payer_usdc_account: &Pubkey,
+    merchant_usdc_account: &Pubkey,
+    temp_usdc_account: &Pubkey,
+) -> Result<()> {
+    require_keys_neq!(
+        *payer_usdc_account,
+        *merchant_usdc_account,
+        FlowMintError::DuplicateAccount
+    );
+    require_keys_neq!(
+        *temp_usdc_account,
+        *payer_usdc_account,
+        FlowMintError::DuplicateAccount
+    );
+    require_keys_neq!(
+        *temp_usdc_account,
+        *merchant_usdc_account,
+        FlowMintError::DuplicateAccount
+    );
+    Ok(())
+}
+
 /// Execute a payment by converting any token to USDC
 ///
 /// # Flow
@@ -169,8 +527,11 @@ fn compute_protocol_fee(exact_usdc_out: u64, protocol_fee_bps: u16) -> Result<u6
 ///
 /// * `ctx` - PayAnyToken context
 /// * `amount_in` - Maximum amount of input tokens to spend
-/// * `exact_usdc_out` - Exact USDC amount merchant should receive
+/// * `exact_usdc_out` - Payment amount; the merchant nets this minus the
+///   protocol fee under `FeePolicy::MerchantPays`
 /// * `memo` - Optional payment reference
+/// * `route_bytes` - Serialized route; when `None` it is read from the first remaining account
+/// * `reference` - Optional merchant reference indexing the payment record
 ///
 /// # Returns
 ///
@@ -180,6 +541,8 @@ pub fn pay_any_token_handler<'info>(
     amount_in: u64,
     exact_usdc_out: u64,
     memo: Option<String>,
+    route_bytes: Option<Vec<u8>>,
+    reference: Option<[u8; 32]>,
 ) -> Result<()> {
     let payer = &ctx.accounts.payer;
     let payer_input_account = &ctx.accounts.payer_input_account;
@@ -188,8 +551,65 @@ pub fn pay_any_token_handler<'info>(
     // ============================================================
     // Step 1: Validate input
     // ============================================================
+    require!(!ctx.accounts.config.payments_paused, FlowMintError::PaymentsPaused);
+
+    let stats_max_age = ctx.accounts.config.stats_max_age_seconds;
+    ctx.accounts
+        .payer_stats
+        .reset_if_dormant(clock.unix_timestamp, stats_max_age);
+
+    // Self-payments create meaningless records and inflate stats
+    require_keys_neq!(
+        payer.key(),
+        ctx.accounts.merchant.key(),
+        FlowMintError::InvalidConfiguration
+    );
+
     require!(amount_in > 0, FlowMintError::AmountTooSmall);
     require!(exact_usdc_out > 0, FlowMintError::AmountTooSmall);
+    require!(
+        ctx.accounts.config.is_payment_allowed(exact_usdc_out),
+        FlowMintError::AmountTooLarge
+    );
+
+    // A reference and its index account must be supplied together
+    require!(
+        reference.is_some() == ctx.accounts.payment_reference.is_some(),
+        FlowMintError::InvalidInstructionData
+    );
+
+    let merchant_usdc_account = existing_or_created_account(
+        ctx.accounts.merchant_usdc_account.as_ref(),
+        ctx.accounts.merchant_usdc_ata.as_ref(),
+    )?;
+    let merchant_usdc_info = merchant_usdc_account.to_account_info();
+
+    check_merchant_account_receivable(merchant_usdc_account.state)?;
+    check_canonical_ata(
+        ctx.accounts.config.require_canonical_ata,
+        &merchant_usdc_account.key(),
+        &ctx.accounts.merchant.key(),
+        &ctx.accounts.usdc_mint.key(),
+        &ctx.accounts.token_program.key(),
+    )?;
+
+    check_temp_usdc_account(
+        &ctx.accounts.temp_usdc_account.key(),
+        &ctx.accounts.payer.key(),
+        ctx.bumps.temp_usdc_account,
+        ctx.program_id,
+    )?;
+
+    ensure_distinct_usdc_accounts(
+        &ctx.accounts.payer_usdc_account.key(),
+        &merchant_usdc_account.key(),
+        &ctx.accounts.temp_usdc_account.key(),
+    )?;
+    refund_destination(
+        ctx.accounts.payer_usdc_account.key(),
+        ctx.accounts.refund_account.as_ref().map(|a| a.key()),
+        &ctx.accounts.temp_usdc_account.key(),
+    )?;
 
     // Check payer has sufficient balance
     require!(
@@ -201,23 +621,27 @@ pub fn pay_any_token_handler<'info>(
     // Step 2: Handle direct USDC payment (no swap needed)
     // ============================================================
     let is_direct_usdc = ctx.accounts.input_mint.key() == ctx.accounts.usdc_mint.key();
-    
-    let protocol_fee = compute_protocol_fee(exact_usdc_out, ctx.accounts.config.protocol_fee_bps)?;
-    let required_usdc_out = exact_usdc_out
-        .checked_add(protocol_fee)
-        .ok_or(FlowMintError::MathOverflow)?;
+
+    let fee_bps = ctx
+        .accounts
+        .config
+        .fee_bps_for_user(&ctx.accounts.payer_stats);
+    let protocol_fee = compute_protocol_fee(exact_usdc_out, fee_bps)?;
+    let (required_usdc_out, merchant_amount) = ctx
+        .accounts
+        .config
+        .fee_policy
+        .settle(exact_usdc_out, protocol_fee)?;
 
     let actual_amount_in: u64;
-    let actual_usdc_received: u64;
 
     if is_direct_usdc {
         // Direct USDC transfer - no swap needed
-        // Payer covers merchant amount + protocol fee (if enabled)
+        // Payer covers merchant amount + protocol fee per the fee policy
         require!(amount_in >= required_usdc_out, FlowMintError::AmountTooSmall);
         actual_amount_in = required_usdc_out;
-        actual_usdc_received = required_usdc_out;
 
-        // Ensure the payer has enough USDC to cover merchant + fee
+        // Ensure the payer has enough USDC to cover the whole settlement
         require!(
             payer_input_account.amount >= required_usdc_out,
             FlowMintError::InsufficientBalance
@@ -225,37 +649,93 @@ pub fn pay_any_token_handler<'info>(
 
         // Transfer protocol fee to FeeVault (if any)
         if protocol_fee > 0 {
-            let fee_ctx = CpiContext::new(
-                ctx.accounts.token_program.to_account_info(),
-                Transfer {
-                    from: ctx.accounts.payer_input_account.to_account_info(),
-                    to: ctx.accounts.fee_vault_usdc_account.to_account_info(),
-                    authority: ctx.accounts.payer.to_account_info(),
-                },
-            );
-            token::transfer(fee_ctx, protocol_fee)?;
+            transfer_tokens(
+                &ctx.accounts.token_program.to_account_info(),
+                &ctx.accounts.payer_input_account.to_account_info(),
+                &ctx.accounts.usdc_mint.to_account_info(),
+                &ctx.accounts.fee_vault_usdc_account.to_account_info(),
+                &ctx.accounts.payer.to_account_info(),
+                ctx.remaining_accounts,
+                protocol_fee,
+                ctx.accounts.usdc_mint.decimals,
+                &[],
+            )
+            .map_err(|e| payment_step_failed(PaymentStep::FeeTransfer, e))?;
         }
 
         // Transfer USDC directly from payer to merchant
-        let transfer_ctx = CpiContext::new(
-            ctx.accounts.token_program.to_account_info(),
-            Transfer {
-                from: ctx.accounts.payer_input_account.to_account_info(),
-                to: ctx.accounts.merchant_usdc_account.to_account_info(),
-                authority: ctx.accounts.payer.to_account_info(),
-            },
+        transfer_tokens(
+            &ctx.accounts.token_program.to_account_info(),
+            &ctx.accounts.payer_input_account.to_account_info(),
+            &ctx.accounts.usdc_mint.to_account_info(),
+            &merchant_usdc_info,
+            &ctx.accounts.payer.to_account_info(),
+            ctx.remaining_accounts,
+            merchant_amount,
+            ctx.accounts.usdc_mint.decimals,
+            &[],
+        )
+        .map_err(|e| payment_step_failed(PaymentStep::MerchantTransfer, e))?;
+    } else if ctx.accounts.config.is_direct_settlement_mint(&ctx.accounts.input_mint.key()) {
+        // Direct transfer of an alternate accepted stable, 1:1 with USDC
+        let input_decimals =
+            Mint::try_deserialize(&mut &ctx.accounts.input_mint.try_borrow_data()?[..])?.decimals;
+        check_direct_settlement_decimals(input_decimals, ctx.accounts.usdc_mint.decimals)?;
+
+        require!(amount_in >= required_usdc_out, FlowMintError::AmountTooSmall);
+        require!(
+            payer_input_account.amount >= required_usdc_out,
+            FlowMintError::InsufficientBalance
         );
-        token::transfer(transfer_ctx, exact_usdc_out)?;
+        actual_amount_in = required_usdc_out;
+
+        let merchant_input_account = ctx
+            .accounts
+            .merchant_input_account
+            .as_ref()
+            .ok_or(FlowMintError::InvalidInstructionData)?;
+        check_merchant_account_receivable(merchant_input_account.state)?;
+
+        if protocol_fee > 0 {
+            let fee_vault = ctx
+                .accounts
+                .fee_vault_input_account
+                .as_ref()
+                .ok_or(FlowMintError::MissingFeeVault)?;
+            transfer_tokens(
+                &ctx.accounts.token_program.to_account_info(),
+                &ctx.accounts.payer_input_account.to_account_info(),
+                &ctx.accounts.input_mint.to_account_info(),
+                &fee_vault.to_account_info(),
+                &ctx.accounts.payer.to_account_info(),
+                ctx.remaining_accounts,
+                protocol_fee,
+                input_decimals,
+                &[],
+            )
+            .map_err(|e| payment_step_failed(PaymentStep::FeeTransfer, e))?;
+        }
+
+        transfer_tokens(
+            &ctx.accounts.token_program.to_account_info(),
+            &ctx.accounts.payer_input_account.to_account_info(),
+            &ctx.accounts.input_mint.to_account_info(),
+            &merchant_input_account.to_account_info(),
+            &ctx.accounts.payer.to_account_info(),
+            ctx.remaining_accounts,
+            merchant_amount,
+            input_decimals,
+            &[],
+        )
+        .map_err(|e| payment_step_failed(PaymentStep::MerchantTransfer, e))?;
     } else {
         // ============================================================
         // Step 3: Deserialize and validate Jupiter route
         // ============================================================
         let remaining_accounts = &ctx.remaining_accounts;
-        require!(!remaining_accounts.is_empty(), FlowMintError::InvalidInstructionData);
-
-        let route_account = &remaining_accounts[0];
-        let route_data = route_account.try_borrow_data()?;
-        let route = deserialize_route(&route_data)?;
+        ctx.accounts.config.check_remaining_accounts(remaining_accounts.len())?;
+        let (route, first_swap_account) =
+            load_route(route_bytes.as_deref(), remaining_accounts)?;
 
         // Validate route is for input -> USDC
         route.validate(
@@ -266,31 +746,62 @@ pub fn pay_any_token_handler<'info>(
             ctx.accounts.config.default_slippage_bps, // Use protocol default for payments
         )?;
 
-        // Check quote expiration
-        require!(
-            !route.is_expired(clock.unix_timestamp),
-            FlowMintError::QuoteExpired
-        );
+        enforce_amm_allowlist(
+            &ctx.accounts.config,
+            ctx.accounts.amm_allowlist.as_deref(),
+            &route,
+        )?;
+        check_hop_token_programs(&route, &remaining_accounts[first_swap_account..])?;
+
+        // Validate price impact when protected mode is enforced for the payer
+        if ctx
+            .accounts
+            .config
+            .is_protected_required(false, ctx.accounts.payer_stats.always_protected)
+        {
+            ensure_impact_within_limit(
+                ImpactSource::RouteFees,
+                route.price_impact_bps() as u64,
+                ctx.accounts.config.max_price_impact_bps,
+            )?;
+        }
+
+        // Enforce the protocol's quote freshness policy and expiration
+        // Payments may require fresher quotes than swaps
+        ensure_quote_fresh(
+            &route,
+            ctx.accounts.config.payment_quote_ttl_seconds(),
+            ctx.accounts.config.quote_grace_seconds,
+            clock.unix_timestamp,
+        )?;
 
         // ============================================================
         // Step 4: Execute Jupiter swap via CPI
         // ============================================================
+        // The temp account is expected to be empty at entry; any residue from
+        // an earlier payment is excluded by the delta and left for sweeping
         let temp_usdc_balance_before = ctx.accounts.temp_usdc_account.amount;
+        if temp_usdc_balance_before > 0 {
+            msg!("Temp USDC account holds {} residue", temp_usdc_balance_before);
+        }
+        let input_balance_before = ctx.accounts.payer_input_account.amount;
 
-        let jupiter_accounts: Vec<AccountInfo<'info>> = remaining_accounts[1..].to_vec();
+        let jupiter_accounts: Vec<AccountInfo<'info>> =
+            remaining_accounts[first_swap_account..].to_vec();
+        check_swap_destination(&ctx.accounts.temp_usdc_account.key(), &jupiter_accounts)?;
         execute_jupiter_swap(
             &ctx.accounts.jupiter_program,
             &jupiter_accounts,
             &route,
             None,
-        )?;
+        )
+        .map_err(|e| payment_step_failed(PaymentStep::Swap, e))?;
 
         // Reload temp account to get updated balance
         ctx.accounts.temp_usdc_account.reload()?;
         let temp_usdc_balance_after = ctx.accounts.temp_usdc_account.amount;
-        actual_usdc_received = temp_usdc_balance_after
-            .checked_sub(temp_usdc_balance_before)
-            .ok_or(FlowMintError::MathOverflow)?;
+        let actual_usdc_received =
+            compute_temp_usdc_received(temp_usdc_balance_before, temp_usdc_balance_after)?;
 
         // Verify we received at least merchant + protocol fee
         require!(
@@ -300,68 +811,93 @@ pub fn pay_any_token_handler<'info>(
 
         // Get actual input amount used (for refund calculation)
         ctx.accounts.payer_input_account.reload()?;
-        actual_amount_in = amount_in
-            .checked_sub(ctx.accounts.payer_input_account.amount)
-            .unwrap_or(amount_in);
+        actual_amount_in = compute_amount_spent(
+            input_balance_before,
+            ctx.accounts.payer_input_account.amount,
+            amount_in,
+        )?;
+
+        // A route that burns the whole input for the minimum USDC is suspect
+        check_input_within_quote(
+            actual_amount_in,
+            route.in_amount,
+            ctx.accounts.config.default_slippage_bps,
+        )?;
 
         // ============================================================
-        // Step 5: Transfer exact USDC amount to merchant
+        // Step 5: Transfer protocol fee to FeeVault (if any)
         // ============================================================
         // Config PDA is the token authority for temp_usdc_account and fee_vault_usdc_account.
         let config_seeds = &[b"config".as_ref(), &[ctx.accounts.config.bump]];
         let signer_seeds = &[&config_seeds[..]];
 
-        // ============================================================
-        // Step 5: Transfer protocol fee to FeeVault (if any)
-        // ============================================================
         if protocol_fee > 0 {
-            let fee_ctx = CpiContext::new_with_signer(
-                ctx.accounts.token_program.to_account_info(),
-                Transfer {
-                    from: ctx.accounts.temp_usdc_account.to_account_info(),
-                    to: ctx.accounts.fee_vault_usdc_account.to_account_info(),
-                    authority: ctx.accounts.config.to_account_info(),
-                },
+            transfer_tokens(
+                &ctx.accounts.token_program.to_account_info(),
+                &ctx.accounts.temp_usdc_account.to_account_info(),
+                &ctx.accounts.usdc_mint.to_account_info(),
+                &ctx.accounts.fee_vault_usdc_account.to_account_info(),
+                &ctx.accounts.config.to_account_info(),
+                ctx.remaining_accounts,
+                protocol_fee,
+                ctx.accounts.usdc_mint.decimals,
                 signer_seeds,
-            );
-            token::transfer(fee_ctx, protocol_fee)?;
+            )
+            .map_err(|e| payment_step_failed(PaymentStep::FeeTransfer, e))?;
         }
 
-        let transfer_to_merchant_ctx = CpiContext::new_with_signer(
-            ctx.accounts.token_program.to_account_info(),
-            Transfer {
-                from: ctx.accounts.temp_usdc_account.to_account_info(),
-                to: ctx.accounts.merchant_usdc_account.to_account_info(),
-                authority: ctx.accounts.config.to_account_info(),
-            },
+        // ============================================================
+        // Step 6: Transfer exact USDC amount to merchant
+        // ============================================================
+        transfer_tokens(
+            &ctx.accounts.token_program.to_account_info(),
+            &ctx.accounts.temp_usdc_account.to_account_info(),
+            &ctx.accounts.usdc_mint.to_account_info(),
+            &merchant_usdc_info,
+            &ctx.accounts.config.to_account_info(),
+            ctx.remaining_accounts,
+            merchant_amount,
+            ctx.accounts.usdc_mint.decimals,
             signer_seeds,
-        );
-        token::transfer(transfer_to_merchant_ctx, exact_usdc_out)?;
+        )
+        .map_err(|e| payment_step_failed(PaymentStep::MerchantTransfer, e))?;
 
         // ============================================================
-        // Step 6: Refund excess USDC to payer (if any)
+        // Step 7: Refund excess USDC to payer or refund account (if any)
         // ============================================================
-        let excess_usdc = actual_usdc_received.saturating_sub(required_usdc_out);
+        let excess_usdc = compute_payment_refund(actual_usdc_received, required_usdc_out)?;
+        check_refund_plausible(
+            excess_usdc,
+            actual_usdc_received,
+            ctx.accounts.config.max_refund_bps,
+        )?;
         if excess_usdc > 0 {
-            let refund_ctx = CpiContext::new_with_signer(
-                ctx.accounts.token_program.to_account_info(),
-                Transfer {
-                    from: ctx.accounts.temp_usdc_account.to_account_info(),
-                    to: ctx.accounts.payer_usdc_account.to_account_info(),
-                    authority: ctx.accounts.config.to_account_info(),
-                },
+            let refund_account = match &ctx.accounts.refund_account {
+                Some(account) => account.to_account_info(),
+                None => ctx.accounts.payer_usdc_account.to_account_info(),
+            };
+            transfer_tokens(
+                &ctx.accounts.token_program.to_account_info(),
+                &ctx.accounts.temp_usdc_account.to_account_info(),
+                &ctx.accounts.usdc_mint.to_account_info(),
+                &refund_account,
+                &ctx.accounts.config.to_account_info(),
+                ctx.remaining_accounts,
+                excess_usdc,
+                ctx.accounts.usdc_mint.decimals,
                 signer_seeds,
-            );
-            token::transfer(refund_ctx, excess_usdc)?;
+            )
+            .map_err(|e| payment_step_failed(PaymentStep::Refund, e))?;
         }
     }
 
     // ============================================================
-    // Step 7: Process memo and create payment record
+    // Step 8: Process memo and create payment record
     // ============================================================
     let mut memo_bytes = [0u8; MAX_MEMO_LENGTH];
     let memo_len = if let Some(ref m) = memo {
         let bytes = m.as_bytes();
+        ctx.accounts.config.memo_policy.validate(bytes, MAX_MEMO_LENGTH)?;
         let len = bytes.len().min(MAX_MEMO_LENGTH);
         memo_bytes[..len].copy_from_slice(&bytes[..len]);
         len as u8
@@ -374,14 +910,23 @@ pub fn pay_any_token_handler<'info>(
     record.merchant = ctx.accounts.merchant.key();
     record.input_mint = ctx.accounts.input_mint.key();
     record.amount_in = actual_amount_in;
-    record.usdc_amount = exact_usdc_out;
+    record.usdc_amount = merchant_amount;
     record.memo = memo_bytes;
     record.memo_len = memo_len;
     record.timestamp = clock.unix_timestamp;
     record.bump = ctx.bumps.payment_record;
 
+    if let (Some(reference), Some(payment_reference)) =
+        (reference, ctx.accounts.payment_reference.as_mut())
+    {
+        payment_reference.merchant = ctx.accounts.merchant.key();
+        payment_reference.reference = reference;
+        payment_reference.payment_record = ctx.accounts.payment_record.key();
+        payment_reference.bump = ctx.bumps.payment_reference.unwrap_or_default();
+    }
+
     // ============================================================
-    // Step 8: Update user stats
+    // Step 9: Update user stats
     // ============================================================
     let payer_stats = &mut ctx.accounts.payer_stats;
     if payer_stats.user == Pubkey::default() {
@@ -391,14 +936,31 @@ pub fn pay_any_token_handler<'info>(
     payer_stats.total_payments = payer_stats.total_payments.saturating_add(1);
     payer_stats.last_activity = clock.unix_timestamp;
 
+    let volume_usd = normalize_usd_volume(exact_usdc_out, ctx.accounts.usdc_mint.decimals);
+    payer_stats.record_volume(volume_usd);
+
+    let merchant_stats = &mut ctx.accounts.merchant_stats;
+    if merchant_stats.merchant == Pubkey::default() {
+        merchant_stats.merchant = ctx.accounts.merchant.key();
+        merchant_stats.bump = ctx.bumps.merchant_stats;
+    }
+    merchant_stats.record_payment(merchant_amount, clock.unix_timestamp);
+    let merchant_total_payments = merchant_stats.total_payments;
+    let merchant_total_volume_usdc = merchant_stats.total_volume_usdc;
+
+    let config = &mut ctx.accounts.config;
+    config.total_volume_usd = config.total_volume_usd.saturating_add(volume_usd);
+    config.record_fee_collected(protocol_fee);
+    let sequence = config.next_sequence()?;
+
     // ============================================================
-    // Step 9: Emit event
+    // Step 10: Emit event
     // ============================================================
     msg!(
         "Payment executed: {} {} -> {} USDC to {}",
         actual_amount_in,
         ctx.accounts.input_mint.key(),
-        exact_usdc_out,
+        merchant_amount,
         ctx.accounts.merchant.key()
     );
 
@@ -407,9 +969,36 @@ pub fn pay_any_token_handler<'info>(
         merchant: ctx.accounts.merchant.key(),
         input_mint: ctx.accounts.input_mint.key(),
         amount_in: actual_amount_in,
-        usdc_amount: exact_usdc_out,
+        usdc_amount: merchant_amount,
         timestamp: clock.unix_timestamp,
         payment_record: ctx.accounts.payment_record.key(),
+        merchant_total_payments,
+        merchant_total_volume_usdc,
+        sequence,
+    });
+
+    Ok(())
+}
+
+/// Close a payment record with both parties' consent, refunding rent to the payer
+///
+/// Used to resolve disputes: the record is removed only when the payer and
+/// merchant both sign.
+pub fn reclaim_payment_record_handler(ctx: Context<ReclaimPaymentRecord>) -> Result<()> {
+    let sequence = ctx.accounts.config.next_sequence()?;
+
+    msg!(
+        "Payment record {} reclaimed by {} and {}",
+        ctx.accounts.payment_record.key(),
+        ctx.accounts.payer.key(),
+        ctx.accounts.merchant.key()
+    );
+
+    emit!(PaymentRecordReclaimed {
+        payment_record: ctx.accounts.payment_record.key(),
+        payer: ctx.accounts.payer.key(),
+        merchant: ctx.accounts.merchant.key(),
+        sequence,
     });
 
     Ok(())
@@ -432,4 +1021,228 @@ pub struct PaymentExecuted {
     pub timestamp: i64,
     /// Payment record account
     pub payment_record: Pubkey,
+    /// Merchant's total payments received, including this one
+    pub merchant_total_payments: u64,
+    /// Merchant's total settlement-mint amount received, including this one
+    pub merchant_total_volume_usdc: u64,
+    /// Protocol event sequence number
+    pub sequence: u64,
+}
+
+/// Event emitted when a payment record is jointly reclaimed
+#[event]
+pub struct PaymentRecordReclaimed {
+    /// Closed payment record
+    pub payment_record: Pubkey,
+    /// Payer (rent recipient)
+    pub payer: Pubkey,
+    /// Merchant co-signer
+    pub merchant: Pubkey,
+    /// Protocol event sequence number
+    pub sequence: u64,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::jupiter::AggregatorSource;
+
+    #[test]
+    fn test_temp_usdc_received() {
+        // Empty at entry
+        assert_eq!(compute_temp_usdc_received(0, 1_003_000).unwrap(), 1_003_000);
+
+        // Residue from an earlier payment is not attributed to this one
+        assert_eq!(compute_temp_usdc_received(2_500, 1_005_500).unwrap(), 1_003_000);
+
+        // Balance decreased: clear error rather than an underflow
+        assert_eq!(
+            compute_temp_usdc_received(2_500, 1_000).unwrap_err(),
+            FlowMintError::TempBalanceDecreased.into()
+        );
+    }
+
+    #[test]
+    fn test_payment_quote_ttl() {
+        use crate::jupiter::JupiterRoute;
+
+        let mut config = ProtocolConfig {
+            max_quote_ttl_seconds: 60,
+            ..Default::default()
+        };
+        let route = JupiterRoute {
+            input_mint: Pubkey::new_unique(),
+            output_mint: Pubkey::new_unique(),
+            in_amount: 1_000_000,
+            out_amount: 1_000_000,
+            slippage_bps: 50,
+            route_steps: vec![],
+            quote_timestamp: 1_000,
+            quote_expiration_seconds: 45,
+            source: AggregatorSource::Jupiter,
+            token_ledger: None,
+        };
+
+        // Unset: payments use the swap TTL
+        assert_eq!(config.payment_quote_ttl_seconds(), 60);
+        assert!(route.freshness_rejection(config.payment_quote_ttl_seconds(), 0, 1_010).is_none());
+
+        // Acceptable for a swap but too long-lived for a payment
+        config.payment_max_quote_ttl_seconds = 20;
+        assert!(route.freshness_rejection(config.max_quote_ttl_seconds, 0, 1_010).is_none());
+        assert!(route.freshness_rejection(config.payment_quote_ttl_seconds(), 0, 1_010).is_some());
+    }
+
+    #[test]
+    fn test_temp_usdc_account_check() {
+        let payer = Pubkey::new_unique();
+        let (temp, bump) =
+            Pubkey::find_program_address(&[b"temp_usdc", payer.as_ref()], &crate::ID);
+        assert!(check_temp_usdc_account(&temp, &payer, bump, &crate::ID).is_ok());
+
+        // Non-canonical account, or another payer's temp PDA
+        assert_eq!(
+            check_temp_usdc_account(&Pubkey::new_unique(), &payer, bump, &crate::ID).unwrap_err(),
+            FlowMintError::InvalidTempAccount.into()
+        );
+        assert!(check_temp_usdc_account(&temp, &Pubkey::new_unique(), bump, &crate::ID).is_err());
+
+        // Non-canonical bump
+        assert!(check_temp_usdc_account(&temp, &payer, bump.wrapping_sub(1), &crate::ID).is_err());
+    }
+
+    #[test]
+    fn test_refund_plausibility() {
+        // Small refund within a 5% cap
+        assert!(check_refund_plausible(7_000, 1_010_000, 500).is_ok());
+        assert!(check_refund_plausible(50_500, 1_010_000, 500).is_ok());
+
+        // Refund larger than the received amount allows
+        assert_eq!(
+            check_refund_plausible(50_501, 1_010_000, 500).unwrap_err(),
+            FlowMintError::RefundExceedsInput.into()
+        );
+        assert!(check_refund_plausible(900_000, 1_000_000, 500).is_err());
+
+        // Unlimited when unset
+        assert!(check_refund_plausible(900_000, 1_000_000, 0).is_ok());
+    }
+
+    #[test]
+    fn test_existing_or_created_account() {
+        let (existing, ata) = (Pubkey::new_unique(), Pubkey::new_unique());
+
+        // Existing account, or the ATA created for a merchant without one
+        assert_eq!(existing_or_created_account(Some(existing), None).unwrap(), existing);
+        assert_eq!(existing_or_created_account(None, Some(ata)).unwrap(), ata);
+
+        // Ambiguous or missing destination
+        assert!(existing_or_created_account(Some(existing), Some(ata)).is_err());
+        assert!(existing_or_created_account::<Pubkey>(None, None).is_err());
+    }
+
+    #[test]
+    fn test_canonical_ata_check() {
+        let (merchant, mint) = (Pubkey::new_unique(), Pubkey::new_unique());
+        let token_program = anchor_spl::token::ID;
+        let ata = get_associated_token_address_with_program_id(&merchant, &mint, &token_program);
+        let other = Pubkey::new_unique();
+
+        // Flag off: any account is accepted
+        assert!(check_canonical_ata(false, &other, &merchant, &mint, &token_program).is_ok());
+
+        // Flag on: only the canonical ATA is accepted
+        assert!(check_canonical_ata(true, &ata, &merchant, &mint, &token_program).is_ok());
+        assert!(check_canonical_ata(true, &other, &merchant, &mint, &token_program).is_err());
+        assert!(
+            check_canonical_ata(true, &ata, &merchant, &mint, &anchor_spl::token_2022::ID).is_err()
+        );
+    }
+
+    #[test]
+    fn test_refund_destination() {
+        let (payer, temp) = (Pubkey::new_unique(), Pubkey::new_unique());
+
+        // Defaults to the payer's USDC account
+        assert_eq!(refund_destination(payer, None, &temp).unwrap(), payer);
+
+        // Alternate sweep account
+        let sweep = Pubkey::new_unique();
+        assert_eq!(refund_destination(payer, Some(sweep), &temp).unwrap(), sweep);
+
+        // Refunding into the temp account is rejected
+        assert!(refund_destination(payer, Some(temp), &temp).is_err());
+    }
+
+    #[test]
+    fn test_direct_settlement_decimals() {
+        // e.g. USDT (6) settling as USDC (6)
+        assert!(check_direct_settlement_decimals(6, 6).is_ok());
+        assert!(check_direct_settlement_decimals(9, 6).is_err());
+    }
+
+    #[test]
+    fn test_merchant_account_receivable() {
+        assert!(check_merchant_account_receivable(AccountState::Initialized).is_ok());
+
+        let err = check_merchant_account_receivable(AccountState::Frozen).unwrap_err();
+        assert_eq!(err, FlowMintError::PaymentFailed.into());
+        assert!(check_merchant_account_receivable(AccountState::Uninitialized).is_err());
+    }
+
+    #[test]
+    fn test_payment_step_failure_maps_to_payment_failed() {
+        let err = payment_step_failed(
+            PaymentStep::MerchantTransfer,
+            ProgramError::InsufficientFunds.into(),
+        );
+        assert_eq!(err, FlowMintError::PaymentFailed.into());
+    }
+
+    #[test]
+    fn test_payment_refund() {
+        // Received exactly what was required: nothing to refund
+        assert_eq!(compute_payment_refund(1_003_000, 1_003_000).unwrap(), 0);
+
+        // Received more than required
+        assert_eq!(compute_payment_refund(1_010_000, 1_003_000).unwrap(), 7_000);
+
+        // Received less than required is an invariant violation
+        assert!(compute_payment_refund(1_002_999, 1_003_000).is_err());
+    }
+
+    #[test]
+    fn test_input_within_quote() {
+        // Exact and within 0.5% tolerance
+        assert!(check_input_within_quote(1_000_000, 1_000_000, 50).is_ok());
+        assert!(check_input_within_quote(1_005_000, 1_000_000, 50).is_ok());
+
+        // Overspend beyond tolerance
+        assert!(check_input_within_quote(1_005_001, 1_000_000, 50).is_err());
+        assert!(check_input_within_quote(2_000_000, 1_000_000, 50).is_err());
+    }
+
+    #[test]
+    fn test_compute_protocol_fee() {
+        assert_eq!(compute_protocol_fee(1_000_000, 0).unwrap(), 0);
+        assert_eq!(compute_protocol_fee(1_000_000, 30).unwrap(), 3_000);
+        // Rounds down
+        assert_eq!(compute_protocol_fee(999, 10).unwrap(), 0);
+        assert_eq!(compute_protocol_fee(u64::MAX, 10_000).unwrap(), u64::MAX);
+    }
+
+    #[test]
+    fn test_distinct_usdc_accounts() {
+        let payer = Pubkey::new_unique();
+        let merchant = Pubkey::new_unique();
+        let temp = Pubkey::new_unique();
+
+        assert!(ensure_distinct_usdc_accounts(&payer, &merchant, &temp).is_ok());
+
+        // Every duplicate permutation is rejected
+        assert!(ensure_distinct_usdc_accounts(&payer, &payer, &temp).is_err());
+        assert!(ensure_distinct_usdc_accounts(&payer, &merchant, &payer).is_err());
+        assert!(ensure_distinct_usdc_accounts(&payer, &merchant, &merchant).is_err());
+        assert!(ensure_distinct_usdc_accounts(&payer, &payer, &payer).is_err());
+    }
 }
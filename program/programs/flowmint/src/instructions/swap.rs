@@ -12,13 +12,24 @@
 //! 6. Record receipt on-chain
 
 use anchor_lang::prelude::*;
-use anchor_spl::token::{Token, TokenAccount};
+use anchor_spl::associated_token::AssociatedToken;
+use anchor_spl::token::{self, Mint, Token, TokenAccount};
+use anchor_spl::{token_2022, token_interface};
 
 use crate::errors::FlowMintError;
+use crate::instructions::payment::{compute_protocol_fee, existing_or_created_account, is_usdc_mint};
+use crate::instructions::validate_route::{RouteCheck, RouteRejection};
 use crate::jupiter::{
-    JupiterRoute, execute_jupiter_swap, deserialize_route, verify_swap_output
+    check_hop_token_programs, check_minimum_out_scale, check_route_rate_plausible,
+    check_swap_destination, cross_check_returned_out, JupiterRoute, ensure_quote_fresh, load_route,
+    execute_jupiter_swap, returned_out_amount, verify_swap_output,
 };
-use crate::state::{ProtocolConfig, SwapReceipt, UserStats};
+use crate::oracle::{deviation_bps, fair_amount_out, read_oracle_price, usd_value, OraclePrice};
+use crate::state::{
+    normalize_usd_volume, AmmAllowlist, FillStatus, PriceTwap, ProtocolConfig, SwapReceipt,
+    TokenVolume, UserStats, UNPRICED_TWAP_SAMPLE_NOTIONAL_USD,
+};
+use crate::token_transfer::transfer_tokens;
 
 /// Accounts for the ExecuteSwap instruction
 #[derive(Accounts)]
@@ -43,13 +54,13 @@ pub struct ExecuteSwap<'info> {
     )]
     pub user_input_account: Account<'info, TokenAccount>,
 
-    /// User's output token account
+    /// User's existing output token account; omit to use `user_output_ata`
     #[account(
         mut,
         constraint = user_output_account.owner == user.key() @ FlowMintError::InvalidOwner,
         constraint = user_output_account.mint == output_mint.key() @ FlowMintError::InvalidMint
     )]
-    pub user_output_account: Account<'info, TokenAccount>,
+    pub user_output_account: Option<Account<'info, TokenAccount>>,
 
     /// Input token mint
     /// CHECK: Validated by token account constraints
@@ -83,6 +94,75 @@ pub struct ExecuteSwap<'info> {
     )]
     pub user_stats: Account<'info, UserStats>,
 
+    /// Protocol FeeVault PDA for the output mint (required when a protocol fee is
+    /// charged, even before `init_fee_vault` has created it)
+    /// CHECK: Address checked by seeds; contents validated by `load_fee_vault`
+    #[account(
+        mut,
+        seeds = [b"fee_vault", output_mint.key().as_ref()],
+        bump,
+    )]
+    pub fee_vault: Option<UncheckedAccount<'info>>,
+
+    /// Referrer's output token account (required when a referrer is given)
+    #[account(
+        mut,
+        constraint = referrer_token_account.mint == output_mint.key() @ FlowMintError::InvalidMint
+    )]
+    pub referrer_token_account: Option<Account<'info, TokenAccount>>,
+
+    /// Pyth price feed for the input token (optional, used in protected mode)
+    /// CHECK: Owner and layout validated when read
+    pub input_price_feed: Option<UncheckedAccount<'info>>,
+
+    /// Pyth price feed for the output token (optional, used in protected mode)
+    /// CHECK: Owner and layout validated when read
+    pub output_price_feed: Option<UncheckedAccount<'info>>,
+
+    /// AMM allowlist (required while the allowlist is enabled)
+    #[account(seeds = [b"amm_allowlist"], bump = amm_allowlist.bump)]
+    pub amm_allowlist: Option<Account<'info, AmmAllowlist>>,
+
+    /// Short on-chain TWAP for the pair (required in protected mode)
+    #[account(
+        init_if_needed,
+        payer = user,
+        space = PriceTwap::SIZE,
+        seeds = [b"twap", input_mint.key().as_ref(), output_mint.key().as_ref()],
+        bump
+    )]
+    pub price_twap: Option<Account<'info, PriceTwap>>,
+
+    /// Cumulative volume of the input mint (optional, analytics only)
+    #[account(
+        init_if_needed,
+        payer = user,
+        space = TokenVolume::SIZE,
+        seeds = [b"token_volume", input_mint.key().as_ref()],
+        bump
+    )]
+    pub input_token_volume: Option<Account<'info, TokenVolume>>,
+
+    /// Cumulative volume of the output mint (optional, analytics only)
+    #[account(
+        init_if_needed,
+        payer = user,
+        space = TokenVolume::SIZE,
+        seeds = [b"token_volume", output_mint.key().as_ref()],
+        bump
+    )]
+    pub output_token_volume: Option<Account<'info, TokenVolume>>,
+
+    /// User's output ATA, created (user funds rent) if it doesn't exist yet;
+    /// used when `user_output_account` is omitted
+    #[account(
+        init_if_needed,
+        payer = user,
+        associated_token::mint = output_mint,
+        associated_token::authority = user
+    )]
+    pub user_output_ata: Option<Account<'info, TokenAccount>>,
+
     /// Jupiter program
     /// CHECK: Validated against known Jupiter program ID
     pub jupiter_program: AccountInfo<'info>,
@@ -92,6 +172,9 @@ pub struct ExecuteSwap<'info> {
 
     /// System program
     pub system_program: Program<'info, System>,
+
+    /// Associated token program (required with `user_output_ata`)
+    pub associated_token_program: Option<Program<'info, AssociatedToken>>,
 }
 
 /// Execute a token swap through Jupiter
@@ -103,41 +186,67 @@ pub struct ExecuteSwap<'info> {
 /// 3. Deserialize and validate Jupiter route from remaining accounts
 /// 4. Execute Jupiter CPI swap
 /// 5. Verify output meets minimum requirements
-/// 6. Record swap receipt
-/// 7. Update user stats and protocol stats
+/// 6. Collect the protocol fee (in the output mint) into its FeeVault
+/// 7. Record swap receipt
+/// 8. Update user stats and protocol stats
 ///
 /// # Arguments
 ///
 /// * `ctx` - ExecuteSwap context with all required accounts
 /// * `amount_in` - Amount of input tokens to swap
-/// * `minimum_amount_out` - Minimum acceptable output amount
+/// * `minimum_amount_out` - Minimum acceptable output amount; `0` derives it
+///   from the route (`JupiterRoute::effective_min_out`)
 /// * `slippage_bps` - Slippage tolerance in basis points
-/// * `protected_mode` - Use protected mode with stricter limits
+/// * `protected_mode` - Use protected mode with stricter limits (can only tighten)
+/// * `referrer` - Optional referrer receiving a share of the protocol fee
+/// * `route_bytes` - Serialized route; when `None` it is read from the first remaining account
+/// * `client_order_id` - Optional caller order ID stored on the receipt and event
+/// * `min_out_bps_of_fair` - Optional output floor as a share of the oracle fair
+///   value; enforced in protected mode and requires both price feeds
 ///
 /// # Returns
 ///
 /// * `Result<()>` - Success or error
+#[allow(clippy::too_many_arguments)]
 pub fn execute_swap_handler<'info>(
     ctx: Context<'_, '_, 'info, 'info, ExecuteSwap<'info>>,
     amount_in: u64,
     minimum_amount_out: u64,
     slippage_bps: u16,
     protected_mode: bool,
+    referrer: Option<Pubkey>,
+    route_bytes: Option<Vec<u8>>,
+    client_order_id: Option<[u8; 16]>,
+    min_out_bps_of_fair: Option<u16>,
 ) -> Result<()> {
+    let client_order_id = client_order_id.unwrap_or_default();
     let config = &mut ctx.accounts.config;
     let user = &ctx.accounts.user;
     let user_input_account = &ctx.accounts.user_input_account;
-    let user_output_account = &ctx.accounts.user_output_account;
+    let user_output_account = existing_or_created_account(
+        ctx.accounts.user_output_account.as_ref(),
+        ctx.accounts.user_output_ata.as_ref(),
+    )?;
     let clock = Clock::get()?;
+    log_compute_units("execute_swap: start");
+
+    require!(!config.swaps_paused, FlowMintError::SwapsPaused);
+
+    ctx.accounts
+        .user_stats
+        .reset_if_dormant(clock.unix_timestamp, config.stats_max_age_seconds);
 
     // ============================================================
-    // Step 1: Validate slippage against configuration
+    // Step 1: Resolve protected mode and the protocol fee
     // ============================================================
-    let effective_protected_mode = protected_mode || config.protected_mode_enabled;
-    require!(
-        config.validate_slippage(slippage_bps, effective_protected_mode),
-        FlowMintError::SlippageExceeded
-    );
+    let effective_protected_mode =
+        config.is_protected_required(protected_mode, ctx.accounts.user_stats.always_protected);
+    let fee_bps = swap_fee_bps(
+        config,
+        &ctx.accounts.user_stats,
+        ctx.accounts.fee_vault.as_ref().map(|vault| vault.to_account_info()).as_ref(),
+        &ctx.accounts.output_mint.key(),
+    )?;
 
     // ============================================================
     // Step 2: Check user has sufficient balance
@@ -147,118 +256,263 @@ pub fn execute_swap_handler<'info>(
         FlowMintError::InsufficientBalance
     );
 
+    // Swapping a token into itself is a no-op
+    require_keys_neq!(
+        ctx.accounts.input_mint.key(),
+        ctx.accounts.output_mint.key(),
+        FlowMintError::InvalidMint
+    );
+
     // Validate minimum amounts
     require!(amount_in > 0, FlowMintError::AmountTooSmall);
-    require!(minimum_amount_out > 0, FlowMintError::AmountTooSmall);
+
+    check_referrer(referrer, &user.key())?;
 
     // ============================================================
     // Step 3: Deserialize and validate Jupiter route
     // ============================================================
     let remaining_accounts = &ctx.remaining_accounts;
-    require!(!remaining_accounts.is_empty(), FlowMintError::InvalidInstructionData);
 
-    // First remaining account contains the route data
-    let route_account = &remaining_accounts[0];
-    let route_data = route_account.try_borrow_data()?;
-    
-    let route = deserialize_route(&route_data)?;
+    // Route comes from instruction data, or else the first remaining account
+    let (route, first_swap_account) = load_route(route_bytes.as_deref(), remaining_accounts)?;
 
-    // Validate route matches expected parameters
-    route.validate(
-        &ctx.accounts.input_mint.key(),
-        &ctx.accounts.output_mint.key(),
-        amount_in,
+    let input_decimals =
+        Mint::try_deserialize(&mut &ctx.accounts.input_mint.try_borrow_data()?[..])?.decimals;
+    let output_decimals =
+        Mint::try_deserialize(&mut &ctx.accounts.output_mint.try_borrow_data()?[..])?.decimals;
+    let ValidatedSwap {
         minimum_amount_out,
-        slippage_bps,
+        quoted_net_out,
+        fair_floor,
+        prices,
+    } = validate_swap(
+        config,
+        &route,
+        &SwapRequest {
+            input_mint: ctx.accounts.input_mint.key(),
+            output_mint: ctx.accounts.output_mint.key(),
+            amount_in,
+            minimum_amount_out,
+            slippage_bps,
+            protected_mode: effective_protected_mode,
+            output_fee_bps: fee_bps,
+            min_out_bps_of_fair,
+        },
+        &SwapMarket {
+            allowlist: ctx.accounts.amm_allowlist.as_deref(),
+            input_price_feed: ctx.accounts.input_price_feed.as_deref(),
+            output_price_feed: ctx.accounts.output_price_feed.as_deref(),
+            price_twap: ctx.accounts.price_twap.as_deref(),
+            remaining_accounts,
+            swap_accounts: &remaining_accounts[first_swap_account..],
+            input_decimals,
+            output_decimals,
+            current_timestamp: clock.unix_timestamp,
+        },
     )?;
 
-    // Check quote expiration
-    require!(
-        !route.is_expired(clock.unix_timestamp),
-        FlowMintError::QuoteExpired
-    );
-
-    // Validate price impact if in protected mode
-    if effective_protected_mode {
-        let price_impact_bps = calculate_price_impact(&route);
-        require!(
-            price_impact_bps <= config.max_price_impact_bps,
-            FlowMintError::PriceImpactTooHigh
-        );
-    }
-
     // ============================================================
-    // Step 4: Record output balance before swap
+    // Step 4: Record input and output balances before swap
     // ============================================================
+    let input_balance_before = user_input_account.amount;
     let output_balance_before = user_output_account.amount;
 
     // ============================================================
     // Step 5: Execute Jupiter CPI swap
     // ============================================================
-    let jupiter_accounts: Vec<AccountInfo<'info>> = remaining_accounts[1..].to_vec();
+    let jupiter_accounts: Vec<AccountInfo<'info>> =
+        remaining_accounts[first_swap_account..].to_vec();
+    check_swap_destination(&user_output_account.key(), &jupiter_accounts)?;
 
+    log_compute_units("execute_swap: before Jupiter CPI");
     let _actual_output = execute_jupiter_swap(
         &ctx.accounts.jupiter_program,
         &jupiter_accounts,
         &route,
         None, // User signs directly, no PDA signer needed
     )?;
+    log_compute_units("execute_swap: after Jupiter CPI");
 
     // ============================================================
-    // Step 6: Verify output meets minimum requirements
+    // Step 6: Measure the input consumed (routes may partially fill)
+    //         and verify output meets minimum requirements
     // ============================================================
-    ctx.accounts.user_output_account.reload()?;
-    let output_balance_after = ctx.accounts.user_output_account.amount;
+    ctx.accounts.user_input_account.reload()?;
+    let actual_amount_in = compute_amount_spent(
+        input_balance_before,
+        ctx.accounts.user_input_account.amount,
+        amount_in,
+    )?;
+    if actual_amount_in < amount_in {
+        msg!("Partial fill: consumed {} of {} input", actual_amount_in, amount_in);
+    }
+
+    let user_output_account = existing_or_created_account(
+        ctx.accounts.user_output_account.as_mut(),
+        ctx.accounts.user_output_ata.as_mut(),
+    )?;
+    user_output_account.reload()?;
+    let output_balance_after = user_output_account.amount;
+    let user_output_info = user_output_account.to_account_info();
     let actual_amount_out = output_balance_after
         .checked_sub(output_balance_before)
         .ok_or(FlowMintError::MathOverflow)?;
 
+    // Cross-check against the output amount Jupiter returns, when present
+    if let Some(returned_out) = returned_out_amount(&ctx.accounts.jupiter_program.key()) {
+        cross_check_returned_out(returned_out, actual_amount_out)?;
+    }
+
+    let protocol_fee = compute_protocol_fee(actual_amount_out, fee_bps)?;
+    let net_amount_out = actual_amount_out - protocol_fee;
     verify_swap_output(
-        actual_amount_out,
+        net_amount_out,
         minimum_amount_out,
         slippage_bps,
-        route.out_amount,
+        quoted_net_out,
+        config.max_positive_slippage_bps,
     )?;
+    if let Some(floor) = fair_floor {
+        check_fair_value_floor(net_amount_out, floor)?;
+    }
+
+    // Fold the realized price into the pair's TWAP, weighted by notional. Swaps
+    // that can't be valued get a reduced weight; the update interval and the
+    // per-sample move cap bound how fast any run of swaps can steer it.
+    if let Some(price_twap) = ctx.accounts.price_twap.as_mut() {
+        if price_twap.last_update == 0 {
+            price_twap.input_mint = ctx.accounts.input_mint.key();
+            price_twap.output_mint = ctx.accounts.output_mint.key();
+            price_twap.bump = ctx.bumps.price_twap.unwrap_or_default();
+        }
+        let notional_usd = swap_notional_usd(
+            &ctx.accounts.input_mint.key(),
+            actual_amount_in,
+            input_decimals,
+            &ctx.accounts.output_mint.key(),
+            actual_amount_out,
+            output_decimals,
+            prices.as_ref().map(|(input_price, _)| input_price),
+        )
+        .unwrap_or(UNPRICED_TWAP_SAMPLE_NOTIONAL_USD);
+        if let Some(price) = PriceTwap::implied_price(actual_amount_in, actual_amount_out) {
+            price_twap.record_price(price, notional_usd, clock.unix_timestamp);
+        }
+    }
 
     // ============================================================
-    // Step 7: Record swap receipt
+    // Step 7: Collect protocol fee into the output mint's FeeVault
+    // ============================================================
+    let (treasury_fee, referrer_fee) = match referrer {
+        Some(_) => config.split_referral_fee(protocol_fee)?,
+        None => (protocol_fee, 0),
+    };
+
+    if referrer_fee > 0 {
+        let referrer_token_account = ctx
+            .accounts
+            .referrer_token_account
+            .as_ref()
+            .ok_or(FlowMintError::InvalidInstructionData)?;
+        require_keys_eq!(
+            referrer_token_account.owner,
+            referrer.unwrap_or_default(),
+            FlowMintError::InvalidOwner
+        );
+
+        transfer_tokens(
+            &ctx.accounts.token_program.to_account_info(),
+            &user_output_info,
+            &ctx.accounts.output_mint,
+            &referrer_token_account.to_account_info(),
+            &user.to_account_info(),
+            ctx.remaining_accounts,
+            referrer_fee,
+            output_decimals,
+            &[],
+        )?;
+    }
+
+    if treasury_fee > 0 {
+        let fee_vault = ctx
+            .accounts
+            .fee_vault
+            .as_ref()
+            .ok_or(FlowMintError::MissingFeeVault)?;
+
+        transfer_tokens(
+            &ctx.accounts.token_program.to_account_info(),
+            &user_output_info,
+            &ctx.accounts.output_mint,
+            &fee_vault.to_account_info(),
+            &user.to_account_info(),
+            ctx.remaining_accounts,
+            treasury_fee,
+            output_decimals,
+            &[],
+        )?;
+    }
+
+    // ============================================================
+    // Step 8: Record swap receipt
     // ============================================================
     let receipt = &mut ctx.accounts.receipt;
     receipt.user = user.key();
     receipt.input_mint = ctx.accounts.input_mint.key();
     receipt.output_mint = ctx.accounts.output_mint.key();
-    receipt.amount_in = amount_in;
-    receipt.amount_out = actual_amount_out;
+    receipt.amount_in = actual_amount_in;
+    receipt.amount_out = net_amount_out;
     receipt.slippage_bps = slippage_bps;
     receipt.protected_mode = effective_protected_mode;
     receipt.timestamp = clock.unix_timestamp;
     receipt.tx_signature = [0u8; 32];
+    receipt.referrer = referrer.unwrap_or_default();
+    receipt.client_order_id = client_order_id;
+    receipt.execution_price = SwapReceipt::execution_price(
+        actual_amount_in,
+        input_decimals,
+        net_amount_out,
+        output_decimals,
+    );
+    receipt.status = FillStatus::from_output(net_amount_out, quoted_net_out, minimum_amount_out);
     receipt.bump = ctx.bumps.receipt;
 
     // ============================================================
-    // Step 8: Update user stats
+    // Step 9: Update user stats
     // ============================================================
     let user_stats = &mut ctx.accounts.user_stats;
     if user_stats.user == Pubkey::default() {
         user_stats.user = user.key();
         user_stats.bump = ctx.bumps.user_stats;
     }
-    user_stats.total_swaps = user_stats.total_swaps.saturating_add(1);
-    user_stats.last_activity = clock.unix_timestamp;
+    let user_receipt_index = user_stats.record_swap(clock.unix_timestamp);
+    let volume_usd = swap_volume_usd(
+        &ctx.accounts.input_mint.key(),
+        actual_amount_in,
+        input_decimals,
+        &ctx.accounts.output_mint.key(),
+        actual_amount_out,
+        output_decimals,
+        prices.map(|(_, output_price)| output_price).as_ref(),
+    );
+    user_stats.record_volume(volume_usd);
 
     // ============================================================
-    // Step 9: Update protocol stats
+    // Step 10: Update protocol stats
     // ============================================================
     config.total_swaps = config.total_swaps.saturating_add(1);
+    config.total_volume_usd = config.total_volume_usd.saturating_add(volume_usd);
+    config.record_fee_collected(treasury_fee);
+    let sequence = config.next_sequence()?;
 
     // ============================================================
-    // Step 10: Emit event for off-chain indexing
+    // Step 11: Emit event for off-chain indexing
     // ============================================================
     msg!(
         "Swap executed: {} {} -> {} {} (slippage: {} bps, protected: {})",
-        amount_in,
+        actual_amount_in,
         ctx.accounts.input_mint.key(),
-        actual_amount_out,
+        net_amount_out,
         ctx.accounts.output_mint.key(),
         slippage_bps,
         effective_protected_mode
@@ -268,31 +522,568 @@ pub fn execute_swap_handler<'info>(
         user: user.key(),
         input_mint: ctx.accounts.input_mint.key(),
         output_mint: ctx.accounts.output_mint.key(),
-        amount_in,
-        amount_out: actual_amount_out,
+        amount_in: actual_amount_in,
+        amount_out: net_amount_out,
+        protocol_fee,
+        referrer: referrer.unwrap_or_default(),
+        referrer_fee,
         slippage_bps,
         protected_mode: effective_protected_mode,
         timestamp: clock.unix_timestamp,
         receipt: ctx.accounts.receipt.key(),
+        client_order_id,
+        execution_price: ctx.accounts.receipt.execution_price,
+        sequence,
     });
 
+    emit!(ReceiptCreated {
+        user: user.key(),
+        receipt: ctx.accounts.receipt.key(),
+        user_receipt_index,
+        sequence,
+    });
+
+    // ============================================================
+    // Step 12: Per-token analytics, when the volume accounts are passed
+    // ============================================================
+    let token_volumes = [
+        (
+            ctx.accounts.input_token_volume.as_mut(),
+            ctx.bumps.input_token_volume,
+            ctx.accounts.input_mint.key(),
+            actual_amount_in,
+            0,
+        ),
+        (
+            ctx.accounts.output_token_volume.as_mut(),
+            ctx.bumps.output_token_volume,
+            ctx.accounts.output_mint.key(),
+            0,
+            actual_amount_out,
+        ),
+    ];
+    for (token_volume, bump, mint, amount_in, amount_out) in token_volumes {
+        let Some(token_volume) = token_volume else {
+            continue;
+        };
+        if token_volume.mint == Pubkey::default() {
+            token_volume.mint = mint;
+            token_volume.bump = bump.unwrap_or_default();
+        }
+        token_volume.record_swap(amount_in, amount_out, clock.unix_timestamp);
+
+        emit!(TokenVolumeUpdated {
+            mint,
+            volume_in: token_volume.volume_in,
+            volume_out: token_volume.volume_out,
+            swap_count: token_volume.swap_count,
+            sequence,
+        });
+    }
+
+    log_compute_units("execute_swap: end");
+
     Ok(())
 }
 
-/// Calculate price impact from route
-fn calculate_price_impact(route: &JupiterRoute) -> u16 {
-    if route.in_amount == 0 || route.out_amount == 0 {
-        return 0;
+/// Compute the input actually consumed by a swap
+///
+/// Jupiter may fill less than requested on fragmented liquidity; the unspent
+/// input simply stays in the user's account. Consuming more than the
+/// authorized `amount_in` is rejected.
+pub fn compute_amount_spent(balance_before: u64, balance_after: u64, amount_in: u64) -> Result<u64> {
+    let spent = balance_before
+        .checked_sub(balance_after)
+        .ok_or(FlowMintError::MathOverflow)?;
+    require!(spent <= amount_in, FlowMintError::InsufficientBalance);
+    Ok(spent)
+}
+
+/// Log the remaining compute budget (only with the `compute-logging` feature)
+#[inline(always)]
+fn log_compute_units(_label: &str) {
+    #[cfg(feature = "compute-logging")]
+    {
+        msg!(_label);
+        anchor_lang::solana_program::log::sol_log_compute_units();
     }
+}
 
-    let total_fee: u64 = route.route_steps.iter().map(|s| s.fee_amount).sum();
-    let impact_bps = if route.in_amount > 0 {
-        (total_fee * 10000 / route.in_amount) as u16
+/// Swap parameters checked before the Jupiter CPI
+pub struct SwapRequest {
+    /// Input token mint
+    pub input_mint: Pubkey,
+    /// Output token mint
+    pub output_mint: Pubkey,
+    /// Amount of input tokens to swap
+    pub amount_in: u64,
+    /// Minimum output net of the protocol fee; `0` derives it from the route
+    pub minimum_amount_out: u64,
+    /// Slippage tolerance in basis points
+    pub slippage_bps: u16,
+    /// Whether the swap runs in protected mode (see `is_protected_required`)
+    pub protected_mode: bool,
+    /// Protocol fee taken from the output, in basis points
+    pub output_fee_bps: u16,
+    /// Optional output floor as a share of the oracle fair value
+    pub min_out_bps_of_fair: Option<u16>,
+}
+
+/// Accounts and market data read by the pre-CPI swap checks
+pub struct SwapMarket<'a, 'info> {
+    /// AMM allowlist, if passed
+    pub allowlist: Option<&'a AmmAllowlist>,
+    /// Pyth price feed for the input token, if passed
+    pub input_price_feed: Option<&'a AccountInfo<'info>>,
+    /// Pyth price feed for the output token, if passed
+    pub output_price_feed: Option<&'a AccountInfo<'info>>,
+    /// On-chain TWAP for the pair, if passed (required in protected mode)
+    pub price_twap: Option<&'a PriceTwap>,
+    /// All remaining accounts of the instruction
+    pub remaining_accounts: &'a [AccountInfo<'info>],
+    /// Remaining accounts forwarded to Jupiter
+    pub swap_accounts: &'a [AccountInfo<'info>],
+    /// Input mint decimals
+    pub input_decimals: u8,
+    /// Output mint decimals
+    pub output_decimals: u8,
+    /// Current Unix timestamp
+    pub current_timestamp: i64,
+}
+
+/// Output bounds established by `validate_swap`
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ValidatedSwap {
+    /// Minimum net output, with the `0` sentinel resolved
+    pub minimum_amount_out: u64,
+    /// Route's quoted output net of the protocol fee
+    pub quoted_net_out: u64,
+    /// Oracle fair-value floor on the net output, when requested
+    pub fair_floor: Option<u64>,
+    /// Verified `(input, output)` oracle prices, when read in protected mode
+    pub prices: Option<(OraclePrice, OraclePrice)>,
+}
+
+/// Run every check a route must pass before a swap CPI
+///
+/// Shared by `execute_swap`, `swap_exact_out` and the `validate_route` dry
+/// run so all three enforce the same slippage, route, freshness, allowlist,
+/// scale and plausibility checks, plus the hop cap and fee, oracle and TWAP
+/// impact bands in protected mode. A failure reports the check it came from
+/// alongside the error the swap fails with.
+pub fn validate_swap(
+    config: &ProtocolConfig,
+    route: &JupiterRoute,
+    request: &SwapRequest,
+    market: &SwapMarket,
+) -> std::result::Result<ValidatedSwap, RouteRejection> {
+    RouteRejection::ensure(
+        config.validate_slippage(request.slippage_bps, request.protected_mode),
+        RouteCheck::Slippage,
+        FlowMintError::SlippageExceeded,
+    )?;
+    config
+        .check_remaining_accounts(market.remaining_accounts.len())
+        .map_err(RouteRejection::of(RouteCheck::RemainingAccounts))?;
+
+    // A zero minimum is a sentinel: derive the floor from the route's quote
+    let minimum_amount_out =
+        resolve_minimum_amount_out(request.minimum_amount_out, route, request.output_fee_bps)
+            .map_err(RouteRejection::of(RouteCheck::MinimumAmountOut))?;
+    // Minimums, slippage and the fair floor all apply to what the user keeps
+    let quoted_net_out = net_of_fee(route.out_amount, request.output_fee_bps)
+        .map_err(RouteRejection::of(RouteCheck::RouteParameters))?;
+
+    route
+        .validate(
+            &request.input_mint,
+            &request.output_mint,
+            request.amount_in,
+            minimum_amount_out,
+            request.slippage_bps,
+        )
+        .map_err(RouteRejection::of(RouteCheck::RouteParameters))?;
+
+    // Enforce the protocol's quote freshness policy and expiration
+    ensure_quote_fresh(
+        route,
+        config.max_quote_ttl_seconds,
+        config.quote_grace_seconds,
+        market.current_timestamp,
+    )
+    .map_err(RouteRejection::of(RouteCheck::QuoteFreshness))?;
+
+    enforce_amm_allowlist(config, market.allowlist, route)
+        .map_err(RouteRejection::of(RouteCheck::AmmAllowlist))?;
+    check_hop_token_programs(route, market.swap_accounts)
+        .map_err(RouteRejection::of(RouteCheck::HopTokenPrograms))?;
+
+    // Catch minimum outputs expressed in the wrong mint's decimals
+    check_minimum_out_scale(
+        minimum_amount_out,
+        route.out_amount,
+        market.input_decimals,
+        market.output_decimals,
+    )
+    .map_err(RouteRejection::of(RouteCheck::MinimumOutScale))?;
+    check_route_rate_plausible(
+        route.in_amount,
+        route.out_amount,
+        market.input_decimals,
+        market.output_decimals,
+        config.max_route_rate_ratio,
+    )
+    .map_err(RouteRejection::of(RouteCheck::RouteRate))?;
+
+    let mut fair_floor = None;
+    let mut prices = None;
+    if request.protected_mode {
+        RouteRejection::ensure(
+            config.is_hop_count_allowed(route.route_steps.len(), true),
+            RouteCheck::TooManyHops,
+            FlowMintError::TooManyHops,
+        )?;
+
+        ensure_impact_within_limit(
+            ImpactSource::RouteFees,
+            route.price_impact_bps() as u64,
+            config.max_price_impact_bps,
+        )
+        .map_err(RouteRejection::of(RouteCheck::PriceImpact))?;
+
+        // Compare the route's implied price against the oracle, if provided
+        prices = read_pair_prices(
+            config,
+            market.input_price_feed,
+            market.output_price_feed,
+            &request.input_mint,
+            &request.output_mint,
+            market.current_timestamp,
+        )
+        .map_err(RouteRejection::of(RouteCheck::Oracle))?;
+        match prices {
+            Some((input_price, output_price)) => {
+                let fair_out = fair_amount_out(
+                    route.in_amount,
+                    &input_price,
+                    &output_price,
+                    market.input_decimals,
+                    market.output_decimals,
+                )
+                .and_then(|fair_out| {
+                    ensure_impact_within_limit(
+                        ImpactSource::Oracle,
+                        deviation_bps(route.out_amount, fair_out)?,
+                        config.max_price_impact_bps,
+                    )?;
+                    Ok(fair_out)
+                })
+                .map_err(RouteRejection::of(RouteCheck::Oracle))?;
+
+                if let Some(bps) = request.min_out_bps_of_fair {
+                    let floor = fair_value_floor(fair_out, bps)
+                        .and_then(|floor| {
+                            check_fair_value_floor(quoted_net_out, floor)?;
+                            Ok(floor)
+                        })
+                        .map_err(RouteRejection::of(RouteCheck::FairValueFloor))?;
+                    fair_floor = Some(floor);
+                }
+            }
+            // A fair-value floor can't be enforced without an oracle
+            None => RouteRejection::ensure(
+                request.min_out_bps_of_fair.is_none(),
+                RouteCheck::FairValueFloor,
+                FlowMintError::InvalidOracle,
+            )?,
+        }
+
+        // Compare against the on-chain TWAP, once it has samples. The account
+        // is required so a caller can't skip the check by omitting it.
+        let price_twap = market.price_twap.ok_or_else(|| RouteRejection {
+            check: RouteCheck::Twap,
+            error: FlowMintError::MissingPriceTwap.into(),
+        })?;
+        if let Some(twap_out) = price_twap.fair_amount_out(route.in_amount) {
+            deviation_bps(route.out_amount, twap_out)
+                .and_then(|deviation| {
+                    ensure_impact_within_limit(
+                        ImpactSource::Twap,
+                        deviation,
+                        config.max_price_impact_bps,
+                    )
+                })
+                .map_err(RouteRejection::of(RouteCheck::Twap))?;
+        }
+    }
+
+    // Hold large trades to the slippage tiers once their notional is known
+    let notional_usd = swap_notional_usd(
+        &request.input_mint,
+        request.amount_in,
+        market.input_decimals,
+        &request.output_mint,
+        route.out_amount,
+        market.output_decimals,
+        prices.as_ref().map(|(input_price, _)| input_price),
+    );
+    RouteRejection::ensure(
+        config.validate_slippage_for_notional(
+            request.slippage_bps,
+            request.protected_mode,
+            notional_usd,
+        ),
+        RouteCheck::Slippage,
+        FlowMintError::SlippageExceeded,
+    )?;
+
+    Ok(ValidatedSwap {
+        minimum_amount_out,
+        quoted_net_out,
+        fair_floor,
+        prices,
+    })
+}
+
+/// Reject a referrer that is the swapping user
+///
+/// Self-referral would hand the user a rebate on their own protocol fee.
+pub fn check_referrer(referrer: Option<Pubkey>, user: &Pubkey) -> Result<()> {
+    if let Some(referrer) = referrer {
+        require_keys_neq!(referrer, *user, FlowMintError::SelfReferral);
+    }
+    Ok(())
+}
+
+/// Minimum output to enforce for a swap, net of the protocol fee
+///
+/// `0` is a sentinel asking the program to derive the floor from the route's
+/// quoted output and slippage, less the `fee_bps` protocol fee. Either way the
+/// result must be non-zero.
+pub fn resolve_minimum_amount_out(
+    requested: u64,
+    route: &JupiterRoute,
+    fee_bps: u16,
+) -> Result<u64> {
+    let minimum_amount_out = if requested == 0 {
+        net_of_fee(route.effective_min_out(), fee_bps)?
     } else {
-        0
+        requested
     };
+    require!(minimum_amount_out > 0, FlowMintError::AmountTooSmall);
+    Ok(minimum_amount_out)
+}
+
+/// Output left to the user once a `fee_bps` protocol fee is taken from `amount`
+pub fn net_of_fee(amount: u64, fee_bps: u16) -> Result<u64> {
+    Ok(amount - compute_protocol_fee(amount, fee_bps)?)
+}
+
+/// Protocol fee rate charged on a swap's output
+///
+/// Output mints without a FeeVault yet (see `init_fee_vault`) are swapped
+/// fee-free instead of failing. The vault PDA must still be passed whenever a
+/// fee applies, so omitting it can't be used to skip the fee.
+pub fn swap_fee_bps(
+    config: &Account<ProtocolConfig>,
+    user_stats: &UserStats,
+    fee_vault: Option<&AccountInfo>,
+    output_mint: &Pubkey,
+) -> Result<u16> {
+    let fee_bps = config.fee_bps_for_user(user_stats);
+    if fee_bps == 0 {
+        return Ok(0);
+    }
+
+    let fee_vault = fee_vault.ok_or(FlowMintError::MissingFeeVault)?;
+    if !load_fee_vault(fee_vault, output_mint, &config.key())? {
+        msg!("No fee vault for mint {}; swapping without a protocol fee", output_mint);
+        return Ok(0);
+    }
+    Ok(fee_bps)
+}
+
+/// Check a FeeVault for `mint`, returning `false` if it hasn't been created
+///
+/// A created vault must be an SPL Token or Token-2022 account holding `mint`
+/// with the config PDA as its owner.
+pub fn load_fee_vault(fee_vault: &AccountInfo, mint: &Pubkey, config: &Pubkey) -> Result<bool> {
+    if fee_vault.data_is_empty() {
+        return Ok(false);
+    }
+
+    require!(
+        *fee_vault.owner == token::ID || *fee_vault.owner == token_2022::ID,
+        FlowMintError::InvalidOwner
+    );
+    let vault =
+        token_interface::TokenAccount::try_deserialize(&mut &fee_vault.try_borrow_data()?[..])?;
+    require_keys_eq!(vault.mint, *mint, FlowMintError::InvalidMint);
+    require_keys_eq!(vault.owner, *config, FlowMintError::InvalidOwner);
+    Ok(true)
+}
 
-    impact_bps
+/// Source of a protected-mode impact estimate
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ImpactSource {
+    /// Fee-derived heuristic from the route steps (`simulate_price_impact`)
+    RouteFees,
+    /// Deviation of the quoted output from the oracle fair price
+    Oracle,
+    /// Deviation of the quoted output from the on-chain TWAP
+    Twap,
+}
+
+/// Log line reporting an impact rejection with the computed value and limit
+pub fn impact_rejection_message(source: ImpactSource, impact_bps: u64, limit_bps: u16) -> String {
+    let label = match source {
+        ImpactSource::RouteFees => "Fee",
+        ImpactSource::Oracle => "Oracle price",
+        ImpactSource::Twap => "TWAP price",
+    };
+    format!("{} impact {} bps exceeds limit {} bps", label, impact_bps, limit_bps)
+}
+
+/// Ensure a protected-mode impact estimate is within `limit_bps`
+///
+/// Logs the computed impact and the limit before failing, with
+/// `FeeImpactTooHigh` for the fee heuristic and `PriceImpactTooHigh` for
+/// price-based signals, so clients can tell the two apart.
+pub fn ensure_impact_within_limit(
+    source: ImpactSource,
+    impact_bps: u64,
+    limit_bps: u16,
+) -> Result<()> {
+    if impact_bps <= limit_bps as u64 {
+        return Ok(());
+    }
+
+    msg!("{}", impact_rejection_message(source, impact_bps, limit_bps));
+    match source {
+        ImpactSource::RouteFees => Err(FlowMintError::FeeImpactTooHigh.into()),
+        ImpactSource::Oracle | ImpactSource::Twap => Err(FlowMintError::PriceImpactTooHigh.into()),
+    }
+}
+
+/// Read both legs' oracle prices, each checked against its mint's registered feed
+///
+/// Called for protected swaps. Feeds may only be omitted when the pair can't
+/// be priced (a mint has no registered feed ID) and protected mode isn't
+/// enforced protocol-wide; otherwise the swap must be priced against the
+/// oracle. Passing only one feed, or a feed for a mint without a registered
+/// feed ID, is rejected.
+pub fn read_pair_prices(
+    config: &ProtocolConfig,
+    input_feed: Option<&AccountInfo>,
+    output_feed: Option<&AccountInfo>,
+    input_mint: &Pubkey,
+    output_mint: &Pubkey,
+    current_timestamp: i64,
+) -> Result<Option<(OraclePrice, OraclePrice)>> {
+    match (input_feed, output_feed) {
+        (Some(input_feed), Some(output_feed)) => {
+            let input_feed_id = config
+                .oracle_feed_id(input_mint)
+                .ok_or(FlowMintError::InvalidOracle)?;
+            let output_feed_id = config
+                .oracle_feed_id(output_mint)
+                .ok_or(FlowMintError::InvalidOracle)?;
+            Ok(Some((
+                read_oracle_price(input_feed, &input_feed_id, current_timestamp)?,
+                read_oracle_price(output_feed, &output_feed_id, current_timestamp)?,
+            )))
+        }
+        (None, None) => {
+            require!(!config.protected_mode_enabled, FlowMintError::InvalidOracle);
+            let pair_priced = config.oracle_feed_id(input_mint).is_some()
+                && config.oracle_feed_id(output_mint).is_some();
+            require!(!pair_priced, FlowMintError::InvalidOracle);
+            Ok(None)
+        }
+        _ => Err(FlowMintError::InvalidOracle.into()),
+    }
+}
+
+/// Minimum output for `min_out_bps_of_fair` basis points of the oracle fair value
+pub fn fair_value_floor(fair_out: u64, min_out_bps_of_fair: u16) -> Result<u64> {
+    require!(
+        min_out_bps_of_fair <= 10_000,
+        FlowMintError::InvalidInstructionData
+    );
+    let floor = (fair_out as u128)
+        .checked_mul(min_out_bps_of_fair as u128)
+        .ok_or(FlowMintError::MathOverflow)?
+        / 10_000;
+    u64::try_from(floor).map_err(|_| FlowMintError::MathOverflow.into())
+}
+
+/// Ensure an output amount meets the caller's fair-value floor
+pub fn check_fair_value_floor(amount_out: u64, floor: u64) -> Result<()> {
+    if amount_out < floor {
+        msg!("Output {} is below the fair-value floor {}", amount_out, floor);
+        return Err(FlowMintError::InsufficientOutputAmount.into());
+    }
+    Ok(())
+}
+
+/// Reject routes touching non-allowlisted AMMs while the allowlist is enabled
+pub fn enforce_amm_allowlist(
+    config: &ProtocolConfig,
+    allowlist: Option<&AmmAllowlist>,
+    route: &JupiterRoute,
+) -> Result<()> {
+    if !config.amm_allowlist_enabled {
+        return Ok(());
+    }
+
+    let allowlist = allowlist.ok_or(FlowMintError::MissingAmmAllowlist)?;
+    route.validate_amms(|program_id| allowlist.is_allowed(program_id))
+}
+
+/// USD notional of a swap's input on the 1e6 scale, if it can be verified
+///
+/// Uses the USDC leg when present, otherwise the input's verified oracle
+/// price. Returns `None` when neither is available.
+pub fn swap_notional_usd(
+    input_mint: &Pubkey,
+    amount_in: u64,
+    input_decimals: u8,
+    output_mint: &Pubkey,
+    quoted_out: u64,
+    output_decimals: u8,
+    input_price: Option<&OraclePrice>,
+) -> Option<u64> {
+    if is_usdc_mint(input_mint) {
+        Some(normalize_usd_volume(amount_in, input_decimals))
+    } else if is_usdc_mint(output_mint) {
+        Some(normalize_usd_volume(quoted_out, output_decimals))
+    } else {
+        input_price.map(|price| usd_value(amount_in, price, input_decimals))
+    }
+}
+
+/// USD volume of a swap on the 1e6 scale
+///
+/// Uses the USDC leg when present. Swaps between two non-USDC tokens are
+/// valued at the output's verified oracle price when one was read (protected
+/// mode with price feeds), and record no volume otherwise, so fee tiers
+/// can't be inflated with unverifiable valuations.
+pub fn swap_volume_usd(
+    input_mint: &Pubkey,
+    amount_in: u64,
+    input_decimals: u8,
+    output_mint: &Pubkey,
+    amount_out: u64,
+    output_decimals: u8,
+    output_price: Option<&OraclePrice>,
+) -> u64 {
+    if is_usdc_mint(output_mint) {
+        normalize_usd_volume(amount_out, output_decimals)
+    } else if is_usdc_mint(input_mint) {
+        normalize_usd_volume(amount_in, input_decimals)
+    } else {
+        output_price.map_or(0, |price| usd_value(amount_out, price, output_decimals))
+    }
 }
 
 /// Event emitted when a swap is executed
@@ -304,10 +1095,16 @@ pub struct SwapExecuted {
     pub input_mint: Pubkey,
     /// Output token mint
     pub output_mint: Pubkey,
-    /// Amount of input tokens
+    /// Amount of input tokens actually consumed
     pub amount_in: u64,
-    /// Amount of output tokens received
+    /// Amount of output tokens received, net of the protocol fee
     pub amount_out: u64,
+    /// Protocol fee collected from the output (in the output mint)
+    pub protocol_fee: u64,
+    /// Referrer credited for the swap (default pubkey if none)
+    pub referrer: Pubkey,
+    /// Portion of the protocol fee paid to the referrer
+    pub referrer_fee: u64,
     /// Slippage tolerance used
     pub slippage_bps: u16,
     /// Whether protected mode was active
@@ -316,4 +1113,537 @@ pub struct SwapExecuted {
     pub timestamp: i64,
     /// Receipt account address
     pub receipt: Pubkey,
+    /// Caller-supplied order ID (zeroes if none)
+    pub client_order_id: [u8; 16],
+    /// Decimals-normalized output per input token, scaled by 1e6
+    pub execution_price: u64,
+    /// Protocol event sequence number
+    pub sequence: u64,
+}
+
+/// Event emitted for every receipt created, indexed per user
+///
+/// `user_receipt_index` increments by one per swap so indexers can enumerate
+/// a user's receipts without scanning every `SwapReceipt`.
+#[event]
+pub struct ReceiptCreated {
+    /// Owner of the receipt
+    pub user: Pubkey,
+    /// Receipt account
+    pub receipt: Pubkey,
+    /// 1-based index of the receipt among the user's swaps
+    pub user_receipt_index: u64,
+    /// Protocol event sequence number
+    pub sequence: u64,
+}
+
+/// Event emitted when a swap updates a mint's cumulative volume
+#[event]
+pub struct TokenVolumeUpdated {
+    /// Token mint
+    pub mint: Pubkey,
+    /// Total amount swapped in as the input token
+    pub volume_in: u64,
+    /// Total amount received as the output token
+    pub volume_out: u64,
+    /// Number of recorded swaps touching the mint
+    pub swap_count: u64,
+    /// Protocol event sequence number
+    pub sequence: u64,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::instructions::payment::USDC_MINT_MAINNET;
+    use crate::jupiter::AggregatorSource;
+
+    #[test]
+    fn test_check_referrer() {
+        let user = Pubkey::new_unique();
+        assert!(check_referrer(None, &user).is_ok());
+        assert!(check_referrer(Some(Pubkey::new_unique()), &user).is_ok());
+        assert_eq!(
+            check_referrer(Some(user), &user).unwrap_err(),
+            FlowMintError::SelfReferral.into()
+        );
+    }
+
+    #[test]
+    fn test_read_pair_prices_requires_registered_feeds() {
+        let mut config = ProtocolConfig::default();
+        let input_mint = Pubkey::new_unique();
+        let output_mint = Pubkey::new_unique();
+
+        // Without feeds, the oracle check is skipped only for unpriceable pairs
+        assert_eq!(
+            read_pair_prices(&config, None, None, &input_mint, &output_mint, 0).unwrap(),
+            None
+        );
+        config.set_oracle_feed(input_mint, [1; 32]).unwrap();
+        assert_eq!(
+            read_pair_prices(&config, None, None, &input_mint, &output_mint, 0).unwrap(),
+            None
+        );
+        config.set_oracle_feed(output_mint, [2; 32]).unwrap();
+        assert_eq!(
+            read_pair_prices(&config, None, None, &input_mint, &output_mint, 0).unwrap_err(),
+            FlowMintError::InvalidOracle.into()
+        );
+        config = ProtocolConfig {
+            protected_mode_enabled: true,
+            ..ProtocolConfig::default()
+        };
+        assert!(read_pair_prices(&config, None, None, &input_mint, &output_mint, 0).is_err());
+
+        // Feeds for mints without a registered feed ID are never trusted
+        let key = Pubkey::new_unique();
+        let owner = crate::oracle::PYTH_RECEIVER_PROGRAM_ID;
+        let (mut lamports, mut data) = (0, vec![]);
+        let feed = AccountInfo::new(&key, false, false, &mut lamports, &mut data, &owner, false, 0);
+        let result = read_pair_prices(&config, Some(&feed), None, &input_mint, &output_mint, 0);
+        assert!(result.is_err());
+        let result =
+            read_pair_prices(&config, Some(&feed), Some(&feed), &input_mint, &output_mint, 0);
+        assert_eq!(result.unwrap_err(), FlowMintError::InvalidOracle.into());
+    }
+
+    #[test]
+    fn test_fair_value_floor() {
+        // Oracle values the input at 1_000_000 output units; require 98%
+        let floor = fair_value_floor(1_000_000, 9_800).unwrap();
+        assert_eq!(floor, 980_000);
+        assert!(check_fair_value_floor(980_000, floor).is_ok());
+        assert_eq!(
+            check_fair_value_floor(979_999, floor).unwrap_err(),
+            FlowMintError::InsufficientOutputAmount.into()
+        );
+
+        // A low absolute minimum doesn't help a route far from fair value
+        assert!(check_fair_value_floor(500_000, floor).is_err());
+
+        assert_eq!(fair_value_floor(1_000_000, 10_000).unwrap(), 1_000_000);
+        assert_eq!(fair_value_floor(u64::MAX, 10_000).unwrap(), u64::MAX);
+        assert!(fair_value_floor(1_000_000, 10_001).is_err());
+    }
+
+    #[test]
+    fn test_resolve_minimum_amount_out() {
+        let route = JupiterRoute {
+            input_mint: Pubkey::new_unique(),
+            output_mint: Pubkey::new_unique(),
+            in_amount: 1_000_000,
+            out_amount: 990_000,
+            slippage_bps: 100,
+            route_steps: vec![],
+            quote_timestamp: 0,
+            quote_expiration_seconds: 0,
+            source: AggregatorSource::Jupiter,
+            token_ledger: None,
+        };
+
+        // Explicit minimum is kept as-is
+        assert_eq!(resolve_minimum_amount_out(950_000, &route, 0).unwrap(), 950_000);
+
+        // Zero derives the floor from the quote: 990_000 * 99%
+        let derived = resolve_minimum_amount_out(0, &route, 0).unwrap();
+        assert_eq!(derived, 980_100);
+
+        // The derived floor is enforced on the actual output
+        assert!(verify_swap_output(980_100, derived, 100, route.out_amount, 0).is_ok());
+        assert!(verify_swap_output(980_099, derived, 200, route.out_amount, 0).is_err());
+
+        // A quote that derives a zero floor is rejected
+        let dust = JupiterRoute {
+            out_amount: 0,
+            ..route
+        };
+        assert!(resolve_minimum_amount_out(0, &dust, 0).is_err());
+    }
+
+    #[test]
+    fn test_minimum_out_net_of_fee() {
+        let route = JupiterRoute {
+            input_mint: Pubkey::new_unique(),
+            output_mint: Pubkey::new_unique(),
+            in_amount: 1_000_000,
+            out_amount: 1_000_000,
+            slippage_bps: 100,
+            route_steps: vec![],
+            quote_timestamp: 0,
+            quote_expiration_seconds: 0,
+            source: AggregatorSource::Jupiter,
+            token_ledger: None,
+        };
+        let fee_bps = 30;
+
+        // The derived floor leaves room for the fee: 990_000 less 0.3%
+        let minimum = resolve_minimum_amount_out(0, &route, fee_bps).unwrap();
+        assert_eq!(minimum, 987_030);
+
+        // A fill exactly at the quote passes once the fee is taken out of both
+        let quoted_net = net_of_fee(route.out_amount, fee_bps).unwrap();
+        let actual_out = 1_000_000;
+        let net_out = actual_out - compute_protocol_fee(actual_out, fee_bps).unwrap();
+        assert_eq!(net_out, 997_000);
+        assert!(verify_swap_output(net_out, minimum, 100, quoted_net, 0).is_ok());
+
+        // A user minimum above what they'd keep after the fee is rejected
+        assert!(verify_swap_output(net_out, 999_000, 100, quoted_net, 0).is_err());
+        assert!(check_fair_value_floor(net_out, 998_000).is_err());
+    }
+
+    #[test]
+    fn test_fee_vaults_for_two_mints() {
+        use anchor_lang::solana_program::program_pack::Pack;
+        use anchor_spl::token::spl_token;
+
+        let config = Pubkey::new_unique();
+        let mint_a = Pubkey::new_unique();
+        let mint_b = Pubkey::new_unique();
+        let vault_a = crate::instructions::admin::fee_vault_address(&mint_a);
+        let vault_b = crate::instructions::admin::fee_vault_address(&mint_b);
+        assert_ne!(vault_a, vault_b);
+
+        let vault_data = |mint: Pubkey, amount: u64| {
+            let mut data = vec![0u8; spl_token::state::Account::LEN];
+            spl_token::state::Account {
+                mint,
+                owner: config,
+                amount,
+                state: spl_token::state::AccountState::Initialized,
+                ..Default::default()
+            }
+            .pack_into_slice(&mut data);
+            data
+        };
+        let (mut lamports_a, mut lamports_b, mut lamports_c) = (1, 1, 0);
+        let mut data_a = vault_data(mint_a, 3_000);
+        let mut data_b = vault_data(mint_b, 7_000);
+        let mut data_c = vec![];
+        let token_program = token::ID;
+        let system_program = anchor_lang::system_program::ID;
+        let info_a = AccountInfo::new(
+            &vault_a, false, true, &mut lamports_a, &mut data_a, &token_program, false, 0,
+        );
+        let info_b = AccountInfo::new(
+            &vault_b, false, true, &mut lamports_b, &mut data_b, &token_program, false, 0,
+        );
+
+        // Each mint's vault collects only its own mint, with the balance to withdraw
+        assert!(load_fee_vault(&info_a, &mint_a, &config).unwrap());
+        assert!(load_fee_vault(&info_b, &mint_b, &config).unwrap());
+        assert!(load_fee_vault(&info_a, &mint_b, &config).is_err());
+        assert!(load_fee_vault(&info_b, &mint_a, &Pubkey::new_unique()).is_err());
+        for (info, amount) in [(&info_a, 3_000), (&info_b, 7_000)] {
+            let vault = token_interface::TokenAccount::try_deserialize(
+                &mut &info.try_borrow_data().unwrap()[..],
+            )
+            .unwrap();
+            assert_eq!(vault.amount, amount);
+        }
+
+        // A mint nobody created a vault for swaps fee-free
+        let mint_c = Pubkey::new_unique();
+        let vault_c = crate::instructions::admin::fee_vault_address(&mint_c);
+        let info_c = AccountInfo::new(
+            &vault_c, false, true, &mut lamports_c, &mut data_c, &system_program, false, 0,
+        );
+        assert!(!load_fee_vault(&info_c, &mint_c, &config).unwrap());
+    }
+
+    #[test]
+    fn test_impact_rejection_reports_computed_value() {
+        use crate::jupiter::{simulate_price_impact, RouteStep};
+
+        let mint = Pubkey::new_unique();
+        let route = JupiterRoute {
+            input_mint: mint,
+            output_mint: Pubkey::new_unique(),
+            in_amount: 1_000_000,
+            out_amount: 970_000,
+            slippage_bps: 50,
+            route_steps: vec![RouteStep {
+                program_id: Pubkey::new_unique(),
+                input_mint: mint,
+                output_mint: Pubkey::new_unique(),
+                amount_in: 1_000_000,
+                amount_out: 970_000,
+                fee_amount: 25_000,
+                fee_mint: mint,
+            }],
+            quote_timestamp: 0,
+            quote_expiration_seconds: 0,
+            source: AggregatorSource::Jupiter,
+            token_ledger: None,
+        };
+        let impact = simulate_price_impact(&route) as u64;
+        assert_eq!(impact, 250);
+        assert_eq!(
+            impact_rejection_message(ImpactSource::RouteFees, impact, 100),
+            "Fee impact 250 bps exceeds limit 100 bps"
+        );
+
+        assert!(ensure_impact_within_limit(ImpactSource::RouteFees, impact, 250).is_ok());
+        assert_eq!(
+            ensure_impact_within_limit(ImpactSource::RouteFees, impact, 100).unwrap_err(),
+            FlowMintError::FeeImpactTooHigh.into()
+        );
+        assert_eq!(
+            ensure_impact_within_limit(ImpactSource::Oracle, 150, 100).unwrap_err(),
+            FlowMintError::PriceImpactTooHigh.into()
+        );
+        assert_eq!(
+            ensure_impact_within_limit(ImpactSource::Twap, 150, 100).unwrap_err(),
+            FlowMintError::PriceImpactTooHigh.into()
+        );
+    }
+
+    #[test]
+    fn test_swap_volume_uses_usdc_leg() {
+        let usdc: Pubkey = USDC_MINT_MAINNET.parse().unwrap();
+        let sol = Pubkey::new_unique();
+        let bonk = Pubkey::new_unique();
+
+        // 1 SOL (9 decimals) -> 150 USDC (6 decimals)
+        assert_eq!(
+            swap_volume_usd(&sol, 1_000_000_000, 9, &usdc, 150_000_000, 6, None),
+            150_000_000
+        );
+        // 150 USDC -> 1 SOL
+        assert_eq!(
+            swap_volume_usd(&usdc, 150_000_000, 6, &sol, 1_000_000_000, 9, None),
+            150_000_000
+        );
+        // No USDC leg
+        assert_eq!(swap_volume_usd(&sol, 1_000_000_000, 9, &bonk, 1, 5, None), 0);
+    }
+
+    #[test]
+    fn test_oracle_valued_volume() {
+        let sol = Pubkey::new_unique();
+        let bonk = Pubkey::new_unique();
+        let bonk_price = OraclePrice {
+            feed_id: [1u8; 32],
+            price: 20,
+            conf: 0,
+            exponent: -6,
+            publish_time: 0,
+        };
+
+        // 1,000,000 BONK at $0.000020 is worth $20
+        let volume =
+            |price| swap_volume_usd(&sol, 1_000_000_000, 9, &bonk, 100_000_000_000, 5, price);
+        assert_eq!(volume(Some(&bonk_price)), 20_000_000);
+        // No USDC leg and no verified price: no volume
+        assert_eq!(volume(None), 0);
+    }
+
+    #[test]
+    fn test_swap_notional_scales_by_decimals() {
+        let usdc: Pubkey = USDC_MINT_MAINNET.parse().unwrap();
+        let sol = Pubkey::new_unique();
+        let bonk = Pubkey::new_unique();
+        let bonk_price = OraclePrice {
+            feed_id: [1u8; 32],
+            price: 20,
+            conf: 0,
+            exponent: -6,
+            publish_time: 0,
+        };
+
+        // 1,000 USDC in, or 1,000 USDC quoted out
+        assert_eq!(
+            swap_notional_usd(&usdc, 1_000_000_000, 6, &sol, 5_000_000_000, 9, None),
+            Some(1_000_000_000)
+        );
+        assert_eq!(
+            swap_notional_usd(&sol, 5_000_000_000, 9, &usdc, 1_000_000_000, 6, None),
+            Some(1_000_000_000)
+        );
+        // 1e11 raw BONK is only $20, not a large trade
+        assert_eq!(
+            swap_notional_usd(&bonk, 100_000_000_000, 5, &sol, 1, 9, Some(&bonk_price)),
+            Some(20_000_000)
+        );
+        // No USDC leg and no verified price: unknown
+        assert_eq!(swap_notional_usd(&bonk, 100_000_000_000, 5, &sol, 1, 9, None), None);
+    }
+
+    #[test]
+    fn test_compute_amount_spent_full_fill() {
+        assert_eq!(compute_amount_spent(5_000, 4_000, 1_000).unwrap(), 1_000);
+    }
+
+    #[test]
+    fn test_compute_amount_spent_partial_fill() {
+        // Only 600 of the requested 1000 was consumed
+        assert_eq!(compute_amount_spent(5_000, 4_400, 1_000).unwrap(), 600);
+    }
+
+    #[test]
+    fn test_compute_amount_spent_overspend() {
+        assert!(compute_amount_spent(5_000, 3_000, 1_000).is_err());
+        assert!(compute_amount_spent(1_000, 2_000, 1_000).is_err());
+    }
+
+    fn run_execute_swap<'info>(
+        accounts: &'info [AccountInfo<'info>],
+        route: &JupiterRoute,
+    ) -> Result<()> {
+        let mut bumps = <ExecuteSwap as anchor_lang::Bumps>::Bumps::default();
+        let mut remaining = accounts;
+        let mut swap = ExecuteSwap::try_accounts(
+            &crate::ID,
+            &mut remaining,
+            &[],
+            &mut bumps,
+            &mut std::collections::BTreeSet::new(),
+        )?;
+        let ctx = Context::new(&crate::ID, &mut swap, remaining, bumps);
+        let route_bytes = Some(route.try_to_vec()?);
+        execute_swap_handler(ctx, 1_000_000, 980_000, 50, false, None, route_bytes, None, None)?;
+        swap.exit(&crate::ID)
+    }
+
+    #[test]
+    fn test_swap_creates_missing_output_ata() {
+        use crate::jupiter::{RouteStep, JUPITER_V6_PROGRAM_ID};
+        use crate::test_runtime::{self, RuntimeAccount};
+        use anchor_lang::system_program;
+        use anchor_spl::associated_token::get_associated_token_address;
+
+        test_runtime::install();
+        let user_key = Pubkey::new_unique();
+        let (input_mint_key, output_mint_key) = (Pubkey::new_unique(), Pubkey::new_unique());
+        let ata_key = get_associated_token_address(&user_key, &output_mint_key);
+        let (config_key, config_bump) = Pubkey::find_program_address(&[b"config"], &crate::ID);
+        let (receipt_key, _) = Pubkey::find_program_address(
+            &[b"receipt", user_key.as_ref(), &0i64.to_le_bytes()],
+            &crate::ID,
+        );
+        let (stats_key, _) =
+            Pubkey::find_program_address(&[b"user_stats", user_key.as_ref()], &crate::ID);
+
+        let mut config_data = Vec::new();
+        ProtocolConfig {
+            default_slippage_bps: 100,
+            protected_slippage_bps: 50,
+            max_price_impact_bps: 100,
+            max_quote_ttl_seconds: 60,
+            max_remaining_accounts: crate::state::DEFAULT_MAX_REMAINING_ACCOUNTS,
+            bump: config_bump,
+            ..Default::default()
+        }
+        .try_serialize(&mut config_data)
+        .unwrap();
+        let route = JupiterRoute {
+            input_mint: input_mint_key,
+            output_mint: output_mint_key,
+            in_amount: 1_000_000,
+            out_amount: 990_000,
+            slippage_bps: 50,
+            route_steps: vec![RouteStep {
+                program_id: Pubkey::new_unique(),
+                input_mint: input_mint_key,
+                output_mint: output_mint_key,
+                amount_in: 1_000_000,
+                amount_out: 990_000,
+                fee_amount: 0,
+                fee_mint: input_mint_key,
+            }],
+            quote_timestamp: 0,
+            quote_expiration_seconds: 30,
+            source: AggregatorSource::Jupiter,
+            token_ledger: None,
+        };
+
+        let empty = |key, space| RuntimeAccount::new(key, system_program::ID, 0, &[], space);
+        let mut user =
+            RuntimeAccount::new(user_key, system_program::ID, 10_000_000_000, &[], 0);
+        let mut config =
+            RuntimeAccount::new(config_key, crate::ID, 1, &config_data, config_data.len());
+        let input_key = Pubkey::new_unique();
+        let mut input =
+            RuntimeAccount::token_account(input_key, input_mint_key, user_key, 1_000_000);
+        let mut input_mint = RuntimeAccount::mint(input_mint_key, 6);
+        let mut output_mint = RuntimeAccount::mint(output_mint_key, 6);
+        let mut receipt = empty(receipt_key, SwapReceipt::SIZE);
+        let mut user_stats = empty(stats_key, UserStats::SIZE);
+        let mut ata = empty(ata_key, TokenAccount::LEN);
+        let mut absent: Vec<_> =
+            (0..9).map(|_| RuntimeAccount::new(crate::ID, crate::ID, 0, &[], 0)).collect();
+        let mut programs: Vec<_> = [
+            JUPITER_V6_PROGRAM_ID,
+            token::ID,
+            system_program::ID,
+            anchor_spl::associated_token::ID,
+        ]
+        .map(RuntimeAccount::program)
+        .into();
+        let pool_in_key = Pubkey::new_unique();
+        let pool_out_key = Pubkey::new_unique();
+        let mut pool_in =
+            RuntimeAccount::token_account(pool_in_key, input_mint_key, pool_in_key, 0);
+        let mut pool_out =
+            RuntimeAccount::token_account(pool_out_key, output_mint_key, pool_out_key, 5_000_000);
+
+        // Jupiter stand-in: take the input into the pool and pay the quoted output
+        test_runtime::mock_program(JUPITER_V6_PROGRAM_ID, move |_, accounts| {
+            let account = |key: Pubkey| accounts.iter().find(|info| *info.key == key).unwrap();
+            test_runtime::move_tokens(account(input_key), account(pool_in_key), 1_000_000);
+            test_runtime::move_tokens(account(pool_out_key), account(ata_key), 990_000);
+            Ok(())
+        });
+
+        {
+            let mut absent = absent.iter_mut().map(|account| account.info(false, false));
+            let mut programs = programs.iter_mut().map(|account| account.info(false, true));
+            let mut accounts = vec![
+                user.info(true, false),
+                config.info(false, false),
+                input.info(false, false),
+                absent.next().unwrap(), // user_output_account
+                input_mint.info(false, false),
+                output_mint.info(false, false),
+                receipt.info(false, false),
+                user_stats.info(false, false),
+            ];
+            // fee_vault through output_token_volume
+            accounts.extend(absent.by_ref().take(8));
+            accounts.push(ata.info(false, false));
+            accounts.extend(programs.by_ref());
+            // Jupiter swap accounts
+            let (user_info, input_info, ata_info) =
+                (accounts[0].clone(), accounts[2].clone(), accounts[16].clone());
+            accounts.extend([
+                input_info,
+                ata_info,
+                pool_in.info(false, false),
+                pool_out.info(false, false),
+                user_info,
+            ]);
+            run_execute_swap(&accounts, &route).unwrap();
+        }
+
+        // The ATA was created for the user and received the swap output
+        assert_eq!(ata.owner, token::ID);
+        let created = TokenAccount::try_deserialize(&mut &ata.bytes()[..]).unwrap();
+        assert_eq!((created.owner, created.mint), (user_key, output_mint_key));
+        assert_eq!(created.amount, 990_000);
+        assert_eq!(input.token_amount(), 0);
+        let receipt = SwapReceipt::try_deserialize(&mut &receipt.bytes()[..]).unwrap();
+        assert_eq!(receipt.amount_out, 990_000);
+    }
+
+    #[cfg(feature = "compute-logging")]
+    #[test]
+    fn test_log_compute_units() {
+        crate::test_runtime::install();
+        crate::test_runtime::take_compute_unit_logs();
+
+        log_compute_units("execute_swap: start");
+        log_compute_units("execute_swap: end");
+        assert_eq!(crate::test_runtime::take_compute_unit_logs(), 2);
+    }
 }
@@ -0,0 +1,622 @@
+//! Swap Exact Out Instruction
+//!
+//! Swap any token for an exact amount of any other token.
+//!
+//! ## Flow
+//!
+//! 1. Validate parameters and the Jupiter route
+//! 2. Escrow `max_amount_in` into a temp PDA input account
+//! 3. Execute a Jupiter ExactOut swap (temp input -> temp output) via CPI,
+//!    signed by config
+//! 4. Transfer exactly `exact_amount_out` to the user
+//! 5. Collect the protocol fee (in the input mint) from the user's input
+//!    account and refund unspent escrowed input
+//! 6. Record receipt on-chain
+
+use anchor_lang::prelude::*;
+use anchor_spl::token::{Mint, Token, TokenAccount};
+
+use crate::errors::FlowMintError;
+use crate::instructions::payment::compute_protocol_fee;
+use crate::instructions::swap::{
+    swap_volume_usd, validate_swap, ReceiptCreated, SwapExecuted, SwapMarket, SwapRequest,
+};
+use crate::jupiter::{
+    check_swap_destination, deserialize_route, execute_jupiter_exact_out_swap, JupiterError,
+};
+use crate::state::{AmmAllowlist, FillStatus, PriceTwap, ProtocolConfig, SwapReceipt, UserStats};
+use crate::token_transfer::transfer_tokens;
+
+/// Accounts for the SwapExactOut instruction
+#[derive(Accounts)]
+pub struct SwapExactOut<'info> {
+    /// The user executing the swap
+    #[account(mut)]
+    pub user: Signer<'info>,
+
+    /// Protocol configuration (token authority for the temp accounts)
+    #[account(
+        mut,
+        seeds = [b"config"],
+        bump = config.bump
+    )]
+    pub config: Account<'info, ProtocolConfig>,
+
+    /// User's input token account
+    #[account(
+        mut,
+        constraint = user_input_account.owner == user.key() @ FlowMintError::InvalidOwner,
+        constraint = user_input_account.mint == input_mint.key() @ FlowMintError::InvalidMint
+    )]
+    pub user_input_account: Account<'info, TokenAccount>,
+
+    /// User's output token account
+    #[account(
+        mut,
+        constraint = user_output_account.owner == user.key() @ FlowMintError::InvalidOwner,
+        constraint = user_output_account.mint == output_mint.key() @ FlowMintError::InvalidMint
+    )]
+    pub user_output_account: Account<'info, TokenAccount>,
+
+    /// Input token mint
+    /// CHECK: Validated by token account constraints
+    pub input_mint: AccountInfo<'info>,
+
+    /// Output token mint
+    /// CHECK: Validated by token account constraints
+    pub output_mint: AccountInfo<'info>,
+
+    /// Temporary PDA account escrowing the input for the swap
+    #[account(
+        init_if_needed,
+        payer = user,
+        token::mint = input_mint,
+        token::authority = config,
+        seeds = [b"temp_input", user.key().as_ref(), input_mint.key().as_ref()],
+        bump,
+    )]
+    pub temp_input_account: Account<'info, TokenAccount>,
+
+    /// Temporary PDA account receiving the swap output
+    #[account(
+        init_if_needed,
+        payer = user,
+        token::mint = output_mint,
+        token::authority = config,
+        seeds = [b"temp_output", user.key().as_ref(), output_mint.key().as_ref()],
+        bump,
+    )]
+    pub temp_output_account: Account<'info, TokenAccount>,
+
+    /// Protocol FeeVault for the input mint (required when a protocol fee is charged)
+    #[account(
+        mut,
+        constraint = fee_vault.mint == input_mint.key() @ FlowMintError::InvalidMint,
+        constraint = fee_vault.owner == config.key() @ FlowMintError::InvalidOwner,
+        seeds = [b"fee_vault", input_mint.key().as_ref()],
+        bump,
+    )]
+    pub fee_vault: Option<Account<'info, TokenAccount>>,
+
+    /// Swap receipt account (PDA)
+    #[account(
+        init,
+        payer = user,
+        space = SwapReceipt::SIZE,
+        seeds = [
+            b"receipt",
+            user.key().as_ref(),
+            &Clock::get()?.unix_timestamp.to_le_bytes()
+        ],
+        bump
+    )]
+    pub receipt: Account<'info, SwapReceipt>,
+
+    /// User stats account (PDA)
+    #[account(
+        init_if_needed,
+        payer = user,
+        space = UserStats::SIZE,
+        seeds = [b"user_stats", user.key().as_ref()],
+        bump
+    )]
+    pub user_stats: Account<'info, UserStats>,
+
+    /// AMM allowlist (required while the allowlist is enabled)
+    #[account(seeds = [b"amm_allowlist"], bump = amm_allowlist.bump)]
+    pub amm_allowlist: Option<Account<'info, AmmAllowlist>>,
+
+    /// Pyth price feed for the input token (optional, used in protected mode)
+    /// CHECK: Owner and layout validated when read
+    pub input_price_feed: Option<UncheckedAccount<'info>>,
+
+    /// Pyth price feed for the output token (optional, used in protected mode)
+    /// CHECK: Owner and layout validated when read
+    pub output_price_feed: Option<UncheckedAccount<'info>>,
+
+    /// On-chain TWAP for the pair (required in protected mode; may not have
+    /// been created by a swap yet)
+    /// CHECK: PDA checked by seeds; owner and layout validated when read
+    #[account(
+        seeds = [b"twap", input_mint.key().as_ref(), output_mint.key().as_ref()],
+        bump
+    )]
+    pub price_twap: Option<UncheckedAccount<'info>>,
+
+    /// Jupiter program
+    /// CHECK: Validated against known Jupiter program ID
+    pub jupiter_program: AccountInfo<'info>,
+
+    /// Token program
+    pub token_program: Program<'info, Token>,
+
+    /// System program
+    pub system_program: Program<'info, System>,
+
+    /// Rent sysvar (required for token account init)
+    pub rent: Sysvar<'info, Rent>,
+}
+
+/// Settlement amounts of an ExactOut swap
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ExactOutSettlement {
+    /// Input consumed by the swap
+    pub amount_in_spent: u64,
+    /// Protocol fee charged in the input mint, on top of the swap input
+    pub protocol_fee: u64,
+    /// Unspent escrowed input returned to the user
+    pub input_refund: u64,
+    /// Output delivered to the user: exactly `exact_amount_out`
+    pub amount_out: u64,
+}
+
+/// Compute how an ExactOut swap settles
+///
+/// # Arguments
+///
+/// * `max_amount_in` - Input escrowed for the swap
+/// * `input_remaining` - Escrowed input left after the swap
+/// * `exact_amount_out` - Output the user must receive
+/// * `output_received` - Output the swap actually delivered
+/// * `protocol_fee_bps` - Protocol fee charged on the spent input
+pub fn compute_exact_out_settlement(
+    max_amount_in: u64,
+    input_remaining: u64,
+    exact_amount_out: u64,
+    output_received: u64,
+    protocol_fee_bps: u16,
+) -> Result<ExactOutSettlement> {
+    require!(
+        output_received >= exact_amount_out,
+        FlowMintError::InsufficientOutputAmount
+    );
+    // An ExactOut route delivers its target exactly; anything more is suspect
+    require!(
+        output_received == exact_amount_out,
+        JupiterError::ImplausibleOutput
+    );
+
+    let amount_in_spent = max_amount_in
+        .checked_sub(input_remaining)
+        .ok_or(FlowMintError::MathOverflow)?;
+    let protocol_fee = compute_protocol_fee(amount_in_spent, protocol_fee_bps)?;
+
+    Ok(ExactOutSettlement {
+        amount_in_spent,
+        protocol_fee,
+        input_refund: input_remaining,
+        amount_out: exact_amount_out,
+    })
+}
+
+/// Transfer of an ExactOut settlement
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SettlementLeg {
+    /// Temp output account to the user's output account
+    Output,
+    /// User's input account to the input mint's FeeVault
+    Fee,
+    /// Temp input account back to the user's input account
+    Refund,
+}
+
+/// Pay out an ExactOut settlement, one `transfer` per non-empty leg
+///
+/// The user receives exactly `exact_amount_out` and all unspent escrowed
+/// input; the protocol fee is paid from the user's input account, separately
+/// from the escrowed swap input.
+pub fn settle_exact_out(
+    settlement: &ExactOutSettlement,
+    mut transfer: impl FnMut(SettlementLeg, u64) -> Result<()>,
+) -> Result<()> {
+    transfer(SettlementLeg::Output, settlement.amount_out)?;
+    if settlement.protocol_fee > 0 {
+        transfer(SettlementLeg::Fee, settlement.protocol_fee)?;
+    }
+    if settlement.input_refund > 0 {
+        transfer(SettlementLeg::Refund, settlement.input_refund)?;
+    }
+    Ok(())
+}
+
+/// Swap up to `max_amount_in` for exactly `exact_amount_out`
+///
+/// Runs the same pre-CPI checks as `execute_swap` (see `validate_swap`) and
+/// executes the route as a Jupiter ExactOut swap capped at `max_amount_in`.
+/// The user's output account is credited exactly `exact_amount_out`, unspent
+/// input is returned, and the protocol fee on the spent input is charged
+/// from the user's input account on top of it.
+///
+/// # Arguments
+///
+/// * `ctx` - SwapExactOut context
+/// * `max_amount_in` - Maximum amount of input tokens to spend
+/// * `exact_amount_out` - Exact amount of output tokens to receive
+///
+/// # Returns
+///
+/// * `Result<()>` - Success or error
+pub fn swap_exact_out_handler<'info>(
+    ctx: Context<'_, '_, 'info, 'info, SwapExactOut<'info>>,
+    max_amount_in: u64,
+    exact_amount_out: u64,
+) -> Result<()> {
+    let clock = Clock::get()?;
+
+    // ============================================================
+    // Step 1: Validate input
+    // ============================================================
+    require!(!ctx.accounts.config.swaps_paused, FlowMintError::SwapsPaused);
+
+    let stats_max_age = ctx.accounts.config.stats_max_age_seconds;
+    ctx.accounts
+        .user_stats
+        .reset_if_dormant(clock.unix_timestamp, stats_max_age);
+    require!(max_amount_in > 0, FlowMintError::AmountTooSmall);
+    require!(exact_amount_out > 0, FlowMintError::AmountTooSmall);
+    require_keys_neq!(
+        ctx.accounts.input_mint.key(),
+        ctx.accounts.output_mint.key(),
+        FlowMintError::InvalidMint
+    );
+
+    // The escrow plus the largest possible fee must be covered up front
+    let fee_bps = ctx
+        .accounts
+        .config
+        .fee_bps_for_user(&ctx.accounts.user_stats);
+    let max_protocol_fee = compute_protocol_fee(max_amount_in, fee_bps)?;
+    require!(
+        ctx.accounts.user_input_account.amount
+            >= max_amount_in
+                .checked_add(max_protocol_fee)
+                .ok_or(FlowMintError::MathOverflow)?,
+        FlowMintError::InsufficientBalance
+    );
+
+    // ============================================================
+    // Step 2: Deserialize and validate Jupiter route
+    // ============================================================
+    let remaining_accounts = &ctx.remaining_accounts;
+    require!(!remaining_accounts.is_empty(), FlowMintError::InvalidInstructionData);
+
+    let route_account = &remaining_accounts[0];
+    let route_data = route_account.try_borrow_data()?;
+    let route = deserialize_route(&route_data)?;
+    route.validate_exact_out(exact_amount_out, max_amount_in)?;
+
+    let input_decimals =
+        Mint::try_deserialize(&mut &ctx.accounts.input_mint.try_borrow_data()?[..])?.decimals;
+    let output_decimals =
+        Mint::try_deserialize(&mut &ctx.accounts.output_mint.try_borrow_data()?[..])?.decimals;
+    let protected_mode = ctx
+        .accounts
+        .config
+        .is_protected_required(false, ctx.accounts.user_stats.always_protected);
+    let price_twap = ctx
+        .accounts
+        .price_twap
+        .as_ref()
+        .map(|account| PriceTwap::load(account))
+        .transpose()?;
+
+    // The route's own slippage is held to the protocol limit, and the fee is
+    // charged on the input, so the output floor is exactly `exact_amount_out`.
+    // Checks on the input run against the route's quoted input.
+    let validated = validate_swap(
+        &ctx.accounts.config,
+        &route,
+        &SwapRequest {
+            input_mint: ctx.accounts.input_mint.key(),
+            output_mint: ctx.accounts.output_mint.key(),
+            amount_in: route.in_amount,
+            minimum_amount_out: exact_amount_out,
+            slippage_bps: route.slippage_bps,
+            protected_mode,
+            output_fee_bps: 0,
+            min_out_bps_of_fair: None,
+        },
+        &SwapMarket {
+            allowlist: ctx.accounts.amm_allowlist.as_deref(),
+            input_price_feed: ctx.accounts.input_price_feed.as_deref(),
+            output_price_feed: ctx.accounts.output_price_feed.as_deref(),
+            price_twap: price_twap.as_ref(),
+            remaining_accounts,
+            swap_accounts: &remaining_accounts[1..],
+            input_decimals,
+            output_decimals,
+            current_timestamp: clock.unix_timestamp,
+        },
+    )?;
+
+    // ============================================================
+    // Step 3: Escrow the input into the temp PDA account
+    // ============================================================
+    transfer_tokens(
+        &ctx.accounts.token_program.to_account_info(),
+        &ctx.accounts.user_input_account.to_account_info(),
+        &ctx.accounts.input_mint,
+        &ctx.accounts.temp_input_account.to_account_info(),
+        &ctx.accounts.user.to_account_info(),
+        remaining_accounts,
+        max_amount_in,
+        input_decimals,
+        &[],
+    )?;
+
+    ctx.accounts.temp_input_account.reload()?;
+    let temp_input_before = ctx.accounts.temp_input_account.amount;
+    let temp_output_before = ctx.accounts.temp_output_account.amount;
+
+    // ============================================================
+    // Step 4: Execute Jupiter swap via CPI (config signs for temp input)
+    // ============================================================
+    let config_seeds = &[b"config".as_ref(), &[ctx.accounts.config.bump]];
+    let signer_seeds = &[&config_seeds[..]];
+
+    let jupiter_accounts: Vec<AccountInfo<'info>> = remaining_accounts[1..].to_vec();
+    check_swap_destination(&ctx.accounts.temp_output_account.key(), &jupiter_accounts)?;
+    execute_jupiter_exact_out_swap(
+        &ctx.accounts.jupiter_program,
+        &jupiter_accounts,
+        &route,
+        Some(signer_seeds),
+    )?;
+
+    ctx.accounts.temp_input_account.reload()?;
+    ctx.accounts.temp_output_account.reload()?;
+
+    let input_consumed = temp_input_before
+        .checked_sub(ctx.accounts.temp_input_account.amount)
+        .ok_or(FlowMintError::MathOverflow)?;
+    let input_remaining = max_amount_in
+        .checked_sub(input_consumed)
+        .ok_or(FlowMintError::MathOverflow)?;
+    let output_received = ctx
+        .accounts
+        .temp_output_account
+        .amount
+        .checked_sub(temp_output_before)
+        .ok_or(FlowMintError::MathOverflow)?;
+
+    let settlement = compute_exact_out_settlement(
+        max_amount_in,
+        input_remaining,
+        exact_amount_out,
+        output_received,
+        fee_bps,
+    )?;
+
+    // ============================================================
+    // Step 5: Deliver the output and settle the remainder
+    // ============================================================
+    let accounts = &ctx.accounts;
+    let config_info = accounts.config.to_account_info();
+    let user_info = accounts.user.to_account_info();
+    settle_exact_out(&settlement, |leg, amount| {
+        // The temp accounts are signed for by config; the fee by the user
+        let (from, mint, decimals, to, authority, seeds): (_, _, _, _, _, &[&[&[u8]]]) =
+            match leg {
+                SettlementLeg::Output => (
+                    accounts.temp_output_account.to_account_info(),
+                    &accounts.output_mint,
+                    output_decimals,
+                    accounts.user_output_account.to_account_info(),
+                    &config_info,
+                    signer_seeds,
+                ),
+                SettlementLeg::Fee => (
+                    accounts.user_input_account.to_account_info(),
+                    &accounts.input_mint,
+                    input_decimals,
+                    accounts
+                        .fee_vault
+                        .as_ref()
+                        .ok_or(FlowMintError::MissingFeeVault)?
+                        .to_account_info(),
+                    &user_info,
+                    &[],
+                ),
+                SettlementLeg::Refund => (
+                    accounts.temp_input_account.to_account_info(),
+                    &accounts.input_mint,
+                    input_decimals,
+                    accounts.user_input_account.to_account_info(),
+                    &config_info,
+                    signer_seeds,
+                ),
+            };
+        transfer_tokens(
+            &accounts.token_program.to_account_info(),
+            &from,
+            mint,
+            &to,
+            authority,
+            remaining_accounts,
+            amount,
+            decimals,
+            seeds,
+        )
+    })?;
+
+    // ============================================================
+    // Step 6: Record receipt and update stats
+    // ============================================================
+    let user = &ctx.accounts.user;
+    let receipt = &mut ctx.accounts.receipt;
+    receipt.user = user.key();
+    receipt.input_mint = ctx.accounts.input_mint.key();
+    receipt.output_mint = ctx.accounts.output_mint.key();
+    receipt.amount_in = settlement.amount_in_spent;
+    receipt.amount_out = settlement.amount_out;
+    receipt.slippage_bps = route.slippage_bps;
+    receipt.protected_mode = protected_mode;
+    receipt.timestamp = clock.unix_timestamp;
+    receipt.tx_signature = [0u8; 32];
+    receipt.referrer = Pubkey::default();
+    receipt.client_order_id = [0u8; 16];
+    receipt.execution_price = SwapReceipt::execution_price(
+        settlement.amount_in_spent,
+        input_decimals,
+        settlement.amount_out,
+        output_decimals,
+    );
+    receipt.status =
+        FillStatus::from_input(settlement.amount_in_spent, route.in_amount, max_amount_in);
+    receipt.bump = ctx.bumps.receipt;
+
+    let user_stats = &mut ctx.accounts.user_stats;
+    if user_stats.user == Pubkey::default() {
+        user_stats.user = user.key();
+        user_stats.bump = ctx.bumps.user_stats;
+    }
+    let user_receipt_index = user_stats.record_swap(clock.unix_timestamp);
+    let volume_usd = swap_volume_usd(
+        &ctx.accounts.input_mint.key(),
+        settlement.amount_in_spent,
+        input_decimals,
+        &ctx.accounts.output_mint.key(),
+        settlement.amount_out,
+        output_decimals,
+        validated.prices.map(|(_, output_price)| output_price).as_ref(),
+    );
+    user_stats.record_volume(volume_usd);
+
+    let config = &mut ctx.accounts.config;
+    config.total_swaps = config.total_swaps.saturating_add(1);
+    config.total_volume_usd = config.total_volume_usd.saturating_add(volume_usd);
+    config.record_fee_collected(settlement.protocol_fee);
+    let sequence = config.next_sequence()?;
+
+    msg!(
+        "ExactOut swap executed: {} {} -> {} {} (refund: {})",
+        settlement.amount_in_spent,
+        ctx.accounts.input_mint.key(),
+        settlement.amount_out,
+        ctx.accounts.output_mint.key(),
+        settlement.input_refund
+    );
+
+    emit!(SwapExecuted {
+        user: user.key(),
+        input_mint: ctx.accounts.input_mint.key(),
+        output_mint: ctx.accounts.output_mint.key(),
+        amount_in: settlement.amount_in_spent,
+        amount_out: settlement.amount_out,
+        protocol_fee: settlement.protocol_fee,
+        referrer: Pubkey::default(),
+        referrer_fee: 0,
+        slippage_bps: route.slippage_bps,
+        protected_mode: ctx.accounts.receipt.protected_mode,
+        timestamp: clock.unix_timestamp,
+        receipt: ctx.accounts.receipt.key(),
+        client_order_id: [0u8; 16],
+        execution_price: ctx.accounts.receipt.execution_price,
+        sequence,
+    });
+
+    emit!(ReceiptCreated {
+        user: user.key(),
+        receipt: ctx.accounts.receipt.key(),
+        user_receipt_index,
+        sequence,
+    });
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_exact_out_settlement() {
+        // The ExactOut route spends 800 of the 1000 escrowed for exactly 500 out
+        let settlement = compute_exact_out_settlement(1_000, 200, 500, 500, 0).unwrap();
+        assert_eq!(settlement.amount_in_spent, 800);
+        assert_eq!(settlement.input_refund, 200);
+        assert_eq!(settlement.amount_out, 500);
+
+        // 1% fee on the spent input is charged on top; the refund stays whole
+        let settlement = compute_exact_out_settlement(1_000, 200, 500, 500, 100).unwrap();
+        assert_eq!(settlement.protocol_fee, 8);
+        assert_eq!(settlement.input_refund, 200);
+
+        // The whole escrow spent still leaves the fee payable
+        let settlement = compute_exact_out_settlement(1_000, 0, 500, 500, 100).unwrap();
+        assert_eq!(settlement.protocol_fee, 10);
+        assert_eq!(settlement.input_refund, 0);
+    }
+
+    #[test]
+    fn test_settle_exact_out_pays_user() {
+        // 1_000 escrowed, the swap spends 800 and delivers exactly 500
+        let settlement = compute_exact_out_settlement(1_000, 200, 500, 500, 100).unwrap();
+
+        let (mut user_in, mut user_out, mut fee_vault) = (5_000u64, 0u64, 0u64);
+        let (mut temp_in, mut temp_out) = (200u64, 500u64);
+        user_in -= 1_000;
+        settle_exact_out(&settlement, |leg, amount| {
+            match leg {
+                SettlementLeg::Output => {
+                    temp_out -= amount;
+                    user_out += amount;
+                }
+                SettlementLeg::Fee => {
+                    user_in -= amount;
+                    fee_vault += amount;
+                }
+                SettlementLeg::Refund => {
+                    temp_in -= amount;
+                    user_in += amount;
+                }
+            }
+            Ok(())
+        })
+        .unwrap();
+
+        assert_eq!(user_out, 500);
+        assert_eq!(user_in, 5_000 - 800 - 8);
+        assert_eq!(fee_vault, 8);
+        assert_eq!((temp_in, temp_out), (0, 0));
+
+        // No fee and no remainder: only the output leg runs
+        let settlement = compute_exact_out_settlement(1_000, 0, 500, 500, 0).unwrap();
+        let mut legs = Vec::new();
+        settle_exact_out(&settlement, |leg, amount| {
+            legs.push((leg, amount));
+            Ok(())
+        })
+        .unwrap();
+        assert_eq!(legs, vec![(SettlementLeg::Output, 500)]);
+    }
+
+    #[test]
+    fn test_exact_out_settlement_rejects_inexact_output() {
+        assert!(compute_exact_out_settlement(1_000, 200, 500, 499, 0).is_err());
+        assert_eq!(
+            compute_exact_out_settlement(1_000, 200, 500, 501, 0).unwrap_err(),
+            JupiterError::ImplausibleOutput.into()
+        );
+    }
+}
@@ -0,0 +1,225 @@
+//! Teardown Instructions
+//!
+//! Devnet/test-only teardown of the singleton protocol accounts, so the
+//! protocol can be re-initialized without deploying under a new program ID.
+//! Only compiled with the `devnet-teardown` feature; never enable it for
+//! mainnet builds.
+
+use anchor_lang::prelude::*;
+
+use crate::errors::FlowMintError;
+use crate::instructions::admin::{emit_admin_action, AdminActionKind};
+use crate::state::{AmmAllowlist, PendingConfig, ProtocolConfig};
+
+/// Accounts for closing the protocol config
+#[derive(Accounts)]
+pub struct CloseConfig<'info> {
+    /// The protocol authority (receives the rent)
+    #[account(
+        mut,
+        constraint = authority.key() == config.authority @ FlowMintError::Unauthorized
+    )]
+    pub authority: Signer<'info>,
+
+    /// Protocol configuration
+    #[account(
+        mut,
+        close = authority,
+        seeds = [b"config"],
+        bump = config.bump
+    )]
+    pub config: Account<'info, ProtocolConfig>,
+
+    /// AMM allowlist, if it was created
+    #[account(
+        mut,
+        close = authority,
+        seeds = [b"amm_allowlist"],
+        bump = amm_allowlist.bump
+    )]
+    pub amm_allowlist: Option<Account<'info, AmmAllowlist>>,
+
+    /// Staged config changes, if any were staged
+    #[account(
+        mut,
+        close = authority,
+        seeds = [b"pending_config"],
+        bump = pending_config.bump
+    )]
+    pub pending_config: Option<Account<'info, PendingConfig>>,
+}
+
+/// Close the config and singleton accounts, refunding rent to the authority
+///
+/// # Arguments
+///
+/// * `ctx` - CloseConfig context
+///
+/// # Returns
+///
+/// * `Result<()>` - Success or error
+pub fn close_config_handler(ctx: Context<CloseConfig>) -> Result<()> {
+    msg!(
+        "Closing config (allowlist closed: {}, staged changes closed: {})",
+        ctx.accounts.amm_allowlist.is_some(),
+        ctx.accounts.pending_config.is_some()
+    );
+
+    let event = ConfigClosed {
+        authority: ctx.accounts.authority.key(),
+        sequence: ctx.accounts.config.sequence,
+    };
+    emit_admin_action(
+        ctx.accounts.authority.key(),
+        AdminActionKind::CloseConfig,
+        &event,
+    )?;
+    emit!(event);
+
+    Ok(())
+}
+
+/// Event emitted when the config is closed
+#[event]
+pub struct ConfigClosed {
+    /// Authority that closed the config
+    pub authority: Pubkey,
+    /// Last sequence number issued by the closed config
+    pub sequence: u64,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::instructions::initialize::{self, Initialize};
+    use crate::test_runtime::RuntimeAccount;
+    use anchor_lang::{system_program, Bumps};
+    use std::collections::BTreeSet;
+
+    fn run_close_config<'info>(accounts: &'info [AccountInfo<'info>]) -> Result<()> {
+        let mut bumps = <CloseConfig as Bumps>::Bumps::default();
+        let mut remaining = accounts;
+        let mut close = CloseConfig::try_accounts(
+            &crate::ID,
+            &mut remaining,
+            &[],
+            &mut bumps,
+            &mut BTreeSet::new(),
+        )?;
+        close_config_handler(Context::new(&crate::ID, &mut close, remaining, bumps))?;
+        close.exit(&crate::ID)
+    }
+
+    fn run_initialize<'info>(accounts: &'info [AccountInfo<'info>]) -> Result<()> {
+        let mut bumps = <Initialize as Bumps>::Bumps::default();
+        let mut remaining = accounts;
+        let mut init = Initialize::try_accounts(
+            &crate::ID,
+            &mut remaining,
+            &[],
+            &mut bumps,
+            &mut BTreeSet::new(),
+        )?;
+        let ctx = Context::new(&crate::ID, &mut init, remaining, bumps);
+        initialize::handler(ctx, 100, 50, 300, 30)?;
+        init.exit(&crate::ID)
+    }
+
+    #[test]
+    fn test_close_then_reinitialize() {
+        // Sysvars read by `close_config` and `initialize`
+        crate::test_runtime::install();
+
+        let program_id = crate::ID;
+        let authority_key = Pubkey::new_unique();
+        let (config_key, config_bump) = Pubkey::find_program_address(&[b"config"], &program_id);
+        let (pending_key, pending_bump) =
+            Pubkey::find_program_address(&[b"pending_config"], &program_id);
+
+        let mut config_data = Vec::new();
+        ProtocolConfig {
+            authority: authority_key,
+            sequence: 7,
+            bump: config_bump,
+            ..Default::default()
+        }
+        .try_serialize(&mut config_data)
+        .unwrap();
+        config_data.resize(ProtocolConfig::SIZE, 0);
+        let mut pending_data = Vec::new();
+        PendingConfig {
+            staged_by: authority_key,
+            staged_at: 0,
+            params: vec![1, 2, 3],
+            bump: pending_bump,
+        }
+        .try_serialize(&mut pending_data)
+        .unwrap();
+
+        let rent = Rent::default();
+        let mut authority =
+            RuntimeAccount::new(authority_key, system_program::ID, 10_000_000_000, &[], 0);
+        let mut config = RuntimeAccount::new(
+            config_key,
+            program_id,
+            rent.minimum_balance(ProtocolConfig::SIZE),
+            &config_data,
+            ProtocolConfig::SIZE,
+        );
+        let mut pending = RuntimeAccount::new(
+            pending_key,
+            program_id,
+            rent.minimum_balance(PendingConfig::SIZE),
+            &pending_data,
+            PendingConfig::SIZE,
+        );
+        // Optional accounts that aren't passed are given as the program ID
+        let mut absent = RuntimeAccount::new(program_id, program_id, 0, &[], 0);
+        let refunded = config.lamports + pending.lamports;
+        let authority_before = authority.lamports;
+
+        // Close the config and the staged changes
+        {
+            let accounts = [
+                authority.info(true, false),
+                config.info(false, false),
+                absent.info(false, false),
+                pending.info(false, false),
+            ];
+            run_close_config(&accounts).unwrap();
+        }
+        assert_eq!(authority.lamports, authority_before + refunded);
+        for closed in [&config, &pending] {
+            assert_eq!(closed.lamports, 0);
+            assert_eq!(closed.owner, system_program::ID);
+            assert_eq!(closed.data[0], 0);
+        }
+
+        // The same PDA can be initialized again, from a blank config
+        let new_authority_key = Pubkey::new_unique();
+        let mut new_authority = RuntimeAccount::new(
+            new_authority_key,
+            system_program::ID,
+            10_000_000_000,
+            &[],
+            0,
+        );
+        let mut treasury = RuntimeAccount::new(Pubkey::new_unique(), system_program::ID, 0, &[], 0);
+        let mut system = RuntimeAccount::new(system_program::ID, Pubkey::default(), 1, &[], 0);
+        {
+            let accounts = [
+                new_authority.info(true, false),
+                config.info(false, false),
+                treasury.info(false, false),
+                system.info(false, true),
+            ];
+            run_initialize(&accounts).unwrap();
+        }
+
+        let reinitialized = ProtocolConfig::try_deserialize(&mut &config.bytes()[..]).unwrap();
+        assert_eq!(reinitialized.authority, new_authority_key);
+        assert_eq!(reinitialized.treasury, treasury.key);
+        assert_eq!(reinitialized.sequence, 0);
+        assert_eq!(reinitialized.bump, config_bump);
+    }
+}
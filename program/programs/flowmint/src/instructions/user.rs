@@ -0,0 +1,409 @@
+//! User Instructions
+//!
+//! Instructions for users to manage their own protocol accounts.
+
+use anchor_lang::prelude::*;
+
+use crate::errors::FlowMintError;
+use crate::state::{ProtocolConfig, SwapReceipt, UserStats};
+
+/// Maximum receipts closed by one `close_receipts_batch` call
+pub const MAX_RECEIPTS_PER_BATCH: usize = 16;
+
+/// Minimum receipt age before it can be closed (7 days)
+pub const RECEIPT_RETENTION_SECONDS: i64 = 7 * 24 * 60 * 60;
+
+/// Accounts for closing a user's stats account
+#[derive(Accounts)]
+pub struct CloseUserStats<'info> {
+    /// The user who owns the stats account (receives the rent)
+    #[account(mut)]
+    pub user: Signer<'info>,
+
+    /// Protocol configuration
+    #[account(
+        mut,
+        seeds = [b"config"],
+        bump = config.bump
+    )]
+    pub config: Account<'info, ProtocolConfig>,
+
+    /// User stats account (PDA)
+    #[account(
+        mut,
+        close = user,
+        seeds = [b"user_stats", user.key().as_ref()],
+        bump = user_stats.bump,
+        constraint = user_stats.user == user.key() @ FlowMintError::Unauthorized
+    )]
+    pub user_stats: Account<'info, UserStats>,
+}
+
+/// Close the user's stats account and reclaim its rent
+///
+/// A later swap or payment recreates the account via `init_if_needed`, with
+/// every flag cleared: an authority-granted `fee_exempt` is dropped and must
+/// be granted again with `set_fee_exempt`.
+pub fn close_user_stats_handler(ctx: Context<CloseUserStats>) -> Result<()> {
+    let sequence = ctx.accounts.config.next_sequence()?;
+
+    msg!("Closed user stats for {}", ctx.accounts.user.key());
+
+    emit!(UserStatsClosed {
+        user: ctx.accounts.user.key(),
+        sequence,
+    });
+
+    Ok(())
+}
+
+/// Accounts for closing a batch of the user's swap receipts
+///
+/// The receipts are passed as writable remaining accounts.
+#[derive(Accounts)]
+pub struct CloseReceiptsBatch<'info> {
+    /// The user who owns the receipts (receives the rent)
+    #[account(mut)]
+    pub user: Signer<'info>,
+
+    /// Protocol configuration
+    #[account(
+        mut,
+        seeds = [b"config"],
+        bump = config.bump
+    )]
+    pub config: Account<'info, ProtocolConfig>,
+}
+
+/// Close up to `MAX_RECEIPTS_PER_BATCH` expired receipts and reclaim their rent
+///
+/// Every receipt must belong to the signer and be older than
+/// `RECEIPT_RETENTION_SECONDS`; otherwise the whole batch fails.
+pub fn close_receipts_batch_handler<'info>(
+    ctx: Context<'_, '_, 'info, 'info, CloseReceiptsBatch<'info>>,
+) -> Result<()> {
+    let receipt_infos = ctx.remaining_accounts;
+    check_receipt_batch_size(receipt_infos.len())?;
+
+    let user = ctx.accounts.user.key();
+    let now = Clock::get()?.unix_timestamp;
+
+    for (i, info) in receipt_infos.iter().enumerate() {
+        require!(
+            receipt_infos[..i].iter().all(|other| other.key != info.key),
+            FlowMintError::DuplicateAccount
+        );
+
+        let receipt = Account::<SwapReceipt>::try_from(info)?;
+        check_receipt_closable(&receipt, &user, now)?;
+        receipt.close(ctx.accounts.user.to_account_info())?;
+    }
+
+    let sequence = ctx.accounts.config.next_sequence()?;
+
+    msg!("Closed {} receipts for {}", receipt_infos.len(), user);
+
+    emit!(ReceiptsClosed {
+        user,
+        count: receipt_infos.len() as u8,
+        sequence,
+    });
+
+    Ok(())
+}
+
+/// Bound the batch so closing stays within the compute budget
+pub fn check_receipt_batch_size(count: usize) -> Result<()> {
+    require!(
+        count > 0 && count <= MAX_RECEIPTS_PER_BATCH,
+        FlowMintError::InvalidInstructionData
+    );
+    Ok(())
+}
+
+/// Check a receipt belongs to `user` and is past its retention window
+pub fn check_receipt_closable(receipt: &SwapReceipt, user: &Pubkey, now: i64) -> Result<()> {
+    require_keys_eq!(receipt.user, *user, FlowMintError::Unauthorized);
+    require!(
+        now.saturating_sub(receipt.timestamp) >= RECEIPT_RETENTION_SECONDS,
+        FlowMintError::ReceiptTooRecent
+    );
+    Ok(())
+}
+
+/// Accounts for setting a user's "always protected" preference
+#[derive(Accounts)]
+pub struct SetAlwaysProtected<'info> {
+    /// The user setting the preference
+    #[account(mut)]
+    pub user: Signer<'info>,
+
+    /// Protocol configuration
+    #[account(
+        mut,
+        seeds = [b"config"],
+        bump = config.bump
+    )]
+    pub config: Account<'info, ProtocolConfig>,
+
+    /// User stats account (PDA)
+    #[account(
+        init_if_needed,
+        payer = user,
+        space = UserStats::SIZE,
+        seeds = [b"user_stats", user.key().as_ref()],
+        bump
+    )]
+    pub user_stats: Account<'info, UserStats>,
+
+    /// System program
+    pub system_program: Program<'info, System>,
+}
+
+/// Force (or stop forcing) protected mode on all of the user's swaps
+pub fn set_always_protected_handler(ctx: Context<SetAlwaysProtected>, enabled: bool) -> Result<()> {
+    let user_stats = &mut ctx.accounts.user_stats;
+    if user_stats.user == Pubkey::default() {
+        user_stats.user = ctx.accounts.user.key();
+        user_stats.bump = ctx.bumps.user_stats;
+    }
+    user_stats.always_protected = enabled;
+
+    let sequence = ctx.accounts.config.next_sequence()?;
+
+    msg!(
+        "Always-protected {} for {}",
+        if enabled { "enabled" } else { "disabled" },
+        ctx.accounts.user.key()
+    );
+
+    emit!(AlwaysProtectedUpdated {
+        user: ctx.accounts.user.key(),
+        enabled,
+        sequence,
+    });
+
+    Ok(())
+}
+
+/// Accounts for the read-only user stats view
+#[derive(Accounts)]
+pub struct GetUserStats<'info> {
+    /// User whose stats are viewed
+    /// CHECK: Only used for PDA derivation
+    pub user: AccountInfo<'info>,
+
+    /// User stats account (PDA)
+    #[account(
+        seeds = [b"user_stats", user.key().as_ref()],
+        bump = user_stats.bump
+    )]
+    pub user_stats: Account<'info, UserStats>,
+}
+
+/// Emit derived metrics for a user's stats (read-only)
+pub fn get_user_stats_handler(ctx: Context<GetUserStats>) -> Result<()> {
+    let clock = Clock::get()?;
+    emit!(derive_user_stats_view(&ctx.accounts.user_stats, clock.unix_timestamp));
+    Ok(())
+}
+
+/// Compute derived metrics from stored `UserStats` counters
+pub fn derive_user_stats_view(stats: &UserStats, current_timestamp: i64) -> UserStatsView {
+    let total_operations = stats.total_swaps.saturating_add(stats.total_payments);
+    let average_volume_usd = stats
+        .total_volume_usd
+        .checked_div(total_operations)
+        .unwrap_or(0);
+
+    UserStatsView {
+        user: stats.user,
+        total_operations,
+        total_volume_usd: stats.total_volume_usd,
+        average_volume_usd,
+        last_activity_age_seconds: current_timestamp.saturating_sub(stats.last_activity).max(0),
+    }
+}
+
+/// Event emitted when a user closes their stats account
+#[event]
+pub struct UserStatsClosed {
+    /// User who closed the account
+    pub user: Pubkey,
+    /// Protocol event sequence number
+    pub sequence: u64,
+}
+
+/// Event emitted when a user closes a batch of receipts
+#[event]
+pub struct ReceiptsClosed {
+    /// User who closed the receipts
+    pub user: Pubkey,
+    /// Number of receipts closed
+    pub count: u8,
+    /// Protocol event sequence number
+    pub sequence: u64,
+}
+
+/// Event emitted when a user changes their "always protected" preference
+#[event]
+pub struct AlwaysProtectedUpdated {
+    /// User who changed the preference
+    pub user: Pubkey,
+    /// New preference
+    pub enabled: bool,
+    /// Protocol event sequence number
+    pub sequence: u64,
+}
+
+/// Derived user metrics emitted by `get_user_stats`
+#[event]
+pub struct UserStatsView {
+    /// User the metrics belong to
+    pub user: Pubkey,
+    /// Swaps plus payments
+    pub total_operations: u64,
+    /// Lifetime volume in USD (scaled by 1e6)
+    pub total_volume_usd: u64,
+    /// Average volume per operation in USD (scaled by 1e6)
+    pub average_volume_usd: u64,
+    /// Seconds since the user's last activity
+    pub last_activity_age_seconds: i64,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_close_user_stats_owner_only() {
+        use anchor_lang::Bumps;
+        use std::collections::BTreeSet;
+
+        let program_id = crate::ID;
+        let owner = Pubkey::new_unique();
+        let stranger = Pubkey::new_unique();
+        let (config_key, config_bump) = Pubkey::find_program_address(&[b"config"], &program_id);
+        let (stats_key, stats_bump) =
+            Pubkey::find_program_address(&[b"user_stats", owner.as_ref()], &program_id);
+
+        let mut config_data = Vec::new();
+        ProtocolConfig {
+            bump: config_bump,
+            ..Default::default()
+        }
+        .try_serialize(&mut config_data)
+        .unwrap();
+        let mut stats_data = Vec::new();
+        UserStats {
+            user: owner,
+            bump: stats_bump,
+            fee_exempt: true,
+            ..Default::default()
+        }
+        .try_serialize(&mut stats_data)
+        .unwrap();
+
+        let system_program = anchor_lang::system_program::ID;
+        let (mut owner_lamports, mut stranger_lamports) = (1_000_000, 1_000_000);
+        let (mut config_lamports, mut stats_lamports) = (1_000_000, 1_000_000);
+        let (mut owner_data, mut stranger_data) = (vec![], vec![]);
+        let owner_info = AccountInfo::new(
+            &owner, true, true, &mut owner_lamports, &mut owner_data, &system_program, false, 0,
+        );
+        let stranger_info = AccountInfo::new(
+            &stranger, true, true, &mut stranger_lamports, &mut stranger_data, &system_program,
+            false, 0,
+        );
+        let config_info = AccountInfo::new(
+            &config_key, false, true, &mut config_lamports, &mut config_data, &program_id, false,
+            0,
+        );
+        let stats_info = AccountInfo::new(
+            &stats_key, false, true, &mut stats_lamports, &mut stats_data, &program_id, false, 0,
+        );
+
+        fn try_accounts<'info>(accounts: &'info [AccountInfo<'info>]) -> Result<Pubkey> {
+            let mut bumps = <CloseUserStats as Bumps>::Bumps::default();
+            CloseUserStats::try_accounts(
+                &crate::ID,
+                &mut &accounts[..],
+                &[],
+                &mut bumps,
+                &mut BTreeSet::new(),
+            )
+            .map(|accounts| accounts.user_stats.user)
+        }
+
+        // The owner's close passes account validation
+        let accounts = [owner_info, config_info.clone(), stats_info.clone()];
+        assert_eq!(try_accounts(&accounts).unwrap(), owner);
+
+        // Anyone else is rejected: the PDA is derived from the signer
+        let accounts = [stranger_info, config_info, stats_info];
+        assert_eq!(
+            try_accounts(&accounts).unwrap_err(),
+            anchor_lang::error::ErrorCode::ConstraintSeeds.into()
+        );
+    }
+
+    #[test]
+    fn test_close_receipts_batch_checks() {
+        let user = Pubkey::new_unique();
+        let now = 10 * RECEIPT_RETENTION_SECONDS;
+        let receipt = |owner: Pubkey, timestamp: i64| SwapReceipt {
+            user: owner,
+            timestamp,
+            ..Default::default()
+        };
+
+        // Several expired receipts of the signer close together
+        let batch = [
+            receipt(user, 0),
+            receipt(user, now - RECEIPT_RETENTION_SECONDS),
+            receipt(user, now - 2 * RECEIPT_RETENTION_SECONDS),
+        ];
+        assert!(check_receipt_batch_size(batch.len()).is_ok());
+        assert!(batch.iter().all(|r| check_receipt_closable(r, &user, now).is_ok()));
+
+        // Another user's receipt fails the batch
+        let foreign = receipt(Pubkey::new_unique(), 0);
+        assert_eq!(
+            check_receipt_closable(&foreign, &user, now).unwrap_err(),
+            FlowMintError::Unauthorized.into()
+        );
+
+        // Still inside the retention window
+        let recent = receipt(user, now - RECEIPT_RETENTION_SECONDS + 1);
+        assert_eq!(
+            check_receipt_closable(&recent, &user, now).unwrap_err(),
+            FlowMintError::ReceiptTooRecent.into()
+        );
+
+        assert!(check_receipt_batch_size(0).is_err());
+        assert!(check_receipt_batch_size(MAX_RECEIPTS_PER_BATCH).is_ok());
+        assert!(check_receipt_batch_size(MAX_RECEIPTS_PER_BATCH + 1).is_err());
+    }
+
+    #[test]
+    fn test_user_stats_view() {
+        let stats = UserStats {
+            user: Pubkey::new_unique(),
+            total_swaps: 3,
+            total_payments: 1,
+            total_volume_usd: 1_000_000_000,
+            last_activity: 1_000,
+            ..Default::default()
+        };
+
+        let view = derive_user_stats_view(&stats, 4_600);
+        assert_eq!(view.user, stats.user);
+        assert_eq!(view.total_operations, 4);
+        assert_eq!(view.average_volume_usd, 250_000_000);
+        assert_eq!(view.last_activity_age_seconds, 3_600);
+
+        // No operations yet
+        let view = derive_user_stats_view(&UserStats::default(), 0);
+        assert_eq!(view.average_volume_usd, 0);
+    }
+}
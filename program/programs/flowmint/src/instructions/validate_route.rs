@@ -0,0 +1,498 @@
+//! Validate Route Instruction
+//!
+//! Dry-run the swap route checks without executing a CPI, so clients can
+//! reject a bad route before building a full swap transaction.
+
+use anchor_lang::prelude::*;
+use anchor_spl::token::Mint;
+
+use crate::instructions::swap::{validate_swap, SwapMarket, SwapRequest};
+use crate::jupiter::{load_route, JupiterRoute};
+use crate::state::{AmmAllowlist, PriceTwap, ProtocolConfig, UserStats};
+
+/// Accounts for `validate_route`
+#[derive(Accounts)]
+pub struct ValidateRoute<'info> {
+    /// Protocol configuration
+    #[account(
+        seeds = [b"config"],
+        bump = config.bump
+    )]
+    pub config: Account<'info, ProtocolConfig>,
+
+    /// Input token mint
+    /// CHECK: Key compared against the route; decimals read as an SPL mint
+    pub input_mint: AccountInfo<'info>,
+
+    /// Output token mint
+    /// CHECK: Key compared against the route; decimals read as an SPL mint
+    pub output_mint: AccountInfo<'info>,
+
+    /// AMM allowlist (required while the allowlist is enabled)
+    #[account(seeds = [b"amm_allowlist"], bump = amm_allowlist.bump)]
+    pub amm_allowlist: Option<Account<'info, AmmAllowlist>>,
+
+    /// Stats of the user the route is checked for (optional; applies their
+    /// "always protected" flag and fee rate)
+    pub user_stats: Option<Account<'info, UserStats>>,
+
+    /// Pyth price feed for the input token (optional, used in protected mode)
+    /// CHECK: Owner and layout validated when read
+    pub input_price_feed: Option<UncheckedAccount<'info>>,
+
+    /// Pyth price feed for the output token (optional, used in protected mode)
+    /// CHECK: Owner and layout validated when read
+    pub output_price_feed: Option<UncheckedAccount<'info>>,
+
+    /// On-chain TWAP for the pair (required in protected mode; may not have
+    /// been created by a swap yet)
+    /// CHECK: PDA checked by seeds; owner and layout validated when read
+    #[account(
+        seeds = [b"twap", input_mint.key().as_ref(), output_mint.key().as_ref()],
+        bump
+    )]
+    pub price_twap: Option<UncheckedAccount<'info>>,
+}
+
+/// Route check that failed validation
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RouteCheck {
+    /// Slippage exceeds the (protected) limit
+    Slippage,
+    /// Route mints, amounts, slippage or fees don't match the request
+    RouteParameters,
+    /// Quote TTL exceeds the maximum or the quote has expired
+    QuoteFreshness,
+    /// Route touches a non-allowlisted AMM
+    AmmAllowlist,
+    /// Fee-derived price impact exceeds the maximum in protected mode
+    PriceImpact,
+    /// Route has more hops than protected mode allows
+    TooManyHops,
+    /// Too many remaining accounts
+    RemainingAccounts,
+    /// Minimum output (or the route-derived one for a `0` sentinel) is zero
+    MinimumAmountOut,
+    /// A hop's connecting mint needs a token program the swap doesn't pass
+    HopTokenPrograms,
+    /// Minimum output looks scaled by the wrong mint's decimals
+    MinimumOutScale,
+    /// Route's quoted rate is implausible for the mints' decimals
+    RouteRate,
+    /// Price feeds are missing, unregistered or invalid, or the quote deviates
+    /// from the oracle price in protected mode
+    Oracle,
+    /// Quote deviates from the on-chain TWAP in protected mode
+    Twap,
+    /// Quote is below the requested share of the oracle fair value
+    FairValueFloor,
+}
+
+/// A failed route check and the error the swap fails with
+#[derive(Debug)]
+pub struct RouteRejection {
+    /// Check that failed
+    pub check: RouteCheck,
+    /// Error reported by the swap instructions
+    pub error: Error,
+}
+
+impl RouteRejection {
+    /// Attribute an error to `check`
+    pub fn of(check: RouteCheck) -> impl FnOnce(Error) -> Self {
+        move |error| Self { check, error }
+    }
+
+    /// Fail `check` with `error` unless `condition` holds
+    pub fn ensure(
+        condition: bool,
+        check: RouteCheck,
+        error: impl Into<Error>,
+    ) -> std::result::Result<(), Self> {
+        if condition {
+            Ok(())
+        } else {
+            Err(Self {
+                check,
+                error: error.into(),
+            })
+        }
+    }
+}
+
+impl From<RouteRejection> for Error {
+    fn from(rejection: RouteRejection) -> Self {
+        rejection.error
+    }
+}
+
+/// Swap request a dry run validates, as `execute_swap` would build it
+///
+/// The user's stats, when given, force protected mode for "always protected"
+/// users and set the fee rate the `0` minimum sentinel is resolved against.
+#[allow(clippy::too_many_arguments)]
+pub fn route_request(
+    config: &ProtocolConfig,
+    user_stats: Option<&UserStats>,
+    input_mint: Pubkey,
+    output_mint: Pubkey,
+    amount_in: u64,
+    minimum_amount_out: u64,
+    slippage_bps: u16,
+    protected_mode: bool,
+) -> SwapRequest {
+    let user_stats = user_stats.cloned().unwrap_or_default();
+    SwapRequest {
+        input_mint,
+        output_mint,
+        amount_in,
+        minimum_amount_out,
+        slippage_bps,
+        protected_mode: config.is_protected_required(protected_mode, user_stats.always_protected),
+        output_fee_bps: config.fee_bps_for_user(&user_stats),
+        min_out_bps_of_fair: None,
+    }
+}
+
+/// Run the swap route checks, returning the first one that fails
+///
+/// Runs `validate_swap`, the same pre-CPI checks the swap instructions use.
+pub fn first_failed_route_check(
+    config: &ProtocolConfig,
+    route: &JupiterRoute,
+    request: &SwapRequest,
+    market: &SwapMarket,
+) -> Option<RouteCheck> {
+    validate_swap(config, route, request, market)
+        .err()
+        .map(|rejection| rejection.check)
+}
+
+/// Validate a route against the config and emit the result
+///
+/// # Arguments
+///
+/// * `ctx` - ValidateRoute context
+/// * `amount_in` - Amount of input tokens to swap
+/// * `minimum_amount_out` - Minimum acceptable output amount; `0` derives it from the route
+/// * `slippage_bps` - Slippage tolerance in basis points
+/// * `protected_mode` - Validate as a protected-mode swap
+/// * `route_bytes` - Serialized route; when `None` it is read from the first remaining account
+pub fn validate_route_handler<'info>(
+    ctx: Context<'_, '_, 'info, 'info, ValidateRoute<'info>>,
+    amount_in: u64,
+    minimum_amount_out: u64,
+    slippage_bps: u16,
+    protected_mode: bool,
+    route_bytes: Option<Vec<u8>>,
+) -> Result<()> {
+    let clock = Clock::get()?;
+    let remaining_accounts = ctx.remaining_accounts;
+    let (route, first_swap_account) = load_route(route_bytes.as_deref(), remaining_accounts)?;
+
+    let input_decimals =
+        Mint::try_deserialize(&mut &ctx.accounts.input_mint.try_borrow_data()?[..])?.decimals;
+    let output_decimals =
+        Mint::try_deserialize(&mut &ctx.accounts.output_mint.try_borrow_data()?[..])?.decimals;
+    let request = route_request(
+        &ctx.accounts.config,
+        ctx.accounts.user_stats.as_deref(),
+        ctx.accounts.input_mint.key(),
+        ctx.accounts.output_mint.key(),
+        amount_in,
+        minimum_amount_out,
+        slippage_bps,
+        protected_mode,
+    );
+    let price_twap = ctx
+        .accounts
+        .price_twap
+        .as_ref()
+        .map(|account| PriceTwap::load(account))
+        .transpose()?;
+    let failed_check = first_failed_route_check(
+        &ctx.accounts.config,
+        &route,
+        &request,
+        &SwapMarket {
+            allowlist: ctx.accounts.amm_allowlist.as_deref(),
+            input_price_feed: ctx.accounts.input_price_feed.as_deref(),
+            output_price_feed: ctx.accounts.output_price_feed.as_deref(),
+            price_twap: price_twap.as_ref(),
+            remaining_accounts,
+            swap_accounts: &remaining_accounts[first_swap_account..],
+            input_decimals,
+            output_decimals,
+            current_timestamp: clock.unix_timestamp,
+        },
+    );
+
+    emit!(RouteValidation {
+        ok: failed_check.is_none(),
+        failed_check,
+    });
+
+    Ok(())
+}
+
+/// Event emitted by `validate_route`
+#[event]
+pub struct RouteValidation {
+    /// Whether every check passed
+    pub ok: bool,
+    /// First check that failed, if any
+    pub failed_check: Option<RouteCheck>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::jupiter::{AggregatorSource, RouteStep};
+
+    fn config() -> ProtocolConfig {
+        ProtocolConfig {
+            default_slippage_bps: 100,
+            protected_slippage_bps: 50,
+            max_price_impact_bps: 100,
+            max_quote_ttl_seconds: 60,
+            ..Default::default()
+        }
+    }
+
+    fn route(input_mint: Pubkey, output_mint: Pubkey, fee_amount: u64) -> JupiterRoute {
+        JupiterRoute {
+            input_mint,
+            output_mint,
+            in_amount: 1_000_000,
+            out_amount: 990_000,
+            slippage_bps: 50,
+            route_steps: vec![RouteStep {
+                program_id: Pubkey::new_unique(),
+                input_mint,
+                output_mint,
+                amount_in: 1_000_000,
+                amount_out: 990_000,
+                fee_amount,
+                fee_mint: input_mint,
+            }],
+            quote_timestamp: 1_000,
+            quote_expiration_seconds: 30,
+            source: AggregatorSource::Jupiter,
+            token_ledger: None,
+        }
+    }
+
+    fn request(input_mint: Pubkey, output_mint: Pubkey) -> SwapRequest {
+        SwapRequest {
+            input_mint,
+            output_mint,
+            amount_in: 1_000_000,
+            minimum_amount_out: 980_000,
+            slippage_bps: 50,
+            protected_mode: false,
+            output_fee_bps: 0,
+            min_out_bps_of_fair: None,
+        }
+    }
+
+    fn check(
+        config: &ProtocolConfig,
+        allowlist: Option<&AmmAllowlist>,
+        route: &JupiterRoute,
+        request: &SwapRequest,
+        current_timestamp: i64,
+    ) -> Option<RouteCheck> {
+        check_with_decimals(config, allowlist, route, request, current_timestamp, (6, 6))
+    }
+
+    fn check_with_decimals(
+        config: &ProtocolConfig,
+        allowlist: Option<&AmmAllowlist>,
+        route: &JupiterRoute,
+        request: &SwapRequest,
+        current_timestamp: i64,
+        (input_decimals, output_decimals): (u8, u8),
+    ) -> Option<RouteCheck> {
+        let market = SwapMarket {
+            allowlist,
+            input_price_feed: None,
+            output_price_feed: None,
+            price_twap: Some(&PriceTwap::default()),
+            remaining_accounts: &[],
+            swap_accounts: &[],
+            input_decimals,
+            output_decimals,
+            current_timestamp,
+        };
+        first_failed_route_check(config, route, request, &market)
+    }
+
+    #[test]
+    fn test_valid_route_passes() {
+        let (input, output) = (Pubkey::new_unique(), Pubkey::new_unique());
+        let route = route(input, output, 3_000);
+
+        let mut request = request(input, output);
+        assert_eq!(check(&config(), None, &route, &request, 1_010), None);
+
+        request.protected_mode = true;
+        assert_eq!(check(&config(), None, &route, &request, 1_010), None);
+
+        // The zero sentinel derives the minimum from the route
+        request.minimum_amount_out = 0;
+        assert_eq!(check(&config(), None, &route, &request, 1_010), None);
+    }
+
+    #[test]
+    fn test_each_failed_check_reported() {
+        let (input, output) = (Pubkey::new_unique(), Pubkey::new_unique());
+        let config = config();
+
+        // Slippage above the default limit
+        let mut req = request(input, output);
+        req.slippage_bps = 150;
+        assert_eq!(
+            check(&config, None, &route(input, output, 0), &req, 1_010),
+            Some(RouteCheck::Slippage)
+        );
+
+        // Route for a different input mint
+        let req = request(Pubkey::new_unique(), output);
+        assert_eq!(
+            check(&config, None, &route(input, output, 0), &req, 1_010),
+            Some(RouteCheck::RouteParameters)
+        );
+
+        // Expired quote
+        let req = request(input, output);
+        assert_eq!(
+            check(&config, None, &route(input, output, 0), &req, 2_000),
+            Some(RouteCheck::QuoteFreshness)
+        );
+
+        // Allowlist enabled but not provided
+        let allowlisted = ProtocolConfig {
+            amm_allowlist_enabled: true,
+            ..config
+        };
+        assert_eq!(
+            check(&allowlisted, None, &route(input, output, 0), &req, 1_010),
+            Some(RouteCheck::AmmAllowlist)
+        );
+
+        // 2% fee impact in protected mode
+        let mut req = request(input, output);
+        req.protected_mode = true;
+        assert_eq!(
+            check(&config, None, &route(input, output, 20_000), &req, 1_010),
+            Some(RouteCheck::PriceImpact)
+        );
+
+        // Two hops against a one-hop protected cap
+        let capped = ProtocolConfig {
+            max_hops_protected: 1,
+            ..config
+        };
+        let mut two_hops = route(input, output, 0);
+        let mut second_hop = two_hops.route_steps[0].clone();
+        second_hop.input_mint = Pubkey::new_unique();
+        two_hops.route_steps.push(second_hop);
+        assert_eq!(
+            check(&capped, None, &two_hops, &req, 1_010),
+            Some(RouteCheck::TooManyHops)
+        );
+        req.protected_mode = false;
+        assert_eq!(check(&capped, None, &two_hops, &req, 1_010), None);
+    }
+
+    #[test]
+    fn test_swap_checks_reported() {
+        let (input, output) = (Pubkey::new_unique(), Pubkey::new_unique());
+        let config = config();
+
+        // Minimum given in 9-decimal units for a 6-decimal output
+        let mut req = request(input, output);
+        req.minimum_amount_out = 980_000;
+        let mut route_9 = route(input, output, 0);
+        route_9.out_amount = 990_000_000;
+        route_9.route_steps[0].amount_out = 990_000_000;
+        assert_eq!(
+            check_with_decimals(&config, None, &route_9, &req, 1_010, (6, 9)),
+            Some(RouteCheck::MinimumOutScale)
+        );
+
+        // Between 6-decimal mints, 990 output tokens per input token is implausible
+        let rate_capped = ProtocolConfig {
+            max_route_rate_ratio: 100,
+            ..config
+        };
+        req.minimum_amount_out = 980_000_000;
+        assert_eq!(
+            check(&rate_capped, None, &route_9, &req, 1_010),
+            Some(RouteCheck::RouteRate)
+        );
+
+        // Global protected mode requires the price feeds
+        let protected = ProtocolConfig {
+            protected_mode_enabled: true,
+            ..config
+        };
+        let req = route_request(&protected, None, input, output, 1_000_000, 980_000, 50, false);
+        assert_eq!(
+            check(&protected, None, &route(input, output, 0), &req, 1_010),
+            Some(RouteCheck::Oracle)
+        );
+
+        // A fair-value floor can't be enforced without the feeds
+        let mut req = request(input, output);
+        req.protected_mode = true;
+        req.min_out_bps_of_fair = Some(9_900);
+        assert_eq!(
+            check(&config, None, &route(input, output, 0), &req, 1_010),
+            Some(RouteCheck::FairValueFloor)
+        );
+
+        // Protected swaps can't skip the TWAP band by omitting its account
+        req.min_out_bps_of_fair = None;
+        let no_twap = SwapMarket {
+            allowlist: None,
+            input_price_feed: None,
+            output_price_feed: None,
+            price_twap: None,
+            remaining_accounts: &[],
+            swap_accounts: &[],
+            input_decimals: 6,
+            output_decimals: 6,
+            current_timestamp: 1_010,
+        };
+        let route = route(input, output, 0);
+        assert_eq!(
+            first_failed_route_check(&config, &route, &req, &no_twap),
+            Some(RouteCheck::Twap)
+        );
+        req.protected_mode = false;
+        assert_eq!(first_failed_route_check(&config, &route, &req, &no_twap), None);
+    }
+
+    #[test]
+    fn test_route_request_respects_always_protected() {
+        let (input, output) = (Pubkey::new_unique(), Pubkey::new_unique());
+        let config = config();
+        let stats = UserStats {
+            always_protected: true,
+            ..Default::default()
+        };
+
+        let req =
+            route_request(&config, Some(&stats), input, output, 1_000_000, 980_000, 50, false);
+        assert!(req.protected_mode);
+        assert_eq!(
+            check(&config, None, &route(input, output, 20_000), &req, 1_010),
+            Some(RouteCheck::PriceImpact)
+        );
+
+        let req = route_request(&config, None, input, output, 1_000_000, 980_000, 50, false);
+        assert!(!req.protected_mode);
+        assert_eq!(check(&config, None, &route(input, output, 20_000), &req, 1_010), None);
+    }
+}
@@ -14,6 +14,8 @@ use anchor_lang::solana_program::{
     program::invoke_signed,
 };
 
+use crate::errors::FlowMintError;
+
 /// Jupiter V6 Program ID on mainnet
 pub const JUPITER_V6_PROGRAM_ID: Pubkey = Pubkey::new_from_array([
     // JUP6LkbZbjS1jKKwapdHNy74zcZ3tLUZoi5QNyVTaV4
@@ -42,6 +44,33 @@ pub struct RouteStep {
     pub fee_mint: Pubkey,
 }
 
+/// Aggregator a route was quoted by
+///
+/// Decides the CPI target and instruction encoding used to execute the
+/// route. Only Jupiter routes can be executed today; other sources are
+/// accepted by the encoding so they can be added without a format change.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum AggregatorSource {
+    /// Jupiter V6 aggregator
+    #[default]
+    Jupiter,
+    /// Orca Whirlpools (not yet executable)
+    Orca,
+}
+
+impl AggregatorSource {
+    /// Reject sources that have no CPI integration yet
+    pub fn ensure_supported(&self) -> Result<()> {
+        match self {
+            AggregatorSource::Jupiter => Ok(()),
+            other => {
+                msg!("Unsupported aggregator source: {:?}", other);
+                Err(JupiterError::UnsupportedAggregator.into())
+            }
+        }
+    }
+}
+
 /// Complete Jupiter route plan
 #[derive(AnchorSerialize, AnchorDeserialize, Clone, Debug)]
 pub struct JupiterRoute {
@@ -61,6 +90,11 @@ pub struct JupiterRoute {
     pub quote_timestamp: i64,
     /// Quote expiration in seconds
     pub quote_expiration_seconds: i64,
+    /// Aggregator that produced the quote
+    pub source: AggregatorSource,
+    /// Jupiter token ledger for ledger-based routes, whose input amount is
+    /// the balance change recorded by a preceding `set_token_ledger`
+    pub token_ledger: Option<Pubkey>,
 }
 
 impl JupiterRoute {
@@ -73,6 +107,17 @@ impl JupiterRoute {
         minimum_amount_out: u64,
         max_slippage_bps: u16,
     ) -> Result<()> {
+        self.source.ensure_supported()?;
+
+        // Same-mint routes are zero-hop no-ops; direct transfers have their own path
+        require!(
+            self.input_mint != self.output_mint,
+            JupiterError::InvalidRouteData
+        );
+
+        self.validate_fees()?;
+        self.validate_split_inputs()?;
+
         // Validate mints
         require!(
             self.input_mint == *expected_input_mint,
@@ -102,12 +147,242 @@ impl JupiterRoute {
         Ok(())
     }
 
+    /// Bound declared step fees so they cannot inflate the impact estimate
+    ///
+    /// Each step's fee must not exceed that step's input, and the total of
+    /// all step fees must not exceed the route's input.
+    pub fn validate_fees(&self) -> Result<()> {
+        let mut total_fee: u128 = 0;
+        for step in &self.route_steps {
+            require!(
+                step.fee_amount <= step.amount_in,
+                JupiterError::InvalidRouteData
+            );
+            total_fee += step.fee_amount as u128;
+        }
+
+        require!(
+            total_fee <= self.in_amount as u128,
+            JupiterError::InvalidRouteData
+        );
+
+        Ok(())
+    }
+
+    /// Ensure the first-hop legs of a (split) route add up to `in_amount`
+    ///
+    /// First-hop legs are the steps consuming the route's input mint; their
+    /// `amount_in` must sum exactly to the declared total. Routes without
+    /// steps have nothing to check.
+    pub fn validate_split_inputs(&self) -> Result<()> {
+        if self.route_steps.is_empty() {
+            return Ok(());
+        }
+
+        let mut first_hop_in: u64 = 0;
+        for step in self
+            .route_steps
+            .iter()
+            .filter(|step| step.input_mint == self.input_mint)
+        {
+            first_hop_in = first_hop_in
+                .checked_add(step.amount_in)
+                .ok_or(JupiterError::InvalidRouteData)?;
+        }
+
+        require!(
+            first_hop_in == self.in_amount,
+            JupiterError::AmountMismatch
+        );
+
+        Ok(())
+    }
+
+    /// Ensure every route step uses an allowed AMM program
+    pub fn validate_amms(&self, is_allowed: impl Fn(&Pubkey) -> bool) -> Result<()> {
+        require!(
+            self.route_steps.iter().all(|step| is_allowed(&step.program_id)),
+            JupiterError::DisallowedAmm
+        );
+        Ok(())
+    }
+
+    /// Estimated price impact of the route in basis points
+    ///
+    /// See `simulate_price_impact`.
+    pub fn price_impact_bps(&self) -> u16 {
+        simulate_price_impact(self)
+    }
+
+    /// Minimum output implied by the quote after its slippage tolerance
+    ///
+    /// `out_amount * (10_000 - slippage_bps) / 10_000`, rounded down.
+    pub fn effective_min_out(&self) -> u64 {
+        let keep_bps = 10_000u128.saturating_sub(self.slippage_bps as u128);
+        (self.out_amount as u128 * keep_bps / 10_000) as u64
+    }
+
+    /// Input required to receive `target_out` at the quoted rate after slippage
+    ///
+    /// Inverts the quote's rate, rounding up, then scales by
+    /// `10_000 / (10_000 - slippage_bps)` so that the worst-case output
+    /// still covers `target_out`.
+    pub fn required_in_for_out(&self, target_out: u64) -> Result<u64> {
+        require!(
+            self.in_amount > 0 && self.out_amount > 0 && self.slippage_bps < 10_000,
+            JupiterError::InvalidRouteData
+        );
+
+        let base_in = (target_out as u128)
+            .checked_mul(self.in_amount as u128)
+            .ok_or(FlowMintError::MathOverflow)?
+            .div_ceil(self.out_amount as u128);
+        let required_in = base_in
+            .checked_mul(10_000)
+            .ok_or(FlowMintError::MathOverflow)?
+            .div_ceil(10_000 - self.slippage_bps as u128);
+
+        u64::try_from(required_in).map_err(|_| FlowMintError::MathOverflow.into())
+    }
+
+    /// Most input an ExactOut execution of the route may spend
+    ///
+    /// Jupiter caps ExactOut input at the quoted `in_amount` plus the route's
+    /// slippage: `in_amount * (10_000 + slippage_bps) / 10_000`, rounded up.
+    pub fn max_in_for_exact_out(&self) -> Result<u64> {
+        let max_in = (self.in_amount as u128)
+            .checked_mul(10_000 + self.slippage_bps as u128)
+            .ok_or(FlowMintError::MathOverflow)?
+            .div_ceil(10_000);
+
+        u64::try_from(max_in).map_err(|_| FlowMintError::MathOverflow.into())
+    }
+
+    /// Validate the route as an ExactOut swap
+    ///
+    /// The route must quote exactly `exact_amount_out`, and its worst-case
+    /// input must fit within `max_amount_in`. Ledger routes are ExactIn only.
+    pub fn validate_exact_out(&self, exact_amount_out: u64, max_amount_in: u64) -> Result<()> {
+        require!(self.token_ledger.is_none(), JupiterError::InvalidRouteData);
+        require!(
+            self.out_amount == exact_amount_out,
+            JupiterError::AmountMismatch
+        );
+        require!(
+            self.max_in_for_exact_out()? <= max_amount_in,
+            JupiterError::MaxInputExceeded
+        );
+
+        Ok(())
+    }
+
     /// Check if the quote has expired
-    pub fn is_expired(&self, current_timestamp: i64) -> bool {
-        current_timestamp > self.quote_timestamp + self.quote_expiration_seconds
+    ///
+    /// `grace_seconds` absorbs small validator clock skew past the expiration.
+    pub fn is_expired(&self, current_timestamp: i64, grace_seconds: i64) -> bool {
+        current_timestamp
+            > self
+                .quote_timestamp
+                .saturating_add(self.quote_expiration_seconds)
+                .saturating_add(grace_seconds)
+    }
+
+    /// Check the quote against the protocol's freshness policy
+    ///
+    /// Returns the rejection to report if the quote's TTL exceeds
+    /// `max_ttl_seconds` or the quote has expired beyond `grace_seconds`.
+    pub fn freshness_rejection(
+        &self,
+        max_ttl_seconds: i64,
+        grace_seconds: i64,
+        current_timestamp: i64,
+    ) -> Option<QuoteRejected> {
+        let reason = if self.quote_expiration_seconds > max_ttl_seconds {
+            QuoteRejectionReason::TtlExceeded
+        } else if self.is_expired(current_timestamp, grace_seconds) {
+            QuoteRejectionReason::Expired
+        } else {
+            return None;
+        };
+
+        Some(QuoteRejected {
+            reason,
+            quote_timestamp: self.quote_timestamp,
+            quote_expiration_seconds: self.quote_expiration_seconds,
+            now: current_timestamp,
+        })
     }
 }
 
+/// Estimated price impact of a route in basis points
+///
+/// Derived from the total step fees relative to the input amount,
+/// saturating at `u16::MAX`. Returns 0 for zero-amount routes. Shared by the
+/// on-chain handlers and off-chain simulators so both compute the same value.
+pub fn simulate_price_impact(route: &JupiterRoute) -> u16 {
+    if route.in_amount == 0 || route.out_amount == 0 {
+        return 0;
+    }
+
+    let total_fee: u128 = route
+        .route_steps
+        .iter()
+        .map(|s| s.fee_amount as u128)
+        .sum();
+    let impact_bps = total_fee.saturating_mul(10_000) / route.in_amount as u128;
+
+    u16::try_from(impact_bps).unwrap_or(u16::MAX)
+}
+
+/// Ensure a route's quote is fresh enough to execute
+///
+/// Emits `QuoteRejected` before failing with `QuoteExpired` so off-chain
+/// monitors can tell stale quotes apart from other failures.
+pub fn ensure_quote_fresh(
+    route: &JupiterRoute,
+    max_ttl_seconds: i64,
+    grace_seconds: i64,
+    current_timestamp: i64,
+) -> Result<()> {
+    if let Some(rejection) =
+        route.freshness_rejection(max_ttl_seconds, grace_seconds, current_timestamp)
+    {
+        msg!(
+            "Quote rejected: reason={:?} quote_timestamp={} ttl={} now={}",
+            rejection.reason,
+            rejection.quote_timestamp,
+            rejection.quote_expiration_seconds,
+            rejection.now
+        );
+        emit!(rejection);
+        return err!(FlowMintError::QuoteExpired);
+    }
+
+    Ok(())
+}
+
+/// Why a quote was rejected before execution
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum QuoteRejectionReason {
+    /// Quote TTL is longer than the protocol allows
+    TtlExceeded,
+    /// Quote is past its expiration
+    Expired,
+}
+
+/// Event emitted when a quote is rejected for freshness
+#[event]
+pub struct QuoteRejected {
+    /// Why the quote was rejected
+    pub reason: QuoteRejectionReason,
+    /// When the quote was produced
+    pub quote_timestamp: i64,
+    /// Quote TTL in seconds
+    pub quote_expiration_seconds: i64,
+    /// On-chain time at rejection
+    pub now: i64,
+}
+
 /// Jupiter-specific errors
 #[error_code]
 pub enum JupiterError {
@@ -137,6 +412,74 @@ pub enum JupiterError {
 
     #[msg("Route deserialization failed")]
     DeserializationFailed,
+
+    #[msg("Swap output is implausibly higher than quoted")]
+    ImplausibleOutput,
+
+    #[msg("Route uses an AMM program that is not allowlisted")]
+    DisallowedAmm,
+
+    #[msg("Jupiter's returned output does not match the measured output")]
+    ReturnedOutputMismatch,
+
+    #[msg("Route hop uses a token program not available to the swap")]
+    IncompatibleHopTokenProgram,
+
+    #[msg("Route was quoted by an aggregator that is not supported")]
+    UnsupportedAggregator,
+
+    #[msg("Token ledger required by the route is missing or invalid")]
+    InvalidTokenLedger,
+
+    #[msg("Swap destination account is not among the Jupiter accounts")]
+    InvalidDestination,
+
+    #[msg("Route may spend more input than the maximum allowed")]
+    MaxInputExceeded,
+}
+
+/// Discriminator of Jupiter's `route_with_token_ledger` instruction
+/// (`sha256("global:route_with_token_ledger")[..8]`)
+pub const JUPITER_ROUTE_WITH_TOKEN_LEDGER_DISCRIMINATOR: [u8; 8] =
+    [0x96, 0x56, 0x47, 0x74, 0xa7, 0x5d, 0x0e, 0x68];
+
+/// Jupiter ledger-based swap instruction data
+///
+/// Same as `JupiterSwapParams` without `in_amount`: Jupiter reads the input
+/// amount from the token ledger instead.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone)]
+pub struct JupiterLedgerSwapParams {
+    /// Route plan data (serialized)
+    pub route_plan: Vec<u8>,
+    /// Quoted output amount
+    pub quoted_out_amount: u64,
+    /// Slippage in basis points
+    pub slippage_bps: u16,
+    /// Platform fee in basis points (for FlowMint)
+    pub platform_fee_bps: u16,
+}
+
+/// Discriminator of Jupiter's `exact_out_route` instruction
+/// (`sha256("global:exact_out_route")[..8]`)
+pub const JUPITER_EXACT_OUT_ROUTE_DISCRIMINATOR: [u8; 8] =
+    [0xd0, 0x33, 0xef, 0x97, 0x7b, 0x2b, 0xed, 0x5c];
+
+/// Jupiter ExactOut swap instruction data
+///
+/// Jupiter delivers exactly `out_amount`, spending at most `quoted_in_amount`
+/// plus `slippage_bps`.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone)]
+pub struct JupiterExactOutParams {
+    /// Route plan data (serialized)
+    pub route_plan: Vec<u8>,
+    /// Exact output amount
+    pub out_amount: u64,
+    /// Quoted input amount
+    pub quoted_in_amount: u64,
+    /// Slippage in basis points
+    pub slippage_bps: u16,
+    /// Platform fee in basis points (for FlowMint)
+    pub platform_fee_bps: u16,
 }
 
 /// Jupiter swap instruction data
@@ -172,21 +515,68 @@ pub fn execute_jupiter_swap<'info>(
     route: &JupiterRoute,
     signer_seeds: Option<&[&[&[u8]]]>,
 ) -> Result<u64> {
-    // Build instruction data for Jupiter swap
-    let swap_data = JupiterSwapParams {
-        route_plan: route.try_to_vec().map_err(|_| JupiterError::DeserializationFailed)?,
-        in_amount: route.in_amount,
-        quoted_out_amount: route.out_amount,
-        slippage_bps: route.slippage_bps,
-        platform_fee_bps: 0, // FlowMint platform fee handled separately
+    check_token_ledger(route, accounts, jupiter_program.key)?;
+    let instruction = build_swap_instruction(jupiter_program.key, accounts, route)?;
+    invoke_swap(&instruction, accounts, signer_seeds)?;
+
+    // The actual output amount would be determined by reading the destination
+    // token account balance after the swap. This is handled by the caller.
+    Ok(route.out_amount)
+}
+
+/// Execute a Jupiter ExactOut swap via CPI
+///
+/// Delivers exactly the route's `out_amount`; the input spent is measured by
+/// the caller. See `execute_jupiter_swap` for the arguments.
+pub fn execute_jupiter_exact_out_swap<'info>(
+    jupiter_program: &AccountInfo<'info>,
+    accounts: &[AccountInfo<'info>],
+    route: &JupiterRoute,
+    signer_seeds: Option<&[&[&[u8]]]>,
+) -> Result<()> {
+    let instruction = Instruction {
+        program_id: *jupiter_program.key,
+        accounts: swap_account_metas(accounts),
+        data: encode_exact_out_instruction_data(route)?,
     };
+    invoke_swap(&instruction, accounts, signer_seeds)
+}
+
+/// Invoke a swap instruction, signed by `signer_seeds` when given
+fn invoke_swap(
+    instruction: &Instruction,
+    accounts: &[AccountInfo],
+    signer_seeds: Option<&[&[&[u8]]]>,
+) -> Result<()> {
+    match signer_seeds {
+        Some(seeds) => {
+            invoke_signed(instruction, accounts, seeds)
+                .map_err(|_| JupiterError::CpiInvocationFailed)?;
+        }
+        None => {
+            anchor_lang::solana_program::program::invoke(instruction, accounts)
+                .map_err(|_| JupiterError::CpiInvocationFailed)?;
+        }
+    }
+    Ok(())
+}
 
-    // Serialize instruction data
-    let mut instruction_data = vec![0u8]; // Discriminator for swap instruction
-    instruction_data.extend(swap_data.try_to_vec().map_err(|_| JupiterError::DeserializationFailed)?);
+/// Build the aggregator swap instruction from the swap accounts
+pub fn build_swap_instruction(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    route: &JupiterRoute,
+) -> Result<Instruction> {
+    Ok(Instruction {
+        program_id: *program_id,
+        accounts: swap_account_metas(accounts),
+        data: encode_swap_instruction_data(route)?,
+    })
+}
 
-    // Build account metas from remaining accounts
-    let account_metas: Vec<AccountMeta> = accounts
+/// Account metas passing the swap accounts through with their flags
+fn swap_account_metas(accounts: &[AccountInfo]) -> Vec<AccountMeta> {
+    accounts
         .iter()
         .map(|account| {
             if account.is_writable {
@@ -195,38 +585,206 @@ pub fn execute_jupiter_swap<'info>(
                 AccountMeta::new_readonly(*account.key, account.is_signer)
             }
         })
-        .collect();
+        .collect()
+}
 
-    // Create instruction
-    let instruction = Instruction {
-        program_id: *jupiter_program.key,
-        accounts: account_metas,
-        data: instruction_data,
+/// Check the swap's destination token account is among the Jupiter accounts
+///
+/// Output is measured as the balance change of `destination`; a route that
+/// omits it (or passes it read-only) cannot be depositing into it, so the
+/// swap is rejected before the CPI rather than after a shortfall.
+pub fn check_swap_destination(destination: &Pubkey, accounts: &[AccountInfo]) -> Result<()> {
+    let found = accounts
+        .iter()
+        .any(|account| account.key == destination && account.is_writable);
+    if !found {
+        msg!("Swap destination {} is not a writable Jupiter account", destination);
+        return Err(JupiterError::InvalidDestination.into());
+    }
+    Ok(())
+}
+
+/// Check the token ledger of a ledger-based route is passed to the swap
+///
+/// The ledger must be among the swap accounts, writable, and owned by the
+/// Jupiter program, which fills it in a preceding `set_token_ledger`.
+pub fn check_token_ledger(
+    route: &JupiterRoute,
+    accounts: &[AccountInfo],
+    jupiter_program_id: &Pubkey,
+) -> Result<()> {
+    let Some(token_ledger) = route.token_ledger else {
+        return Ok(());
     };
 
-    // Execute CPI
-    match signer_seeds {
-        Some(seeds) => {
-            invoke_signed(&instruction, accounts, seeds)
-                .map_err(|_| JupiterError::CpiInvocationFailed)?;
+    let ledger = accounts
+        .iter()
+        .find(|account| *account.key == token_ledger)
+        .ok_or(JupiterError::InvalidTokenLedger)?;
+    require!(
+        ledger.is_writable && ledger.owner == jupiter_program_id,
+        JupiterError::InvalidTokenLedger
+    );
+
+    Ok(())
+}
+
+/// Encode the swap instruction data for the route's aggregator
+pub fn encode_swap_instruction_data(route: &JupiterRoute) -> Result<Vec<u8>> {
+    match route.source {
+        AggregatorSource::Jupiter if route.token_ledger.is_some() => {
+            let swap_data = JupiterLedgerSwapParams {
+                route_plan: route.try_to_vec().map_err(|_| JupiterError::DeserializationFailed)?,
+                quoted_out_amount: route.out_amount,
+                slippage_bps: route.slippage_bps,
+                platform_fee_bps: 0, // FlowMint platform fee handled separately
+            };
+
+            let mut instruction_data = JUPITER_ROUTE_WITH_TOKEN_LEDGER_DISCRIMINATOR.to_vec();
+            instruction_data.extend(
+                swap_data.try_to_vec().map_err(|_| JupiterError::DeserializationFailed)?,
+            );
+            Ok(instruction_data)
         }
-        None => {
-            anchor_lang::solana_program::program::invoke(&instruction, accounts)
-                .map_err(|_| JupiterError::CpiInvocationFailed)?;
+        AggregatorSource::Jupiter => {
+            let swap_data = JupiterSwapParams {
+                route_plan: route.try_to_vec().map_err(|_| JupiterError::DeserializationFailed)?,
+                in_amount: route.in_amount,
+                quoted_out_amount: route.out_amount,
+                slippage_bps: route.slippage_bps,
+                platform_fee_bps: 0, // FlowMint platform fee handled separately
+            };
+
+            let mut instruction_data = vec![0u8]; // Discriminator for swap instruction
+            instruction_data.extend(
+                swap_data.try_to_vec().map_err(|_| JupiterError::DeserializationFailed)?,
+            );
+            Ok(instruction_data)
         }
+        _ => Err(JupiterError::UnsupportedAggregator.into()),
     }
+}
 
-    // The actual output amount would be determined by reading the destination
-    // token account balance after the swap. This is handled by the caller.
-    Ok(route.out_amount)
+/// Encode Jupiter's `exact_out_route` instruction data for the route
+///
+/// The route's `out_amount` is the exact output and its `in_amount` the
+/// quoted input. Ledger routes have no ExactOut form.
+pub fn encode_exact_out_instruction_data(route: &JupiterRoute) -> Result<Vec<u8>> {
+    route.source.ensure_supported()?;
+    require!(route.token_ledger.is_none(), JupiterError::InvalidRouteData);
+
+    let swap_data = JupiterExactOutParams {
+        route_plan: route.try_to_vec().map_err(|_| JupiterError::DeserializationFailed)?,
+        out_amount: route.out_amount,
+        quoted_in_amount: route.in_amount,
+        slippage_bps: route.slippage_bps,
+        platform_fee_bps: 0, // FlowMint platform fee handled separately
+    };
+
+    let mut instruction_data = JUPITER_EXACT_OUT_ROUTE_DISCRIMINATOR.to_vec();
+    instruction_data
+        .extend(swap_data.try_to_vec().map_err(|_| JupiterError::DeserializationFailed)?);
+    Ok(instruction_data)
 }
 
 /// Deserialize Jupiter route from remaining accounts data
 ///
 /// The route is expected to be passed as the first remaining account's data
-/// or as instruction data appended after the standard parameters.
+/// or as instruction data appended after the standard parameters. Routes
+/// serialized before `source` or `token_ledger` existed lack those trailing
+/// fields and are read as non-ledger Jupiter routes.
 pub fn deserialize_route(data: &[u8]) -> Result<JupiterRoute> {
-    JupiterRoute::try_from_slice(data).map_err(|_| JupiterError::DeserializationFailed.into())
+    // Each padding appends the defaults of the missing trailing fields:
+    // `token_ledger = None`, then `source = Jupiter` as well
+    let paddings: [&[u8]; 3] = [&[], &[0], &[AggregatorSource::Jupiter as u8, 0]];
+    paddings
+        .iter()
+        .find_map(|padding| JupiterRoute::try_from_slice(&[data, padding].concat()).ok())
+        .ok_or_else(|| JupiterError::DeserializationFailed.into())
+}
+
+/// Load the route from inline instruction bytes or the first remaining account
+///
+/// # Arguments
+/// * `route_bytes` - Serialized route passed as instruction data, if any
+/// * `remaining_accounts` - Instruction remaining accounts
+///
+/// # Returns
+/// The route and the index in `remaining_accounts` where the Jupiter swap
+/// accounts begin (0 for inline routes, 1 when the route is an account).
+pub fn load_route(
+    route_bytes: Option<&[u8]>,
+    remaining_accounts: &[AccountInfo],
+) -> Result<(JupiterRoute, usize)> {
+    if let Some(bytes) = route_bytes {
+        return Ok((deserialize_route(bytes)?, 0));
+    }
+
+    let route_account = remaining_accounts
+        .first()
+        .ok_or(FlowMintError::InvalidInstructionData)?;
+    let route = deserialize_route(&route_account.try_borrow_data()?)?;
+
+    Ok((route, 1))
+}
+
+/// Ensure each chained hop's connecting mint can move between the two steps
+///
+/// For adjacent steps where one step's output mint is the next step's input
+/// mint, the connecting mint must be owned by SPL Token or Token-2022, and
+/// that program must be available to the swap. Mixing a legacy and a
+/// Token-2022 mint across hops otherwise fails opaquely mid-route. Mints
+/// whose owner is unknown (`token_program_of` returns `None`) and split legs
+/// that don't chain are skipped.
+pub fn validate_hop_token_programs(
+    route: &JupiterRoute,
+    token_program_of: impl Fn(&Pubkey) -> Option<Pubkey>,
+    available_programs: &[Pubkey],
+) -> Result<()> {
+    for hop in route.route_steps.windows(2) {
+        let connecting_mint = hop[0].output_mint;
+        if connecting_mint != hop[1].input_mint {
+            continue;
+        }
+
+        let Some(program) = token_program_of(&connecting_mint) else {
+            continue;
+        };
+        let is_token_program =
+            program == anchor_spl::token::ID || program == anchor_spl::token_2022::ID;
+        if !is_token_program || !available_programs.contains(&program) {
+            msg!(
+                "Hop through mint {} needs token program {}",
+                connecting_mint,
+                program
+            );
+            return Err(JupiterError::IncompatibleHopTokenProgram.into());
+        }
+    }
+
+    Ok(())
+}
+
+/// Run `validate_hop_token_programs` against the Jupiter swap accounts
+///
+/// Mint owners and available token programs are read from `accounts`.
+pub fn check_hop_token_programs(route: &JupiterRoute, accounts: &[AccountInfo]) -> Result<()> {
+    let available_programs: Vec<Pubkey> = accounts
+        .iter()
+        .map(|account| *account.key)
+        .filter(|key| *key == anchor_spl::token::ID || *key == anchor_spl::token_2022::ID)
+        .collect();
+
+    validate_hop_token_programs(
+        route,
+        |mint| {
+            accounts
+                .iter()
+                .find(|account| account.key == mint)
+                .map(|account| *account.owner)
+        },
+        &available_programs,
+    )
 }
 
 /// Calculate actual slippage after a swap
@@ -255,11 +813,13 @@ pub fn calculate_actual_slippage(expected_out: u64, actual_out: u64) -> i32 {
 /// * `minimum_out` - Minimum acceptable output
 /// * `max_slippage_bps` - Maximum allowed slippage
 /// * `expected_out` - Expected output from quote
+/// * `max_positive_slippage_bps` - Maximum allowed improvement over the quote (0 = unbounded)
 pub fn verify_swap_output(
     actual_out: u64,
     minimum_out: u64,
     max_slippage_bps: u16,
     expected_out: u64,
+    max_positive_slippage_bps: u16,
 ) -> Result<()> {
     // Check minimum output
     require!(
@@ -275,6 +835,133 @@ pub fn verify_swap_output(
         return Err(JupiterError::SlippageExceeded.into());
     }
 
+    // An output far above the quote indicates a manipulated pool or an
+    // accounting bug (e.g. fee-on-transfer) rather than genuine luck
+    if max_positive_slippage_bps > 0 && actual_slippage > max_positive_slippage_bps as i32 {
+        return Err(JupiterError::ImplausibleOutput.into());
+    }
+
+    Ok(())
+}
+
+/// Decode the output amount Jupiter v6 returns from a swap
+///
+/// Jupiter's `SwapEvent`s are emitted as self-CPI event logs, which the
+/// calling program can't read; the route instructions instead set their
+/// return data to the output amount as a little-endian `u64`. Returns `None`
+/// for data of any other length.
+pub fn parse_jupiter_swap_event(data: &[u8]) -> Option<u64> {
+    data.try_into().ok().map(u64::from_le_bytes)
+}
+
+/// Output amount returned by the Jupiter CPI, if any
+///
+/// Only return data set by `jupiter_program_id` is considered.
+pub fn returned_out_amount(jupiter_program_id: &Pubkey) -> Option<u64> {
+    let (program_id, data) = anchor_lang::solana_program::program::get_return_data()?;
+    if program_id != *jupiter_program_id {
+        return None;
+    }
+    parse_jupiter_swap_event(&data)
+}
+
+/// Cross-check Jupiter's returned output against the measured balance delta
+///
+/// A mismatch points at fee-on-transfer mints or other accounting edge
+/// cases.
+pub fn cross_check_returned_out(returned_out: u64, measured_out: u64) -> Result<()> {
+    if returned_out != measured_out {
+        msg!(
+            "Jupiter returned {} out, measured {}",
+            returned_out,
+            measured_out
+        );
+        return Err(JupiterError::ReturnedOutputMismatch.into());
+    }
+
+    Ok(())
+}
+
+/// Sanity-check the decimal scale of a caller-supplied minimum output
+///
+/// A minimum that differs from the quoted output by at least half the decimal
+/// gap between the two mints (e.g. 500x for a 6 vs 9 decimal pair) almost
+/// certainly came from a client using the wrong mint's decimals.
+///
+/// # Arguments
+/// * `minimum_out` - Caller-supplied minimum output
+/// * `expected_out` - Output quoted by the route for the swapped amount
+/// * `input_decimals` - Decimals of the input mint
+/// * `output_decimals` - Decimals of the output mint
+pub fn check_minimum_out_scale(
+    minimum_out: u64,
+    expected_out: u64,
+    input_decimals: u8,
+    output_decimals: u8,
+) -> Result<()> {
+    let decimal_gap = input_decimals.abs_diff(output_decimals) as u32;
+    if decimal_gap == 0 || expected_out == 0 {
+        return Ok(());
+    }
+
+    let threshold = 10u128.saturating_pow(decimal_gap) / 2;
+    let minimum_out = minimum_out as u128;
+    let expected_out = expected_out as u128;
+
+    require!(
+        minimum_out.saturating_mul(threshold) > expected_out
+            && minimum_out < expected_out.saturating_mul(threshold),
+        FlowMintError::InvalidConfiguration
+    );
+
+    Ok(())
+}
+
+/// Reject routes whose implied exchange rate is absurd for the mints' decimals
+///
+/// The rate is normalized to whole tokens (`out / in`, adjusted by the
+/// decimal difference) and must lie within `[1 / max_ratio, max_ratio]`.
+/// A same-decimals route paying out a million times its input is far more
+/// likely a decimals bug or a manipulated quote than a real price. The band
+/// should be wide enough for legitimate high-ratio pairs; 0 disables the check.
+///
+/// # Arguments
+/// * `in_amount` - Route input amount
+/// * `out_amount` - Route quoted output amount
+/// * `input_decimals` - Decimals of the input mint
+/// * `output_decimals` - Decimals of the output mint
+/// * `max_ratio` - Maximum normalized rate, or inverse rate (0 = disabled)
+pub fn check_route_rate_plausible(
+    in_amount: u64,
+    out_amount: u64,
+    input_decimals: u8,
+    output_decimals: u8,
+    max_ratio: u64,
+) -> Result<()> {
+    if max_ratio == 0 || in_amount == 0 {
+        return Ok(());
+    }
+
+    // Compare out * 10^in_dec against in * 10^out_dec to stay in integers
+    let out_scaled = (out_amount as u128)
+        .saturating_mul(10u128.saturating_pow(input_decimals as u32));
+    let in_scaled = (in_amount as u128)
+        .saturating_mul(10u128.saturating_pow(output_decimals as u32));
+    let max_ratio = max_ratio as u128;
+
+    if out_scaled > in_scaled.saturating_mul(max_ratio)
+        || in_scaled > out_scaled.saturating_mul(max_ratio)
+    {
+        msg!(
+            "Implausible route rate: {} in ({} dp) for {} out ({} dp)",
+            in_amount,
+            input_decimals,
+            out_amount,
+            output_decimals
+        );
+        return Err(JupiterError::InvalidRouteData.into());
+    }
+
     Ok(())
 }
 
@@ -282,6 +969,338 @@ pub fn verify_swap_output(
 mod tests {
     use super::*;
 
+    #[test]
+    fn test_check_swap_destination() {
+        let owner = Pubkey::new_unique();
+        let (destination_key, other_key) = (Pubkey::new_unique(), Pubkey::new_unique());
+        let (mut lamports_a, mut lamports_b) = (0, 0);
+        let (mut data_a, mut data_b) = (vec![], vec![]);
+        let destination = AccountInfo::new(
+            &destination_key,
+            false,
+            true,
+            &mut lamports_a,
+            &mut data_a,
+            &owner,
+            false,
+            0,
+        );
+        let other = AccountInfo::new(
+            &other_key,
+            false,
+            true,
+            &mut lamports_b,
+            &mut data_b,
+            &owner,
+            false,
+            0,
+        );
+
+        let accounts = [other.clone(), destination.clone()];
+        assert!(check_swap_destination(&destination_key, &accounts).is_ok());
+
+        // The route deposits into some other account
+        assert_eq!(
+            check_swap_destination(&destination_key, &[other]).unwrap_err(),
+            JupiterError::InvalidDestination.into()
+        );
+
+        // Present but read-only
+        let mut read_only = destination;
+        read_only.is_writable = false;
+        assert!(check_swap_destination(&destination_key, &[read_only]).is_err());
+    }
+
+    #[test]
+    fn test_token_ledger_route() {
+        let jupiter = Pubkey::new_unique();
+        let ledger_key = Pubkey::new_unique();
+        let route = JupiterRoute {
+            token_ledger: Some(ledger_key),
+            ..route_with_fees(1_000, 900, &[])
+        };
+
+        // Ledger routes use `route_with_token_ledger` and omit `in_amount`
+        let data = encode_swap_instruction_data(&route).unwrap();
+        assert_eq!(data[..8], JUPITER_ROUTE_WITH_TOKEN_LEDGER_DISCRIMINATOR);
+        let params = JupiterLedgerSwapParams::try_from_slice(&data[8..]).unwrap();
+        assert_eq!(params.quoted_out_amount, 900);
+        assert_eq!(params.slippage_bps, route.slippage_bps);
+        assert_eq!(deserialize_route(&params.route_plan).unwrap().token_ledger, Some(ledger_key));
+
+        let authority_key = Pubkey::new_unique();
+        let (mut ledger_lamports, mut authority_lamports) = (0, 0);
+        let (mut ledger_data, mut authority_data) = (vec![], vec![]);
+        let ledger = AccountInfo::new(
+            &ledger_key,
+            false,
+            true,
+            &mut ledger_lamports,
+            &mut ledger_data,
+            &jupiter,
+            false,
+            0,
+        );
+        let authority = AccountInfo::new(
+            &authority_key,
+            true,
+            false,
+            &mut authority_lamports,
+            &mut authority_data,
+            &anchor_lang::system_program::ID,
+            false,
+            0,
+        );
+        let accounts = [authority, ledger];
+        assert!(check_token_ledger(&route, &accounts, &jupiter).is_ok());
+
+        let instruction = build_swap_instruction(&jupiter, &accounts, &route).unwrap();
+        assert_eq!(instruction.program_id, jupiter);
+        assert_eq!(instruction.data, data);
+        assert_eq!(
+            instruction.accounts,
+            vec![
+                AccountMeta::new_readonly(authority_key, true),
+                AccountMeta::new(ledger_key, false),
+            ]
+        );
+
+        // Missing, or not owned by Jupiter
+        assert!(check_token_ledger(&route, &accounts[..1], &jupiter).is_err());
+        assert!(check_token_ledger(&route, &accounts, &Pubkey::new_unique()).is_err());
+
+        // Regular routes keep the plain swap encoding
+        let plain = JupiterRoute {
+            token_ledger: None,
+            ..route
+        };
+        assert!(check_token_ledger(&plain, &[], &jupiter).is_ok());
+        assert_eq!(encode_swap_instruction_data(&plain).unwrap()[0], 0);
+    }
+
+    #[test]
+    fn test_exact_out_route() {
+        // Quoted 1_000 in for exactly 900 out, 1% slippage
+        let route = route_with_fees(1_000, 900, &[]);
+        let route = JupiterRoute {
+            slippage_bps: 100,
+            ..route
+        };
+        assert_eq!(route.max_in_for_exact_out().unwrap(), 1_010);
+
+        assert!(route.validate_exact_out(900, 1_010).is_ok());
+        // The cap must cover the slippage-adjusted input
+        assert_eq!(
+            route.validate_exact_out(900, 1_009).unwrap_err(),
+            JupiterError::MaxInputExceeded.into()
+        );
+        // The route must target the exact output
+        assert!(route.validate_exact_out(901, 2_000).is_err());
+
+        // Encoded as `exact_out_route` carrying the exact output and quoted input
+        let data = encode_exact_out_instruction_data(&route).unwrap();
+        assert_eq!(data[..8], JUPITER_EXACT_OUT_ROUTE_DISCRIMINATOR);
+        let params = JupiterExactOutParams::try_from_slice(&data[8..]).unwrap();
+        assert_eq!(params.out_amount, 900);
+        assert_eq!(params.quoted_in_amount, 1_000);
+        assert_eq!(params.slippage_bps, 100);
+
+        // Ledger routes are ExactIn only
+        let ledger = JupiterRoute {
+            token_ledger: Some(Pubkey::new_unique()),
+            ..route
+        };
+        assert!(ledger.validate_exact_out(900, 2_000).is_err());
+        assert!(encode_exact_out_instruction_data(&ledger).is_err());
+    }
+
+    #[test]
+    fn test_route_rate_plausible() {
+        // 1 SOL (9 dp) for 150 USDC (6 dp)
+        assert!(check_route_rate_plausible(1_000_000_000, 150_000_000, 9, 6, 1_000_000).is_ok());
+        // Same decimals, roughly 1:1
+        assert!(check_route_rate_plausible(1_000_000, 998_000, 6, 6, 1_000_000).is_ok());
+        // Same decimals, a million-fold payout points at a decimals bug
+        assert_eq!(
+            check_route_rate_plausible(1_000_000, 2_000_000_000_000, 6, 6, 1_000_000).unwrap_err(),
+            JupiterError::InvalidRouteData.into()
+        );
+        // The inverse direction is bounded too
+        assert!(check_route_rate_plausible(2_000_000_000_000, 1_000_000, 6, 6, 1_000_000).is_err());
+        // Exactly at the band edge passes
+        assert!(check_route_rate_plausible(1_000, 1_000_000_000, 6, 6, 1_000_000).is_ok());
+        // Disabled
+        assert!(check_route_rate_plausible(1, u64::MAX, 6, 6, 0).is_ok());
+    }
+
+    #[test]
+    fn test_aggregator_source() {
+        let route = route_with_fees(1_000, 900, &[]);
+        assert_eq!(route.source, AggregatorSource::Jupiter);
+        assert!(route
+            .validate(&route.input_mint, &route.output_mint, 1_000, 900, 100)
+            .is_ok());
+        assert!(encode_swap_instruction_data(&route).is_ok());
+
+        // Routes serialized without a source tag or ledger default to Jupiter
+        let mut legacy = route.try_to_vec().unwrap();
+        legacy.truncate(legacy.len() - 2);
+        let decoded = deserialize_route(&legacy).unwrap();
+        assert_eq!(decoded.source, AggregatorSource::Jupiter);
+        assert_eq!(decoded.token_ledger, None);
+
+        let orca = JupiterRoute {
+            source: AggregatorSource::Orca,
+            ..route.clone()
+        };
+        let round_trip = deserialize_route(&orca.try_to_vec().unwrap()).unwrap();
+        assert_eq!(round_trip.source, AggregatorSource::Orca);
+        assert_eq!(
+            orca.validate(&orca.input_mint, &orca.output_mint, 1_000, 900, 100)
+                .unwrap_err(),
+            JupiterError::UnsupportedAggregator.into()
+        );
+        assert_eq!(
+            encode_swap_instruction_data(&orca).unwrap_err(),
+            JupiterError::UnsupportedAggregator.into()
+        );
+
+        // Unknown source tags fail to decode
+        let mut unknown = route.try_to_vec().unwrap();
+        let source_index = unknown.len() - 2;
+        unknown[source_index] = 0xff;
+        assert!(deserialize_route(&unknown).is_err());
+    }
+
+    #[test]
+    fn test_validate_hop_token_programs() {
+        let usdc = Pubkey::new_unique();
+        let token22_mid = Pubkey::new_unique();
+        let sol = Pubkey::new_unique();
+        let step = |input_mint, output_mint| RouteStep {
+            program_id: Pubkey::new_unique(),
+            input_mint,
+            output_mint,
+            amount_in: 1_000,
+            amount_out: 1_000,
+            fee_amount: 0,
+            fee_mint: input_mint,
+        };
+        // Legacy USDC -> Token-2022 mint -> legacy SOL
+        let route = JupiterRoute {
+            input_mint: usdc,
+            output_mint: sol,
+            in_amount: 1_000,
+            out_amount: 1_000,
+            slippage_bps: 50,
+            route_steps: vec![step(usdc, token22_mid), step(token22_mid, sol)],
+            quote_timestamp: 1000,
+            quote_expiration_seconds: 30,
+            source: AggregatorSource::Jupiter,
+            token_ledger: None,
+        };
+        let owner = |mint: &Pubkey| {
+            if *mint == token22_mid {
+                Some(anchor_spl::token_2022::ID)
+            } else {
+                Some(anchor_spl::token::ID)
+            }
+        };
+
+        // Both programs available
+        let both = [anchor_spl::token::ID, anchor_spl::token_2022::ID];
+        assert!(validate_hop_token_programs(&route, owner, &both).is_ok());
+
+        // Only the legacy program: the Token-2022 hop can't be transferred
+        let legacy_only = [anchor_spl::token::ID];
+        assert!(validate_hop_token_programs(&route, owner, &legacy_only).is_err());
+
+        // Connecting mint not owned by a token program
+        let unknown_program = |_: &Pubkey| Some(Pubkey::new_unique());
+        assert!(validate_hop_token_programs(&route, unknown_program, &both).is_err());
+
+        // Unknown owners and non-chained split legs are skipped
+        assert!(validate_hop_token_programs(&route, |_| None, &legacy_only).is_ok());
+        let split = JupiterRoute {
+            route_steps: vec![step(usdc, sol), step(usdc, sol)],
+            ..route
+        };
+        assert!(validate_hop_token_programs(&split, owner, &legacy_only).is_ok());
+    }
+
+    #[test]
+    fn test_parse_and_cross_check_returned_out() {
+        // Jupiter v6 returns the output amount as a little-endian u64
+        let data = 990_000u64.to_le_bytes();
+        assert_eq!(parse_jupiter_swap_event(&data), Some(990_000));
+
+        // Truncated or padded data isn't an output amount
+        assert_eq!(parse_jupiter_swap_event(&data[..7]), None);
+        assert_eq!(parse_jupiter_swap_event(&[data.as_slice(), &[0]].concat()), None);
+        assert_eq!(parse_jupiter_swap_event(&[]), None);
+
+        // Measured delta matches the returned amount
+        assert!(cross_check_returned_out(990_000, 990_000).is_ok());
+
+        // Fee-on-transfer: less arrived than Jupiter returned
+        assert!(cross_check_returned_out(990_000, 985_000).is_err());
+    }
+
+    #[test]
+    fn test_validate_split_inputs() {
+        let input = Pubkey::new_unique();
+        let mid = Pubkey::new_unique();
+        let output = Pubkey::new_unique();
+        let step = |input_mint, output_mint, amount_in| RouteStep {
+            program_id: Pubkey::new_unique(),
+            input_mint,
+            output_mint,
+            amount_in,
+            amount_out: amount_in,
+            fee_amount: 0,
+            fee_mint: input_mint,
+        };
+        let mut route = JupiterRoute {
+            input_mint: input,
+            output_mint: output,
+            in_amount: 1_000_000,
+            out_amount: 990_000,
+            slippage_bps: 50,
+            // 60/40 split, one leg going through an intermediate mint
+            route_steps: vec![
+                step(input, output, 600_000),
+                step(input, mid, 400_000),
+                step(mid, output, 400_000),
+            ],
+            quote_timestamp: 1000,
+            quote_expiration_seconds: 30,
+            source: AggregatorSource::Jupiter,
+            token_ledger: None,
+        };
+        assert!(route.validate_split_inputs().is_ok());
+
+        // Declared total no longer matches the legs
+        route.in_amount = 1_200_000;
+        assert!(route.validate_split_inputs().is_err());
+
+        // Legs overflowing u64 are rejected
+        route.route_steps[0].amount_in = u64::MAX;
+        assert!(route.validate_split_inputs().is_err());
+    }
+
+    #[test]
+    fn test_effective_min_out() {
+        // 50 bps below the quoted output
+        assert_eq!(route_with_fees(1_000_000, 990_000, &[]).effective_min_out(), 985_050);
+
+        let mut route = route_with_fees(1_000_000, 999, &[]);
+        assert_eq!(route.effective_min_out(), 994);
+        route.slippage_bps = 0;
+        assert_eq!(route.effective_min_out(), 999);
+        route.slippage_bps = 10_000;
+        assert_eq!(route.effective_min_out(), 0);
+    }
+
     #[test]
     fn test_slippage_calculation() {
         // No slippage
@@ -294,6 +1313,138 @@ mod tests {
         assert_eq!(calculate_actual_slippage(1000, 990), -100); // -1%
     }
 
+    #[test]
+    fn test_verify_swap_output_positive_slippage() {
+        // Within bounds: 1% better than quoted with a 2% cap
+        assert!(verify_swap_output(1010, 950, 100, 1000, 200).is_ok());
+
+        // Beyond bounds: 5% better than quoted with a 2% cap
+        assert!(verify_swap_output(1050, 950, 100, 1000, 200).is_err());
+
+        // Unbounded when the cap is zero
+        assert!(verify_swap_output(2000, 950, 100, 1000, 0).is_ok());
+    }
+
+    #[test]
+    fn test_validate_rejects_same_mint_route() {
+        let mint = Pubkey::new_unique();
+        let route = JupiterRoute {
+            input_mint: mint,
+            output_mint: mint,
+            in_amount: 1000,
+            out_amount: 1000,
+            slippage_bps: 50,
+            route_steps: vec![],
+            quote_timestamp: 1000,
+            quote_expiration_seconds: 30,
+            source: AggregatorSource::Jupiter,
+            token_ledger: None,
+        };
+
+        assert!(route.validate(&mint, &mint, 1000, 1000, 100).is_err());
+    }
+
+    fn route_with_fees(in_amount: u64, out_amount: u64, fees: &[u64]) -> JupiterRoute {
+        JupiterRoute {
+            input_mint: Pubkey::new_unique(),
+            output_mint: Pubkey::new_unique(),
+            in_amount,
+            out_amount,
+            slippage_bps: 50,
+            route_steps: fees
+                .iter()
+                .map(|&fee_amount| RouteStep {
+                    program_id: Pubkey::new_unique(),
+                    input_mint: Pubkey::new_unique(),
+                    output_mint: Pubkey::new_unique(),
+                    amount_in: in_amount,
+                    amount_out: out_amount,
+                    fee_amount,
+                    fee_mint: Pubkey::new_unique(),
+                })
+                .collect(),
+            quote_timestamp: 1000,
+            quote_expiration_seconds: 30,
+            source: AggregatorSource::Jupiter,
+            token_ledger: None,
+        }
+    }
+
+    #[test]
+    fn test_load_route() {
+        let route = route_with_fees(1_000, 900, &[3]);
+        let bytes = route.try_to_vec().unwrap();
+
+        // Inline bytes: swap accounts start at index 0
+        let (loaded, first_swap_account) = load_route(Some(&bytes), &[]).unwrap();
+        assert_eq!(loaded.in_amount, 1_000);
+        assert_eq!(loaded.out_amount, 900);
+        assert_eq!(first_swap_account, 0);
+
+        // Route account: swap accounts start after it
+        let key = Pubkey::new_unique();
+        let owner = Pubkey::new_unique();
+        let mut lamports = 0;
+        let mut data = bytes.clone();
+        let account =
+            AccountInfo::new(&key, false, false, &mut lamports, &mut data, &owner, false, 0);
+        let (loaded, first_swap_account) = load_route(None, &[account]).unwrap();
+        assert_eq!(loaded.input_mint, route.input_mint);
+        assert_eq!(first_swap_account, 1);
+
+        // Neither inline bytes nor a route account
+        assert!(load_route(None, &[]).is_err());
+        assert!(load_route(Some(&[1, 2, 3]), &[]).is_err());
+    }
+
+    #[test]
+    fn test_validate_fees() {
+        // Reasonable 0.3% fee on each of two steps
+        assert!(route_with_fees(1_000_000, 990_000, &[3_000, 2_970]).validate_fees().is_ok());
+        assert!(route_with_fees(1_000_000, 990_000, &[]).validate_fees().is_ok());
+
+        // Step fee larger than the step input
+        assert!(route_with_fees(1_000, 900, &[1_001]).validate_fees().is_err());
+
+        // Each step fee fits, but the total exceeds the route input
+        assert!(route_with_fees(1_000, 900, &[600, 600]).validate_fees().is_err());
+        assert!(route_with_fees(1, 1, &[u64::MAX, u64::MAX]).validate_fees().is_err());
+    }
+
+    #[test]
+    fn test_simulate_price_impact() {
+        // Zero amounts
+        assert_eq!(simulate_price_impact(&route_with_fees(0, 0, &[1_000])), 0);
+
+        // Single hop: 0.25% fee
+        assert_eq!(simulate_price_impact(&route_with_fees(1_000_000, 990_000, &[2_500])), 25);
+
+        // Multi-hop fees aggregate
+        let route = route_with_fees(1_000_000, 990_000, &[2_500, 1_500, 1_000]);
+        assert_eq!(simulate_price_impact(&route), 50);
+        assert_eq!(simulate_price_impact(&route), route.price_impact_bps());
+
+        // Overflow-safe
+        let route = route_with_fees(1, 1, &[u64::MAX; 8]);
+        assert_eq!(simulate_price_impact(&route), u16::MAX);
+    }
+
+    #[test]
+    fn test_price_impact_bps() {
+        // 0.3% fee on a single step
+        assert_eq!(route_with_fees(1_000_000, 990_000, &[3_000]).price_impact_bps(), 30);
+
+        // Zero amounts yield zero impact
+        assert_eq!(route_with_fees(0, 990_000, &[3_000]).price_impact_bps(), 0);
+        assert_eq!(route_with_fees(1_000_000, 0, &[3_000]).price_impact_bps(), 0);
+
+        // Huge fees saturate instead of overflowing
+        assert_eq!(
+            route_with_fees(1, 1, &[u64::MAX, u64::MAX]).price_impact_bps(),
+            u16::MAX
+        );
+    }
+
     #[test]
     fn test_route_expiration() {
         let route = JupiterRoute {
@@ -305,12 +1456,89 @@ mod tests {
             route_steps: vec![],
             quote_timestamp: 1000,
             quote_expiration_seconds: 30,
+            source: AggregatorSource::Jupiter,
+            token_ledger: None,
         };
 
         // Not expired
-        assert!(!route.is_expired(1015));
+        assert!(!route.is_expired(1015, 0));
         
         // Expired
-        assert!(route.is_expired(1031));
+        assert!(route.is_expired(1031, 0));
+    }
+
+    #[test]
+    fn test_route_expiration_grace() {
+        let route = route_with_fees(1000, 900, &[]);
+
+        // Within the grace window
+        assert!(!route.is_expired(1031, 2));
+        assert!(!route.is_expired(1032, 2));
+
+        // Beyond the grace window
+        assert!(route.is_expired(1033, 2));
+    }
+
+    #[test]
+    fn test_minimum_out_scale() {
+        // 1 SOL (9 decimals) -> 150 USDC (6 decimals), 1% minimum
+        assert!(check_minimum_out_scale(148_500_000, 150_000_000, 9, 6).is_ok());
+
+        // Minimum given with SOL's 9 decimals instead of USDC's 6
+        assert!(check_minimum_out_scale(148_500_000_000, 150_000_000, 9, 6).is_err());
+
+        // Minimum given with 3 decimals too few
+        assert!(check_minimum_out_scale(148_500, 150_000_000, 9, 6).is_err());
+
+        // Same-decimal pairs are not checked
+        assert!(check_minimum_out_scale(1, 150_000_000, 6, 6).is_ok());
+    }
+
+    #[test]
+    fn test_validate_amms() {
+        let route = route_with_fees(1_000, 900, &[1, 2]);
+        let allowed: Vec<Pubkey> = route.route_steps.iter().map(|s| s.program_id).collect();
+
+        // Allowed-only route passes
+        assert!(route.validate_amms(|p| allowed.contains(p)).is_ok());
+
+        // Route with one disallowed AMM fails
+        assert!(route.validate_amms(|p| *p == allowed[0]).is_err());
+    }
+
+    #[test]
+    fn test_required_in_for_out() {
+        // 1_000 in -> 900 out, 0.5% slippage
+        let route = route_with_fees(1_000, 900, &[]);
+
+        for target_out in [1, 450, 900, 1_234_567] {
+            let required_in = route.required_in_for_out(target_out).unwrap() as u128;
+
+            // Worst-case output at the quoted rate after slippage covers the target
+            assert!(required_in * 900 * (10_000 - 50) >= target_out as u128 * 1_000 * 10_000);
+        }
+
+        assert_eq!(route.required_in_for_out(0).unwrap(), 0);
+        assert!(route_with_fees(1_000, 0, &[]).required_in_for_out(1).is_err());
+    }
+
+    #[test]
+    fn test_quote_freshness_rejection() {
+        let route = route_with_fees(1000, 900, &[]);
+
+        // Fresh quote
+        assert!(route.freshness_rejection(60, 0, 1015).is_none());
+        assert!(ensure_quote_fresh(&route, 60, 0, 1015).is_ok());
+
+        // Expired quote
+        let rejected = route.freshness_rejection(60, 0, 1031).unwrap();
+        assert_eq!(rejected.reason, QuoteRejectionReason::Expired);
+        assert_eq!(rejected.quote_timestamp, 1000);
+        assert_eq!(rejected.now, 1031);
+        assert!(ensure_quote_fresh(&route, 60, 0, 1031).is_err());
+
+        // TTL longer than allowed
+        let rejected = route.freshness_rejection(10, 0, 1015).unwrap();
+        assert_eq!(rejected.reason, QuoteRejectionReason::TtlExceeded);
     }
 }
@@ -7,6 +7,7 @@
 //! - **Swap Execution**: Execute token swaps via Jupiter routes with slippage protection
 //! - **Pay Any Token**: Convert any token to USDC for payments
 //! - **Protected Mode**: On-chain slippage validation and safety checks
+//! - **Oracle Checks**: Optional Pyth price sanity checks in protected mode
 //!
 //! ## Architecture
 //!
@@ -14,146 +15,456 @@
 //! cross-program invocations (CPI) to Jupiter's swap program.
 
 use anchor_lang::prelude::*;
-use anchor_spl::token::{Token, TokenAccount, Transfer};
 
 pub mod errors;
 pub mod instructions;
 pub mod jupiter;
+pub mod oracle;
 pub mod state;
+pub mod token_transfer;
+
+#[cfg(test)]
+mod test_runtime;
 
-use errors::FlowMintError;
 use instructions::*;
+use state::PausableFeature;
 
 declare_id!("CmPS9FdZQ4ex9A45yjvJGAjPBdBj6oYY9juQMfdzBJdi");
 
-/// The main FlowMint program module
-#[program]
-pub mod flowmint {
+// `#[program]` emits its IDL handlers next to the program module, and those still
+// call the deprecated `AccountInfo::realloc`; keep the allow confined to that output.
+#[allow(deprecated)]
+mod entrypoint {
     use super::*;
 
-    /// Initialize the FlowMint protocol configuration
-    ///
-    /// # Arguments
-    ///
-    /// * `ctx` - The context containing all accounts
-    /// * `default_slippage_bps` - Default maximum slippage in basis points
-    /// * `protected_slippage_bps` - Protected mode maximum slippage in basis points
-    /// * `max_price_impact_bps` - Maximum allowed price impact in basis points
-    ///
-    /// # Errors
-    ///
-    /// Returns an error if slippage values are invalid
-    pub fn initialize(
-        ctx: Context<Initialize>,
-        default_slippage_bps: u16,
-        protected_slippage_bps: u16,
-        max_price_impact_bps: u16,
-    ) -> Result<()> {
-        instructions::initialize::handler(
-            ctx,
-            default_slippage_bps,
-            protected_slippage_bps,
-            max_price_impact_bps,
-        )
-    }
+    /// The main FlowMint program module
+    #[program]
+    pub mod flowmint {
+        use super::*;
 
-    /// Execute a token swap through Jupiter
-    ///
-    /// This instruction validates the swap parameters against the protocol
-    /// configuration and executes the swap via CPI.
-    ///
-    /// # Arguments
-    ///
-    /// * `ctx` - The context containing all accounts
-    /// * `amount_in` - The amount of input tokens to swap
-    /// * `minimum_amount_out` - The minimum acceptable output amount
-    /// * `slippage_bps` - The slippage tolerance in basis points
-    /// * `protected_mode` - Whether to use protected mode (stricter limits)
-    ///
-    /// # Errors
-    ///
-    /// - `SlippageExceeded` if the slippage tolerance exceeds the allowed maximum
-    /// - `PriceImpactTooHigh` if the estimated price impact is too high
-    /// - `InsufficientBalance` if the user doesn't have enough tokens
-    pub fn execute_swap<'info>(
-        ctx: Context<'_, '_, 'info, 'info, ExecuteSwap<'info>>,
-        amount_in: u64,
-        minimum_amount_out: u64,
-        slippage_bps: u16,
-        protected_mode: bool,
-    ) -> Result<()> {
-        instructions::swap::execute_swap_handler(
-            ctx,
-            amount_in,
-            minimum_amount_out,
-            slippage_bps,
-            protected_mode,
-        )
-    }
+        /// Initialize the FlowMint protocol configuration
+        ///
+        /// # Arguments
+        ///
+        /// * `ctx` - The context containing all accounts
+        /// * `default_slippage_bps` - Default maximum slippage in basis points
+        /// * `protected_slippage_bps` - Protected mode maximum slippage in basis points
+        /// * `max_price_impact_bps` - Maximum allowed price impact in basis points
+        /// * `max_quote_ttl_seconds` - Maximum quote TTL accepted from routes
+        ///
+        /// # Errors
+        ///
+        /// Returns an error if slippage values are invalid
+        pub fn initialize(
+            ctx: Context<Initialize>,
+            default_slippage_bps: u16,
+            protected_slippage_bps: u16,
+            max_price_impact_bps: u16,
+            max_quote_ttl_seconds: i64,
+        ) -> Result<()> {
+            instructions::initialize::handler(
+                ctx,
+                default_slippage_bps,
+                protected_slippage_bps,
+                max_price_impact_bps,
+                max_quote_ttl_seconds,
+            )
+        }
 
-    /// Execute a payment by converting any token to USDC
-    ///
-    /// This instruction allows users to pay with any supported token,
-    /// which gets converted to USDC and sent to the merchant.
-    ///
-    /// # Arguments
-    ///
-    /// * `ctx` - The context containing all accounts
-    /// * `amount_in` - The amount of input tokens
-    /// * `exact_usdc_out` - The exact USDC amount the merchant should receive
-    /// * `memo` - Optional payment memo/reference
-    ///
-    /// # Errors
-    ///
-    /// - `PaymentFailed` if the swap or transfer fails
-    /// - `InsufficientBalance` if the payer doesn't have enough tokens
-    pub fn pay_any_token<'info>(
-        ctx: Context<'_, '_, 'info, 'info, PayAnyToken<'info>>,
-        amount_in: u64,
-        exact_usdc_out: u64,
-        memo: Option<String>,
-    ) -> Result<()> {
-        instructions::payment::pay_any_token_handler(ctx, amount_in, exact_usdc_out, memo)
-    }
+        /// Execute a token swap through Jupiter
+        ///
+        /// This instruction validates the swap parameters against the protocol
+        /// configuration and executes the swap via CPI.
+        ///
+        /// # Arguments
+        ///
+        /// * `ctx` - The context containing all accounts
+        /// * `amount_in` - The amount of input tokens to swap
+        /// * `minimum_amount_out` - The minimum output the user keeps after the protocol
+        ///   fee; `0` derives it from the route's quoted output and slippage
+        /// * `slippage_bps` - The slippage tolerance in basis points
+        /// * `protected_mode` - Whether to use protected mode (stricter limits); can only
+        ///   tighten protection, never disable the global or per-user setting
+        /// * `referrer` - Optional referrer receiving `referral_share_bps` of the protocol fee;
+        ///   must not be the user (`SelfReferral`)
+        /// * `route_bytes` - Serialized Jupiter route; when `None` the route is read from
+        ///   the first remaining account
+        /// * `client_order_id` - Optional caller order ID recorded on the receipt and
+        ///   `SwapExecuted` for reconciliation (need not be unique)
+        /// * `min_out_bps_of_fair` - Optional floor on the output as a share of the
+        ///   oracle-implied fair value, in basis points (protected mode only; requires
+        ///   price feeds)
+        ///
+        /// # Errors
+        ///
+        /// - `SlippageExceeded` if the slippage tolerance exceeds the allowed maximum
+        /// - `FeeImpactTooHigh` if the route's fee-derived impact is too high in
+        ///   protected mode
+        /// - `PriceImpactTooHigh` if the route deviates too far from the oracle or
+        ///   TWAP price in protected mode
+        /// - `InsufficientOutputAmount` if the output is below `min_out_bps_of_fair`
+        ///   of the oracle fair value
+        /// - `InvalidOracle` if a price feed isn't the mint's registered feed, or the
+        ///   feeds are missing while protected mode is enforced globally or the
+        ///   protected swap's mints both have registered feeds
+        /// - `OracleConfidenceTooWide` if a price's confidence interval is too wide
+        /// - `MissingPriceTwap` if a protected swap doesn't pass the pair's TWAP
+        /// - `InsufficientBalance` if the user doesn't have enough tokens
+        #[allow(clippy::too_many_arguments)]
+        pub fn execute_swap<'info>(
+            ctx: Context<'_, '_, 'info, 'info, ExecuteSwap<'info>>,
+            amount_in: u64,
+            minimum_amount_out: u64,
+            slippage_bps: u16,
+            protected_mode: bool,
+            referrer: Option<Pubkey>,
+            route_bytes: Option<Vec<u8>>,
+            client_order_id: Option<[u8; 16]>,
+            min_out_bps_of_fair: Option<u16>,
+        ) -> Result<()> {
+            instructions::swap::execute_swap_handler(
+                ctx,
+                amount_in,
+                minimum_amount_out,
+                slippage_bps,
+                protected_mode,
+                referrer,
+                route_bytes,
+                client_order_id,
+                min_out_bps_of_fair,
+            )
+        }
 
-    /// Update protocol configuration (admin only)
-    ///
-    /// # Arguments
-    ///
-    /// * `ctx` - The context containing all accounts
-    /// * `new_default_slippage_bps` - New default slippage, if updating
-    /// * `new_protected_slippage_bps` - New protected slippage, if updating
-    /// * `new_max_price_impact_bps` - New max price impact, if updating
-    pub fn update_config(
-        ctx: Context<UpdateConfig>,
-        new_default_slippage_bps: Option<u16>,
-        new_protected_slippage_bps: Option<u16>,
-        new_max_price_impact_bps: Option<u16>,
-        new_protocol_fee_bps: Option<u16>,
-        new_treasury: Option<Pubkey>,
-    ) -> Result<()> {
-        instructions::admin::update_config_handler(
-            ctx,
-            new_default_slippage_bps,
-            new_protected_slippage_bps,
-            new_max_price_impact_bps,
-            new_protocol_fee_bps,
-            new_treasury,
-        )
-    }
+        /// Swap up to `max_amount_in` of any token for exactly `exact_amount_out` of another
+        ///
+        /// The input is escrowed in a temp PDA account and swapped with Jupiter's
+        /// ExactOut route; unspent input is refunded after the swap, and the protocol
+        /// fee is charged on top of the spent input. Routes pass the same
+        /// protected-mode checks as `execute_swap`.
+        ///
+        /// # Arguments
+        ///
+        /// * `ctx` - The context containing all accounts
+        /// * `max_amount_in` - The maximum amount of input tokens to spend
+        /// * `exact_amount_out` - The exact amount of output tokens to receive
+        ///
+        /// # Errors
+        ///
+        /// - `InsufficientOutputAmount` if the swap delivers less than `exact_amount_out`
+        /// - `InsufficientBalance` if the user can't cover `max_amount_in` plus its fee
+        /// - `MaxInputExceeded` if the route may spend more than `max_amount_in`
+        pub fn swap_exact_out<'info>(
+            ctx: Context<'_, '_, 'info, 'info, SwapExactOut<'info>>,
+            max_amount_in: u64,
+            exact_amount_out: u64,
+        ) -> Result<()> {
+            instructions::swap_exact_out::swap_exact_out_handler(ctx, max_amount_in, exact_amount_out)
+        }
 
-    /// Toggle protected mode for the protocol
-    ///
-    /// # Arguments
-    ///
-    /// * `ctx` - The context containing all accounts
-    /// * `enabled` - Whether protected mode should be enabled globally
-    pub fn toggle_protected_mode(ctx: Context<UpdateConfig>, enabled: bool) -> Result<()> {
-        instructions::admin::toggle_protected_mode_handler(ctx, enabled)
-    }
+        /// Execute a payment by converting any token to USDC
+        ///
+        /// This instruction allows users to pay with any supported token,
+        /// which gets converted to USDC and sent to the merchant.
+        ///
+        /// # Arguments
+        ///
+        /// * `ctx` - The context containing all accounts
+        /// * `amount_in` - The amount of input tokens
+        /// * `exact_usdc_out` - The exact USDC amount the merchant should receive
+        /// * `memo` - Optional payment memo/reference
+        /// * `route_bytes` - Serialized Jupiter route; when `None` the route is read from
+        ///   the first remaining account
+        /// * `reference` - Optional merchant reference; creates a `PaymentReference` PDA
+        ///   (unique per merchant) pointing at the payment record
+        ///
+        /// # Errors
+        ///
+        /// - `PaymentFailed` if the swap or transfer fails
+        /// - `InsufficientBalance` if the payer doesn't have enough tokens
+        pub fn pay_any_token<'info>(
+            ctx: Context<'_, '_, 'info, 'info, PayAnyToken<'info>>,
+            amount_in: u64,
+            exact_usdc_out: u64,
+            memo: Option<String>,
+            route_bytes: Option<Vec<u8>>,
+            reference: Option<[u8; 32]>,
+        ) -> Result<()> {
+            instructions::payment::pay_any_token_handler(
+                ctx,
+                amount_in,
+                exact_usdc_out,
+                memo,
+                route_bytes,
+                reference,
+            )
+        }
+
+        /// Update protocol configuration (admin only)
+        ///
+        /// # Arguments
+        ///
+        /// * `ctx` - The context containing all accounts
+        /// * `params` - Configuration changes; `None` fields are left unchanged
+        pub fn update_config(ctx: Context<UpdateConfig>, params: UpdateConfigParams) -> Result<()> {
+            instructions::admin::update_config_handler(ctx, params)
+        }
+
+        /// Toggle protected mode for the protocol (authority or operator)
+        ///
+        /// Operators may only enable protected mode; disabling it requires the authority.
+        ///
+        /// # Arguments
+        ///
+        /// * `ctx` - The context containing all accounts
+        /// * `enabled` - Whether protected mode should be enabled globally
+        pub fn toggle_protected_mode(ctx: Context<OperatorAction>, enabled: bool) -> Result<()> {
+            instructions::admin::toggle_protected_mode_handler(ctx, enabled)
+        }
+
+        /// Register an operator allowed to perform lower-risk actions (admin only)
+        ///
+        /// # Arguments
+        ///
+        /// * `ctx` - The context containing all accounts
+        /// * `operator` - The operator pubkey to add
+        pub fn add_operator(ctx: Context<UpdateConfig>, operator: Pubkey) -> Result<()> {
+            instructions::admin::add_operator_handler(ctx, operator)
+        }
+
+        /// Remove a registered operator (admin only)
+        ///
+        /// # Arguments
+        ///
+        /// * `ctx` - The context containing all accounts
+        /// * `operator` - The operator pubkey to remove
+        pub fn remove_operator(ctx: Context<UpdateConfig>, operator: Pubkey) -> Result<()> {
+            instructions::admin::remove_operator_handler(ctx, operator)
+        }
+
+        /// Register or replace the Pyth feed ID expected for a mint (admin only)
+        ///
+        /// Oracle checks reject price accounts for any other feed, and mints
+        /// without a registered feed can't be oracle-checked.
+        pub fn set_oracle_feed(
+            ctx: Context<UpdateConfig>,
+            mint: Pubkey,
+            feed_id: [u8; 32],
+        ) -> Result<()> {
+            instructions::admin::set_oracle_feed_handler(ctx, mint, feed_id)
+        }
+
+        /// Remove a mint's registered Pyth feed ID (admin only)
+        pub fn remove_oracle_feed(ctx: Context<UpdateConfig>, mint: Pubkey) -> Result<()> {
+            instructions::admin::remove_oracle_feed_handler(ctx, mint)
+        }
+
+        /// Create the on-chain FeeVault token account for a mint (admin only)
+        ///
+        /// The vault is a PDA seeded by `[b"fee_vault", mint]` and owned by the config PDA.
+        /// SPL Token and Token-2022 mints are supported; swaps into a mint without a
+        /// vault are charged no protocol fee.
+        pub fn init_fee_vault(ctx: Context<InitFeeVault>) -> Result<()> {
+            instructions::admin::init_fee_vault_handler(ctx)
+        }
+
+        /// Withdraw accumulated protocol fees from a mint's FeeVault to the configured treasury.
+        pub fn withdraw_fees<'info>(
+            ctx: Context<'_, '_, 'info, 'info, WithdrawFees<'info>>,
+        ) -> Result<()> {
+            instructions::admin::withdraw_fees_handler(ctx)
+        }
+
+        /// Close the caller's `UserStats` account and reclaim its rent
+        ///
+        /// Only the owner of the stats account may close it. Closing drops any
+        /// `fee_exempt` grant; the authority must set it again on the new account.
+        pub fn close_user_stats(ctx: Context<CloseUserStats>) -> Result<()> {
+            instructions::user::close_user_stats_handler(ctx)
+        }
+
+        /// Pause or resume swaps or payments independently (authority or operator)
+        ///
+        /// Operators may only pause; resuming requires the authority.
+        ///
+        /// # Arguments
+        ///
+        /// * `ctx` - The context containing all accounts
+        /// * `feature` - The feature to pause or resume
+        /// * `paused` - Whether the feature should be paused
+        pub fn set_feature_paused(
+            ctx: Context<OperatorAction>,
+            feature: PausableFeature,
+            paused: bool,
+        ) -> Result<()> {
+            instructions::admin::set_feature_paused_handler(ctx, feature, paused)
+        }
+
+        /// Pause or resume both swaps and payments (authority or operator)
+        ///
+        /// Operators may only pause; resuming requires the authority.
+        pub fn set_paused(ctx: Context<OperatorAction>, paused: bool) -> Result<()> {
+            instructions::admin::set_paused_handler(ctx, paused)
+        }
+
+        /// Sweep residue left in a payer's temp USDC PDA (admin only)
+        ///
+        /// The balance is returned to a token account owned by the treasury or
+        /// by the original payer.
+        pub fn sweep_stranded_tokens<'info>(
+            ctx: Context<'_, '_, 'info, 'info, SweepStrandedTokens<'info>>,
+        ) -> Result<()> {
+            instructions::admin::sweep_stranded_tokens_handler(ctx)
+        }
+
+        /// Set the mint that payments settle in (admin only)
+        pub fn set_settlement_mint(ctx: Context<SetSettlementMint>) -> Result<()> {
+            instructions::admin::set_settlement_mint_handler(ctx)
+        }
 
-    /// Withdraw accumulated protocol fees (USDC) from the on-chain FeeVault to the configured treasury.
-    pub fn withdraw_fees(ctx: Context<WithdrawFees>) -> Result<()> {
-        instructions::admin::withdraw_fees_handler(ctx)
+        /// Force protected mode on all of the caller's swaps, regardless of the
+        /// per-swap `protected_mode` argument
+        pub fn set_always_protected(ctx: Context<SetAlwaysProtected>, enabled: bool) -> Result<()> {
+            instructions::user::set_always_protected_handler(ctx, enabled)
+        }
+
+        /// Replace the AMM allowlist and toggle its enforcement (admin only)
+        ///
+        /// # Arguments
+        ///
+        /// * `ctx` - The context containing all accounts
+        /// * `programs` - AMM program IDs route steps may use
+        /// * `enabled` - Whether the allowlist is enforced
+        pub fn set_amm_allowlist(
+            ctx: Context<SetAmmAllowlist>,
+            programs: Vec<Pubkey>,
+            enabled: bool,
+        ) -> Result<()> {
+            instructions::admin::set_amm_allowlist_handler(ctx, programs, enabled)
+        }
+
+        /// Emit a `UserStatsView` event with derived metrics (read-only)
+        pub fn get_user_stats(ctx: Context<GetUserStats>) -> Result<()> {
+            instructions::user::get_user_stats_handler(ctx)
+        }
+
+        /// Close a payment record signed by both payer and merchant
+        pub fn reclaim_payment_record(ctx: Context<ReclaimPaymentRecord>) -> Result<()> {
+            instructions::payment::reclaim_payment_record_handler(ctx)
+        }
+
+        /// Run the swap route checks without executing, emitting `RouteValidation`
+        ///
+        /// Uses the same checks as `execute_swap`. Pass the user's stats to apply
+        /// their "always protected" flag, and the price feeds and TWAP to run the
+        /// protected-mode price bands.
+        ///
+        /// # Arguments
+        ///
+        /// * `ctx` - The context containing all accounts
+        /// * `amount_in` - The amount of input tokens to swap
+        /// * `minimum_amount_out` - The minimum acceptable output amount; `0` derives it
+        ///   from the route
+        /// * `slippage_bps` - The slippage tolerance in basis points
+        /// * `protected_mode` - Validate as a protected-mode swap
+        /// * `route_bytes` - Serialized Jupiter route; when `None` the route is read from
+        ///   the first remaining account
+        pub fn validate_route<'info>(
+            ctx: Context<'_, '_, 'info, 'info, ValidateRoute<'info>>,
+            amount_in: u64,
+            minimum_amount_out: u64,
+            slippage_bps: u16,
+            protected_mode: bool,
+            route_bytes: Option<Vec<u8>>,
+        ) -> Result<()> {
+            instructions::validate_route::validate_route_handler(
+                ctx,
+                amount_in,
+                minimum_amount_out,
+                slippage_bps,
+                protected_mode,
+                route_bytes,
+            )
+        }
+
+        /// Rescue tokens sent to a config-owned account by mistake (admin only)
+        ///
+        /// The full balance is sent to the treasury. Fee vaults cannot be rescued.
+        pub fn rescue_tokens<'info>(
+            ctx: Context<'_, '_, 'info, 'info, RescueTokens<'info>>,
+        ) -> Result<()> {
+            instructions::admin::rescue_tokens_handler(ctx)
+        }
+
+        /// Propose a new treasury, applicable after the timelock (admin only)
+        pub fn propose_treasury(ctx: Context<ProposeTreasury>) -> Result<()> {
+            instructions::admin::propose_treasury_handler(ctx)
+        }
+
+        /// Apply the pending treasury once its timelock has elapsed (admin only)
+        pub fn apply_treasury(ctx: Context<UpdateConfig>) -> Result<()> {
+            instructions::admin::apply_treasury_handler(ctx)
+        }
+
+        /// Emit every config field as a versioned `ConfigSnapshot` event (read-only)
+        pub fn export_config(ctx: Context<ExportConfig>) -> Result<()> {
+            instructions::admin::export_config_handler(ctx)
+        }
+
+        /// Grant or revoke a user's protocol fee exemption (admin only)
+        pub fn set_fee_exempt(ctx: Context<SetFeeExempt>, exempt: bool) -> Result<()> {
+            instructions::admin::set_fee_exempt_handler(ctx, exempt)
+        }
+
+        /// Close the config and singleton accounts for devnet teardown (admin only)
+        ///
+        /// Only available with the `devnet-teardown` feature.
+        #[cfg(feature = "devnet-teardown")]
+        pub fn close_config(ctx: Context<CloseConfig>) -> Result<()> {
+            instructions::teardown::close_config_handler(ctx)
+        }
+
+        /// Close up to 16 of the caller's expired swap receipts, passed as
+        /// remaining accounts, and reclaim their rent
+        pub fn close_receipts_batch<'info>(
+            ctx: Context<'_, '_, 'info, 'info, CloseReceiptsBatch<'info>>,
+        ) -> Result<()> {
+            instructions::user::close_receipts_batch_handler(ctx)
+        }
+
+        /// Stage config changes for review without applying them (admin only)
+        pub fn stage_config(ctx: Context<StageConfig>, params: UpdateConfigParams) -> Result<()> {
+            instructions::admin::stage_config_handler(ctx, params)
+        }
+
+        /// Emit the `ConfigSnapshot` the staged changes would produce (read-only)
+        pub fn preview_staged_config(ctx: Context<PreviewStagedConfig>) -> Result<()> {
+            instructions::admin::preview_staged_config_handler(ctx)
+        }
+
+        /// Apply the staged config changes atomically (admin only)
+        pub fn apply_staged_config(ctx: Context<ApplyStagedConfig>) -> Result<()> {
+            instructions::admin::apply_staged_config_handler(ctx)
+        }
+
+        /// Grow a config created by an older program version to the current layout
+        /// (admin only); the fields added since start at their `initialize` defaults
+        pub fn migrate_config(
+            ctx: Context<MigrateConfig>,
+            max_quote_ttl_seconds: i64,
+        ) -> Result<()> {
+            instructions::migrate::migrate_config_handler(ctx, max_quote_ttl_seconds)
+        }
+
+        /// Grow the caller's stats account to the current layout
+        pub fn migrate_user_stats(ctx: Context<MigrateUserStats>) -> Result<()> {
+            instructions::migrate::migrate_user_stats_handler(ctx)
+        }
+
+        /// Grow one of the caller's swap receipts to the current layout
+        pub fn migrate_receipt(ctx: Context<MigrateReceipt>) -> Result<()> {
+            instructions::migrate::migrate_receipt_handler(ctx)
+        }
     }
 }
+
+pub use entrypoint::*;
@@ -0,0 +1,338 @@
+//! Oracle Price Module
+//!
+//! Reads Pyth pull-oracle price accounts and compares a route's implied
+//! price against the oracle price.
+//!
+//! ## Architecture
+//!
+//! Prices are read from `PriceUpdateV2` accounts owned by the Pyth receiver
+//! program. Both legs of a swap are priced in USD, so the fair output amount
+//! is derived from the ratio of the two feeds, adjusted for mint decimals.
+
+use anchor_lang::prelude::*;
+use anchor_lang::solana_program::pubkey;
+
+use crate::errors::FlowMintError;
+use crate::state::USD_VOLUME_DECIMALS;
+
+/// Pyth receiver program ID (owner of `PriceUpdateV2` accounts)
+pub const PYTH_RECEIVER_PROGRAM_ID: Pubkey = pubkey!("rec5EKMGg6MxZYaMdyBfgwp4d5rB9T1VQH5pJv5LtFJ");
+
+/// Anchor discriminator of the Pyth `PriceUpdateV2` account
+pub const PRICE_UPDATE_V2_DISCRIMINATOR: [u8; 8] = [34, 241, 35, 99, 157, 126, 244, 205];
+
+/// Maximum age of an oracle price in seconds
+pub const MAX_ORACLE_AGE_SECONDS: i64 = 60;
+
+/// Maximum confidence interval, relative to the price, in basis points (2%)
+pub const MAX_ORACLE_CONFIDENCE_BPS: u64 = 200;
+
+/// Maximum combined decimal exponent supported when scaling prices
+const MAX_SCALE_EXPONENT: i32 = 30;
+
+/// Oracle price read from a price feed
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct OraclePrice {
+    /// Pyth feed ID the price was published for
+    pub feed_id: [u8; 32],
+    /// Price mantissa
+    pub price: i64,
+    /// Confidence interval
+    pub conf: u64,
+    /// Price exponent (price * 10^exponent)
+    pub exponent: i32,
+    /// Unix timestamp of the price
+    pub publish_time: i64,
+}
+
+/// Read an oracle price from a Pyth `PriceUpdateV2` account
+///
+/// Only fully-verified updates for `expected_feed_id`, no older than
+/// `MAX_ORACLE_AGE_SECONDS` and with a confidence interval within
+/// `MAX_ORACLE_CONFIDENCE_BPS` of the price, are accepted.
+pub fn read_oracle_price(
+    account: &AccountInfo,
+    expected_feed_id: &[u8; 32],
+    current_timestamp: i64,
+) -> Result<OraclePrice> {
+    require_keys_eq!(
+        *account.owner,
+        PYTH_RECEIVER_PROGRAM_ID,
+        FlowMintError::InvalidOracle
+    );
+
+    let data = account.try_borrow_data()?;
+    let price = parse_price_update(&data)?;
+    check_oracle_price(&price, expected_feed_id, current_timestamp)?;
+
+    Ok(price)
+}
+
+/// Ensure a parsed price is for the expected feed, fresh and tightly bounded
+pub fn check_oracle_price(
+    price: &OraclePrice,
+    expected_feed_id: &[u8; 32],
+    current_timestamp: i64,
+) -> Result<()> {
+    require!(price.feed_id == *expected_feed_id, FlowMintError::InvalidOracle);
+    require!(
+        current_timestamp.saturating_sub(price.publish_time) <= MAX_ORACLE_AGE_SECONDS,
+        FlowMintError::StaleOraclePrice
+    );
+
+    // conf / price <= MAX_ORACLE_CONFIDENCE_BPS / 10_000
+    let conf_bps = (price.conf as u128).saturating_mul(10_000) / price.price as u128;
+    if conf_bps > MAX_ORACLE_CONFIDENCE_BPS as u128 {
+        msg!(
+            "Oracle confidence {} bps exceeds limit {} bps",
+            conf_bps,
+            MAX_ORACLE_CONFIDENCE_BPS
+        );
+        return Err(FlowMintError::OracleConfidenceTooWide.into());
+    }
+
+    Ok(())
+}
+
+/// Parse a `PriceUpdateV2` account's raw data
+pub fn parse_price_update(data: &[u8]) -> Result<OraclePrice> {
+    require!(
+        data.len() >= 8 && data[..8] == PRICE_UPDATE_V2_DISCRIMINATOR,
+        FlowMintError::InvalidOracle
+    );
+
+    // discriminator (8) + write_authority (32)
+    let mut offset = 40;
+
+    // VerificationLevel: 0 = Partial { num_signatures: u8 }, 1 = Full
+    let level = *data.get(offset).ok_or(FlowMintError::InvalidOracle)?;
+    require!(level == 1, FlowMintError::InvalidOracle);
+    offset += 1;
+
+    let feed_id: [u8; 32] = read_bytes(data, offset)?;
+    offset += 32;
+
+    let price = i64::from_le_bytes(read_bytes(data, offset)?);
+    offset += 8;
+    let conf = u64::from_le_bytes(read_bytes(data, offset)?);
+    offset += 8;
+    let exponent = i32::from_le_bytes(read_bytes(data, offset)?);
+    offset += 4;
+    let publish_time = i64::from_le_bytes(read_bytes(data, offset)?);
+
+    require!(price > 0, FlowMintError::InvalidOracle);
+
+    Ok(OraclePrice {
+        feed_id,
+        price,
+        conf,
+        exponent,
+        publish_time,
+    })
+}
+
+fn read_bytes<const N: usize>(data: &[u8], offset: usize) -> Result<[u8; N]> {
+    data.get(offset..offset + N)
+        .and_then(|bytes| bytes.try_into().ok())
+        .ok_or(FlowMintError::InvalidOracle.into())
+}
+
+/// Compute the fair output amount implied by two USD oracle prices
+///
+/// # Arguments
+/// * `amount_in` - Input amount in the input mint's base units
+/// * `input_price` - USD price of the input token
+/// * `output_price` - USD price of the output token
+/// * `input_decimals` - Decimals of the input mint
+/// * `output_decimals` - Decimals of the output mint
+pub fn fair_amount_out(
+    amount_in: u64,
+    input_price: &OraclePrice,
+    output_price: &OraclePrice,
+    input_decimals: u8,
+    output_decimals: u8,
+) -> Result<u64> {
+    require!(
+        input_price.price > 0 && output_price.price > 0,
+        FlowMintError::InvalidOracle
+    );
+
+    let scale = input_price.exponent - output_price.exponent + output_decimals as i32
+        - input_decimals as i32;
+    require!(
+        scale.abs() <= MAX_SCALE_EXPONENT,
+        FlowMintError::InvalidOracle
+    );
+
+    let pow = 10u128.pow(scale.unsigned_abs());
+    let mut numerator = (amount_in as u128)
+        .checked_mul(input_price.price as u128)
+        .ok_or(FlowMintError::MathOverflow)?;
+    let mut denominator = output_price.price as u128;
+
+    if scale >= 0 {
+        numerator = numerator
+            .checked_mul(pow)
+            .ok_or(FlowMintError::MathOverflow)?;
+    } else {
+        denominator = denominator
+            .checked_mul(pow)
+            .ok_or(FlowMintError::MathOverflow)?;
+    }
+
+    let fair = numerator
+        .checked_div(denominator)
+        .ok_or(FlowMintError::MathOverflow)?;
+
+    u64::try_from(fair).map_err(|_| FlowMintError::MathOverflow.into())
+}
+
+/// USD value of `amount` at an oracle price, on the 1e6 volume scale
+///
+/// Rounds down and saturates at `u64::MAX`; non-positive prices and
+/// unsupported exponents value at 0.
+pub fn usd_value(amount: u64, price: &OraclePrice, decimals: u8) -> u64 {
+    if price.price <= 0 {
+        return 0;
+    }
+
+    let scale = price.exponent + USD_VOLUME_DECIMALS as i32 - decimals as i32;
+    if scale.abs() > MAX_SCALE_EXPONENT {
+        return 0;
+    }
+
+    let value = (amount as u128).saturating_mul(price.price as u128);
+    let pow = 10u128.pow(scale.unsigned_abs());
+    let value = if scale >= 0 {
+        value.saturating_mul(pow)
+    } else {
+        value / pow
+    };
+    u64::try_from(value).unwrap_or(u64::MAX)
+}
+
+/// Deviation of a quoted output from the fair output, in basis points
+pub fn deviation_bps(quoted_out: u64, fair_out: u64) -> Result<u64> {
+    require!(fair_out > 0, FlowMintError::InvalidOracle);
+
+    let diff = quoted_out.abs_diff(fair_out) as u128;
+    let bps = diff
+        .checked_mul(10_000)
+        .ok_or(FlowMintError::MathOverflow)?
+        / fair_out as u128;
+
+    Ok(u64::try_from(bps).unwrap_or(u64::MAX))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn price(price: i64, exponent: i32) -> OraclePrice {
+        OraclePrice {
+            feed_id: [7u8; 32],
+            price,
+            conf: 0,
+            exponent,
+            publish_time: 0,
+        }
+    }
+
+    #[test]
+    fn test_usd_value() {
+        // 2 SOL (9 decimals) at $150.00000000
+        let sol = price(15_000_000_000, -8);
+        assert_eq!(usd_value(2_000_000_000, &sol, 9), 300_000_000);
+
+        // 1,000,000 BONK (5 decimals) at $0.000020
+        let bonk = price(20, -6);
+        assert_eq!(usd_value(100_000_000_000, &bonk, 5), 20_000_000);
+
+        assert_eq!(usd_value(2_000_000_000, &price(0, -8), 9), 0);
+        assert_eq!(usd_value(2_000_000_000, &price(-1, -8), 9), 0);
+    }
+
+    #[test]
+    fn test_parse_price_update() {
+        let mut data = PRICE_UPDATE_V2_DISCRIMINATOR.to_vec();
+        data.extend([0u8; 32]); // write authority
+        data.push(1); // Full verification
+        data.extend([7u8; 32]); // feed id
+        data.extend(15_000_000_000i64.to_le_bytes());
+        data.extend(1_000u64.to_le_bytes());
+        data.extend((-8i32).to_le_bytes());
+        data.extend(1_700_000_000i64.to_le_bytes());
+
+        let parsed = parse_price_update(&data).unwrap();
+        assert_eq!(parsed.feed_id, [7u8; 32]);
+        assert_eq!(parsed.price, 15_000_000_000);
+        assert_eq!(parsed.conf, 1_000);
+        assert_eq!(parsed.exponent, -8);
+        assert_eq!(parsed.publish_time, 1_700_000_000);
+
+        // Partially verified updates are rejected
+        data[40] = 0;
+        assert!(parse_price_update(&data).is_err());
+    }
+
+    #[test]
+    fn test_check_oracle_price() {
+        let now = 1_700_000_000;
+        let mut sol = OraclePrice {
+            publish_time: now,
+            ..price(15_000_000_000, -8)
+        };
+        assert!(check_oracle_price(&sol, &[7u8; 32], now).is_ok());
+
+        // A price published for another feed is rejected
+        assert_eq!(
+            check_oracle_price(&sol, &[8u8; 32], now).unwrap_err(),
+            FlowMintError::InvalidOracle.into()
+        );
+
+        // Stale prices are rejected
+        assert_eq!(
+            check_oracle_price(&sol, &[7u8; 32], now + MAX_ORACLE_AGE_SECONDS + 1).unwrap_err(),
+            FlowMintError::StaleOraclePrice.into()
+        );
+
+        // Confidence of 2% of the price passes; 2.01% fails
+        sol.conf = 300_000_000;
+        assert!(check_oracle_price(&sol, &[7u8; 32], now).is_ok());
+        sol.conf = 301_500_000;
+        assert_eq!(
+            check_oracle_price(&sol, &[7u8; 32], now).unwrap_err(),
+            FlowMintError::OracleConfidenceTooWide.into()
+        );
+    }
+
+    #[test]
+    fn test_fair_amount_out_with_decimals() {
+        // 1 SOL (9 decimals) at $150 -> USDC (6 decimals) at $1
+        let sol = price(15_000_000_000, -8);
+        let usdc = price(100_000_000, -8);
+
+        assert_eq!(
+            fair_amount_out(1_000_000_000, &sol, &usdc, 9, 6).unwrap(),
+            150_000_000
+        );
+        assert_eq!(
+            fair_amount_out(150_000_000, &usdc, &sol, 6, 9).unwrap(),
+            1_000_000_000
+        );
+    }
+
+    #[test]
+    fn test_oracle_band() {
+        let sol = price(15_000_000_000, -8);
+        let usdc = price(100_000_000, -8);
+        let fair = fair_amount_out(1_000_000_000, &sol, &usdc, 9, 6).unwrap();
+        let max_price_impact_bps = 100;
+
+        // In-band: route quotes 149.5 USDC (~0.33% off)
+        assert!(deviation_bps(149_500_000, fair).unwrap() <= max_price_impact_bps);
+
+        // Out-of-band: route quotes 140 USDC (~6.7% off)
+        assert!(deviation_bps(140_000_000, fair).unwrap() > max_price_impact_bps);
+    }
+}
@@ -4,10 +4,182 @@
 
 use anchor_lang::prelude::*;
 
+use crate::errors::FlowMintError;
+use crate::instructions::payment::is_usdc_mint;
+
+/// Decimals of the `total_volume_usd` counters (USD scaled by 1e6)
+pub const USD_VOLUME_DECIMALS: u8 = 6;
+
+/// Normalize a USD-stablecoin amount to the 1e6 volume scale
+pub fn normalize_usd_volume(amount: u64, decimals: u8) -> u64 {
+    let amount = amount as u128;
+    let scaled = if decimals >= USD_VOLUME_DECIMALS {
+        amount / 10u128.pow((decimals - USD_VOLUME_DECIMALS) as u32)
+    } else {
+        amount.saturating_mul(10u128.pow((USD_VOLUME_DECIMALS - decimals) as u32))
+    };
+    u64::try_from(scaled).unwrap_or(u64::MAX)
+}
+
+/// Maximum number of operators that can be registered
+pub const MAX_OPERATORS: usize = 4;
+
+/// Maximum grace window past quote expiration, in seconds
+pub const MAX_QUOTE_GRACE_SECONDS: i64 = 5;
+
+/// Maximum number of AMM programs in the allowlist
+pub const MAX_ALLOWLISTED_AMMS: usize = 16;
+
+/// Default delay between proposing and applying a treasury change (24h)
+pub const DEFAULT_TREASURY_TIMELOCK_SECONDS: i64 = 86_400;
+
+/// Shortest treasury timelock an update can set (1h)
+pub const MIN_TREASURY_TIMELOCK_SECONDS: i64 = 3_600;
+
+/// Default cap on remaining accounts passed to swaps and payments
+pub const DEFAULT_MAX_REMAINING_ACCOUNTS: u16 = 64;
+
+/// Maximum serialized size of a staged config change set
+pub const MAX_STAGED_CONFIG_LEN: usize = 512;
+
+/// Maximum number of alternate mints accepted for direct payments
+pub const MAX_DIRECT_SETTLEMENT_MINTS: usize = 4;
+
+/// Maximum number of volume-based fee tiers
+pub const MAX_FEE_TIERS: usize = 4;
+
+/// Volume-based protocol fee discount tier
+///
+/// A tier with a zero threshold is unset.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct FeeTier {
+    /// Lifetime user volume in USD (scaled by 1e6) at which the tier applies
+    pub volume_threshold_usd: u64,
+    /// Protocol fee charged once the threshold is reached
+    pub fee_bps: u16,
+}
+
+/// Maximum number of mints with a registered oracle feed
+pub const MAX_ORACLE_FEEDS: usize = 8;
+
+/// Pyth price feed registered for a mint
+///
+/// An entry with a default mint is unset.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct OracleFeed {
+    /// Mint priced by the feed
+    pub mint: Pubkey,
+    /// Pyth feed ID the mint's `PriceUpdateV2` accounts must carry
+    pub feed_id: [u8; 32],
+}
+
+/// Maximum number of notional-scaled slippage tiers
+pub const MAX_SLIPPAGE_TIERS: usize = 4;
+
+/// Notional-scaled slippage tier
+///
+/// Swaps whose USD notional reaches the threshold are held to the tier's
+/// slippage limit when it is tighter than the flat limit. A tier with a
+/// zero threshold is unset; with no tiers set, the flat limit applies.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct SlippageTier {
+    /// USD notional (1e6 scale) at which the tier applies
+    pub notional_usd_threshold: u64,
+    /// Maximum slippage once the threshold is reached
+    pub max_slippage_bps: u16,
+}
+
+/// Protocol feature that can be paused independently
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PausableFeature {
+    /// `execute_swap` and `swap_exact_out`
+    Swaps,
+    /// `pay_any_token`
+    Payments,
+}
+
+/// Who bears the protocol fee on payments
+///
+/// * `PayerPays` - the payer funds `exact_usdc_out + fee`; the merchant nets
+///   exactly `exact_usdc_out`.
+/// * `MerchantPays` - the payer funds exactly `exact_usdc_out`; the fee is
+///   deducted from it and the merchant nets `exact_usdc_out - fee`.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum FeePolicy {
+    /// Fee is added on top of the payment amount
+    #[default]
+    PayerPays,
+    /// Fee is deducted from the merchant's proceeds
+    MerchantPays,
+}
+
+/// How `pay_any_token` treats memo content
+///
+/// * `Truncate` - memos are stored as raw bytes, truncated to the maximum
+///   length (legacy behavior).
+/// * `Utf8` - memos must fit the maximum length and contain no control
+///   characters.
+/// * `PrintableAscii` - as `Utf8`, restricted to printable ASCII.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum MemoPolicy {
+    /// Store raw bytes, truncated
+    #[default]
+    Truncate,
+    /// Reject over-length memos, invalid UTF-8 and control characters
+    Utf8,
+    /// Reject over-length memos and anything but printable ASCII
+    PrintableAscii,
+}
+
+impl MemoPolicy {
+    /// Validate a memo's bytes against the policy
+    pub fn validate(&self, memo: &[u8], max_len: usize) -> Result<()> {
+        if *self == MemoPolicy::Truncate {
+            return Ok(());
+        }
+
+        require!(memo.len() <= max_len, FlowMintError::InvalidInstructionData);
+        let text = core::str::from_utf8(memo).map_err(|_| FlowMintError::InvalidInstructionData)?;
+        let allowed = match self {
+            MemoPolicy::Truncate => true,
+            MemoPolicy::Utf8 => !text.chars().any(char::is_control),
+            MemoPolicy::PrintableAscii => text.bytes().all(|b| b.is_ascii_graphic() || b == b' '),
+        };
+        require!(allowed, FlowMintError::InvalidInstructionData);
+        Ok(())
+    }
+}
+
+impl FeePolicy {
+    /// Split a payment into the USDC the payer must fund and the merchant's net
+    ///
+    /// # Returns
+    ///
+    /// * `(required_usdc_out, merchant_amount)`
+    pub fn settle(&self, exact_usdc_out: u64, protocol_fee: u64) -> Result<(u64, u64)> {
+        match self {
+            FeePolicy::PayerPays => {
+                let required = exact_usdc_out
+                    .checked_add(protocol_fee)
+                    .ok_or(FlowMintError::MathOverflow)?;
+                Ok((required, exact_usdc_out))
+            }
+            FeePolicy::MerchantPays => {
+                let merchant_amount = exact_usdc_out
+                    .checked_sub(protocol_fee)
+                    .ok_or(FlowMintError::MathOverflow)?;
+                Ok((exact_usdc_out, merchant_amount))
+            }
+        }
+    }
+}
+
 /// Protocol configuration account
 ///
 /// Stores global settings for the FlowMint protocol including
-/// slippage limits and protection parameters.
+/// slippage limits and protection parameters. Fields added after the first
+/// deployment go after `_reserved`, so older accounts stay a prefix of this
+/// layout and are grown with `migrate_config`.
 #[account]
 pub struct ProtocolConfig {
     /// The authority that can update the configuration
@@ -42,22 +214,134 @@ pub struct ProtocolConfig {
 
     /// Reserved space for future upgrades
     pub _reserved: [u8; 64],
+
+    /// Operators allowed to perform lower-risk actions (unused slots are default)
+    pub operators: [Pubkey; MAX_OPERATORS],
+
+    /// Maximum allowed output above the quote in basis points (0 = unbounded)
+    pub max_positive_slippage_bps: u16,
+
+    /// Maximum quote TTL (route `quote_expiration_seconds`) accepted
+    pub max_quote_ttl_seconds: i64,
+
+    /// Monotonic event sequence number, incremented once per state-mutating instruction
+    pub sequence: u64,
+
+    /// Share of the protocol fee paid to swap referrers, in basis points
+    pub referral_share_bps: u16,
+
+    /// Volume-based protocol fee discount tiers
+    pub fee_tiers: [FeeTier; MAX_FEE_TIERS],
+
+    /// Whether swaps are paused
+    pub swaps_paused: bool,
+
+    /// Whether payments are paused
+    pub payments_paused: bool,
+
+    /// Grace window past quote expiration to absorb clock skew, in seconds
+    pub quote_grace_seconds: i64,
+
+    /// Maximum USDC per payment (0 = unlimited)
+    pub max_payment_usdc: u64,
+
+    /// Mint payments settle in (default = known USDC mints)
+    pub settlement_mint: Pubkey,
+
+    /// Whether route steps must use allowlisted AMM programs
+    pub amm_allowlist_enabled: bool,
+
+    /// Who bears the protocol fee on payments
+    pub fee_policy: FeePolicy,
+
+    /// Notional-scaled slippage tiers (all unset = flat limit)
+    pub slippage_tiers: [SlippageTier; MAX_SLIPPAGE_TIERS],
+
+    /// Delay between proposing and applying a treasury change
+    pub treasury_timelock_seconds: i64,
+
+    /// Proposed treasury (default pubkey = none pending)
+    pub pending_treasury: Pubkey,
+
+    /// Earliest time the pending treasury can be applied
+    pub treasury_change_eta: i64,
+
+    /// Maximum route hops in protected mode (0 = unlimited)
+    pub max_hops_protected: u8,
+
+    /// Inactivity after which volume-derived user stats reset (0 = never)
+    pub stats_max_age_seconds: i64,
+
+    /// Alternate stable mints paid directly 1:1 without a swap (default = unused)
+    pub direct_settlement_mints: [Pubkey; MAX_DIRECT_SETTLEMENT_MINTS],
+
+    /// Require the merchant USDC account to be the canonical ATA
+    pub require_canonical_ata: bool,
+
+    /// Total protocol fees collected into FeeVaults (raw units, summed across mints)
+    pub total_fees_collected: u64,
+
+    /// Maximum payment change as a share of the USDC received, in bps (0 = unlimited)
+    pub max_refund_bps: u16,
+
+    /// Maximum quote TTL for payments (0 = same as `max_quote_ttl_seconds`)
+    pub payment_max_quote_ttl_seconds: i64,
+
+    /// Validation applied to payment memos
+    pub memo_policy: MemoPolicy,
+
+    /// Maximum remaining accounts accepted by swaps and payments (0 = unlimited)
+    pub max_remaining_accounts: u16,
+
+    /// Maximum decimals-adjusted route rate, or its inverse, before a route is rejected as implausible (0 = disabled)
+    pub max_route_rate_ratio: u64,
+
+    /// Expected Pyth feed per mint, checked whenever a price feed is read
+    pub oracle_feeds: [OracleFeed; MAX_ORACLE_FEEDS],
 }
 
 impl Default for ProtocolConfig {
     fn default() -> Self {
         Self {
             authority: Pubkey::default(),
+            operators: [Pubkey::default(); MAX_OPERATORS],
             default_slippage_bps: 0,
             protected_slippage_bps: 0,
             max_price_impact_bps: 0,
+            max_positive_slippage_bps: 0,
+            max_quote_ttl_seconds: 0,
             protected_mode_enabled: false,
             protocol_fee_bps: 0,
             treasury: Pubkey::default(),
             total_swaps: 0,
             total_volume_usd: 0,
+            sequence: 0,
+            referral_share_bps: 0,
+            fee_tiers: [FeeTier::default(); MAX_FEE_TIERS],
+            swaps_paused: false,
+            payments_paused: false,
+            quote_grace_seconds: 0,
+            max_payment_usdc: 0,
+            settlement_mint: Pubkey::default(),
+            amm_allowlist_enabled: false,
+            fee_policy: FeePolicy::PayerPays,
+            slippage_tiers: [SlippageTier::default(); MAX_SLIPPAGE_TIERS],
+            treasury_timelock_seconds: 0,
+            pending_treasury: Pubkey::default(),
+            treasury_change_eta: 0,
+            max_hops_protected: 0,
+            stats_max_age_seconds: 0,
+            direct_settlement_mints: [Pubkey::default(); MAX_DIRECT_SETTLEMENT_MINTS],
+            require_canonical_ata: false,
+            total_fees_collected: 0,
+            max_refund_bps: 0,
+            payment_max_quote_ttl_seconds: 0,
+            memo_policy: MemoPolicy::Truncate,
+            max_remaining_accounts: 0,
+            max_route_rate_ratio: 0,
             bump: 0,
             _reserved: [0u8; 64],
+            oracle_feeds: [OracleFeed::default(); MAX_ORACLE_FEEDS],
         }
     }
 }
@@ -75,22 +359,371 @@ impl ProtocolConfig {
         8 +  // total_swaps
         8 +  // total_volume_usd
         1 +  // bump
-        64;  // reserved
+        64 + // reserved
+        32 * MAX_OPERATORS + // operators
+        2 +  // max_positive_slippage_bps
+        8 +  // max_quote_ttl_seconds
+        8 +  // sequence
+        2 +  // referral_share_bps
+        10 * MAX_FEE_TIERS + // fee_tiers
+        1 +  // swaps_paused
+        1 +  // payments_paused
+        8 +  // quote_grace_seconds
+        8 +  // max_payment_usdc
+        32 + // settlement_mint
+        1 +  // amm_allowlist_enabled
+        1 +  // fee_policy
+        10 * MAX_SLIPPAGE_TIERS + // slippage_tiers
+        8 +  // treasury_timelock_seconds
+        32 + // pending_treasury
+        8 +  // treasury_change_eta
+        1 +  // max_hops_protected
+        8 +  // stats_max_age_seconds
+        32 * MAX_DIRECT_SETTLEMENT_MINTS + // direct_settlement_mints
+        1 +  // require_canonical_ata
+        8 +  // total_fees_collected
+        2 +  // max_refund_bps
+        8 +  // payment_max_quote_ttl_seconds
+        1 +  // memo_policy
+        2 +  // max_remaining_accounts
+        8 +  // max_route_rate_ratio
+        64 * MAX_ORACLE_FEEDS; // oracle_feeds
+
+    /// Whether a swap or payment must run in protected mode
+    ///
+    /// The caller's request can only tighten protection: the global setting
+    /// and the user's "always protected" flag each force protected mode on
+    /// their own.
+    pub fn is_protected_required(&self, requested: bool, user_always_protected: bool) -> bool {
+        requested || self.protected_mode_enabled || user_always_protected
+    }
+
+    /// Slippage cap that applies to a swap
+    ///
+    /// Returns the protected cap when protected mode is requested or globally
+    /// enabled, and the default cap otherwise.
+    pub fn effective_slippage_limit(&self, protected_mode: bool) -> u16 {
+        if self.is_protected_required(protected_mode, false) {
+            self.protected_slippage_bps
+        } else {
+            self.default_slippage_bps
+        }
+    }
 
     /// Validate slippage against configuration
     pub fn validate_slippage(&self, slippage_bps: u16, protected_mode: bool) -> bool {
-        if protected_mode || self.protected_mode_enabled {
-            slippage_bps <= self.protected_slippage_bps
+        slippage_bps <= self.effective_slippage_limit(protected_mode)
+    }
+
+    /// Slippage limit for a swap worth `notional_usd` (1e6 scale)
+    ///
+    /// Applies the tier with the highest threshold reached, but never loosens
+    /// the flat (or protected) limit. A swap whose notional can't be verified
+    /// is held to the flat limit.
+    pub fn slippage_limit_for_notional(
+        &self,
+        notional_usd: Option<u64>,
+        protected_mode: bool,
+    ) -> u16 {
+        let flat_limit = self.effective_slippage_limit(protected_mode);
+        let Some(notional_usd) = notional_usd else {
+            return flat_limit;
+        };
+        self.slippage_tiers
+            .iter()
+            .filter(|tier| tier.notional_usd_threshold > 0)
+            .filter(|tier| notional_usd >= tier.notional_usd_threshold)
+            .max_by_key(|tier| tier.notional_usd_threshold)
+            .map_or(flat_limit, |tier| tier.max_slippage_bps.min(flat_limit))
+    }
+
+    /// Validate slippage against configuration, scaled by trade notional
+    pub fn validate_slippage_for_notional(
+        &self,
+        slippage_bps: u16,
+        protected_mode: bool,
+        notional_usd: Option<u64>,
+    ) -> bool {
+        slippage_bps <= self.slippage_limit_for_notional(notional_usd, protected_mode)
+    }
+
+    /// Whether a route with `hops` steps may execute
+    ///
+    /// Only protected-mode swaps are capped.
+    pub fn is_hop_count_allowed(&self, hops: usize, protected_mode: bool) -> bool {
+        !protected_mode || self.max_hops_protected == 0 || hops <= self.max_hops_protected as usize
+    }
+
+    /// Whether a route's quote TTL is within the configured maximum
+    pub fn is_quote_ttl_allowed(&self, quote_expiration_seconds: i64) -> bool {
+        quote_expiration_seconds <= self.max_quote_ttl_seconds
+    }
+
+    /// Reject oversized remaining-account lists
+    ///
+    /// Bounds transaction size and the CPI account-meta loop.
+    pub fn check_remaining_accounts(&self, count: usize) -> Result<()> {
+        require!(
+            self.max_remaining_accounts == 0 || count <= self.max_remaining_accounts as usize,
+            FlowMintError::InvalidInstructionData
+        );
+        Ok(())
+    }
+
+    /// Maximum quote TTL accepted by payments
+    ///
+    /// Falls back to the swap TTL when no payment-specific TTL is set.
+    pub fn payment_quote_ttl_seconds(&self) -> i64 {
+        if self.payment_max_quote_ttl_seconds > 0 {
+            self.payment_max_quote_ttl_seconds
+        } else {
+            self.max_quote_ttl_seconds
+        }
+    }
+
+    /// Whether `key` is a registered operator
+    pub fn is_operator(&self, key: &Pubkey) -> bool {
+        *key != Pubkey::default() && self.operators.contains(key)
+    }
+
+    /// Whether `key` may perform operator-level actions (authority or operator)
+    pub fn can_operate(&self, key: &Pubkey) -> bool {
+        *key == self.authority || self.is_operator(key)
+    }
+
+    /// Register a new operator in the first free slot
+    pub fn add_operator(&mut self, operator: Pubkey) -> Result<()> {
+        require!(
+            operator != Pubkey::default() && !self.is_operator(&operator),
+            FlowMintError::InvalidConfiguration
+        );
+        let slot = self
+            .operators
+            .iter_mut()
+            .find(|slot| **slot == Pubkey::default())
+            .ok_or(FlowMintError::InvalidConfiguration)?;
+        *slot = operator;
+        Ok(())
+    }
+
+    /// Remove a registered operator
+    pub fn remove_operator(&mut self, operator: &Pubkey) -> Result<()> {
+        require!(self.is_operator(operator), FlowMintError::InvalidConfiguration);
+        for slot in self.operators.iter_mut().filter(|slot| *slot == operator) {
+            *slot = Pubkey::default();
+        }
+        Ok(())
+    }
+
+    /// Feed ID registered for `mint`, if any
+    pub fn oracle_feed_id(&self, mint: &Pubkey) -> Option<[u8; 32]> {
+        if *mint == Pubkey::default() {
+            return None;
+        }
+        self.oracle_feeds
+            .iter()
+            .find(|feed| feed.mint == *mint)
+            .map(|feed| feed.feed_id)
+    }
+
+    /// Register or replace the oracle feed for `mint`
+    pub fn set_oracle_feed(&mut self, mint: Pubkey, feed_id: [u8; 32]) -> Result<()> {
+        require!(
+            mint != Pubkey::default() && feed_id != [0u8; 32],
+            FlowMintError::InvalidConfiguration
+        );
+        let slot = match self.oracle_feeds.iter().position(|feed| feed.mint == mint) {
+            Some(index) => index,
+            None => self
+                .oracle_feeds
+                .iter()
+                .position(|feed| feed.mint == Pubkey::default())
+                .ok_or(FlowMintError::InvalidConfiguration)?,
+        };
+        self.oracle_feeds[slot] = OracleFeed { mint, feed_id };
+        Ok(())
+    }
+
+    /// Remove the oracle feed registered for `mint`
+    pub fn remove_oracle_feed(&mut self, mint: &Pubkey) -> Result<()> {
+        require!(
+            self.oracle_feed_id(mint).is_some(),
+            FlowMintError::InvalidConfiguration
+        );
+        for feed in self.oracle_feeds.iter_mut().filter(|feed| feed.mint == *mint) {
+            *feed = OracleFeed::default();
+        }
+        Ok(())
+    }
+
+    /// Whether `mint` is the mint payments settle in
+    ///
+    /// Falls back to the known USDC mints until a settlement mint is set.
+    pub fn is_settlement_mint(&self, mint: &Pubkey) -> bool {
+        if self.settlement_mint == Pubkey::default() {
+            is_usdc_mint(mint)
         } else {
-            slippage_bps <= self.default_slippage_bps
+            *mint == self.settlement_mint
+        }
+    }
+
+    /// Add a collected protocol fee to the running total
+    ///
+    /// Only fees sent to a FeeVault count; referral shares are excluded.
+    pub fn record_fee_collected(&mut self, fee: u64) {
+        self.total_fees_collected = self.total_fees_collected.saturating_add(fee);
+    }
+
+    /// Whether `mint` is an alternate mint accepted for direct 1:1 payments
+    pub fn is_direct_settlement_mint(&self, mint: &Pubkey) -> bool {
+        *mint != Pubkey::default() && self.direct_settlement_mints.contains(mint)
+    }
+
+    /// Whether a payment amount is within the per-payment cap
+    pub fn is_payment_allowed(&self, usdc_amount: u64) -> bool {
+        self.max_payment_usdc == 0 || usdc_amount <= self.max_payment_usdc
+    }
+
+    /// Whether a feature is paused
+    pub fn is_paused(&self, feature: PausableFeature) -> bool {
+        match feature {
+            PausableFeature::Swaps => self.swaps_paused,
+            PausableFeature::Payments => self.payments_paused,
+        }
+    }
+
+    /// Pause or resume a single feature
+    pub fn set_feature_paused(&mut self, feature: PausableFeature, paused: bool) {
+        match feature {
+            PausableFeature::Swaps => self.swaps_paused = paused,
+            PausableFeature::Payments => self.payments_paused = paused,
         }
     }
+
+    /// Validate invariants that must hold across all config fields
+    ///
+    /// Called after any update so that partial updates can never leave
+    /// protected mode looser than the default.
+    pub fn validate_invariants(&self) -> Result<()> {
+        require!(
+            self.protected_slippage_bps <= self.default_slippage_bps,
+            FlowMintError::InvalidConfiguration
+        );
+        require!(
+            (0..=MAX_QUOTE_GRACE_SECONDS).contains(&self.quote_grace_seconds),
+            FlowMintError::InvalidConfiguration
+        );
+        require!(
+            self.treasury_timelock_seconds >= 0,
+            FlowMintError::InvalidConfiguration
+        );
+        require!(
+            self.stats_max_age_seconds >= 0,
+            FlowMintError::InvalidConfiguration
+        );
+        require!(
+            self.max_refund_bps <= 10_000,
+            FlowMintError::InvalidConfiguration
+        );
+        require!(
+            self.payment_max_quote_ttl_seconds >= 0,
+            FlowMintError::InvalidConfiguration
+        );
+        // Fee tiers may only discount the flat protocol fee
+        require!(
+            self.fee_tiers
+                .iter()
+                .filter(|tier| tier.volume_threshold_usd > 0)
+                .all(|tier| tier.fee_bps <= self.protocol_fee_bps),
+            FlowMintError::InvalidConfiguration
+        );
+        Ok(())
+    }
+
+    /// Protocol fee that applies to a user with the given lifetime volume
+    ///
+    /// Uses the tier with the highest threshold the user has reached, falling
+    /// back to the flat `protocol_fee_bps` when no tier applies.
+    pub fn effective_fee_bps(&self, user_volume_usd: u64) -> u16 {
+        self.fee_tiers
+            .iter()
+            .filter(|tier| tier.volume_threshold_usd > 0)
+            .filter(|tier| user_volume_usd >= tier.volume_threshold_usd)
+            .max_by_key(|tier| tier.volume_threshold_usd)
+            .map_or(self.protocol_fee_bps, |tier| tier.fee_bps)
+    }
+
+    /// Protocol fee that applies to a user, honoring fee exemptions
+    pub fn fee_bps_for_user(&self, user_stats: &UserStats) -> u16 {
+        if user_stats.fee_exempt {
+            return 0;
+        }
+        self.effective_fee_bps(user_stats.total_volume_usd)
+    }
+
+    /// Split a protocol fee between the treasury and a referrer
+    ///
+    /// Returns `(treasury_fee, referrer_fee)`.
+    pub fn split_referral_fee(&self, protocol_fee: u64) -> Result<(u64, u64)> {
+        let referrer_fee = (protocol_fee as u128)
+            .checked_mul(self.referral_share_bps as u128)
+            .ok_or(FlowMintError::MathOverflow)?
+            .checked_div(10_000)
+            .ok_or(FlowMintError::MathOverflow)? as u64;
+        let treasury_fee = protocol_fee
+            .checked_sub(referrer_fee)
+            .ok_or(FlowMintError::MathOverflow)?;
+        Ok((treasury_fee, referrer_fee))
+    }
+
+    /// Propose a treasury change, returning the time it can be applied
+    ///
+    /// Replaces any earlier proposal and restarts the timelock.
+    pub fn propose_treasury(&mut self, treasury: Pubkey, current_timestamp: i64) -> Result<i64> {
+        let eta = current_timestamp
+            .checked_add(self.treasury_timelock_seconds)
+            .ok_or(FlowMintError::MathOverflow)?;
+        self.pending_treasury = treasury;
+        self.treasury_change_eta = eta;
+        Ok(eta)
+    }
+
+    /// Apply the pending treasury once its timelock has elapsed
+    pub fn apply_pending_treasury(&mut self, current_timestamp: i64) -> Result<Pubkey> {
+        require!(
+            self.pending_treasury != Pubkey::default(),
+            FlowMintError::NoPendingTreasury
+        );
+        require!(
+            current_timestamp >= self.treasury_change_eta,
+            FlowMintError::TimelockNotElapsed
+        );
+
+        self.treasury = self.pending_treasury;
+        self.pending_treasury = Pubkey::default();
+        self.treasury_change_eta = 0;
+        Ok(self.treasury)
+    }
+
+    /// Advance the event sequence and return the new value
+    ///
+    /// Must be called exactly once per state-mutating instruction so that
+    /// indexers can detect dropped or reordered events.
+    pub fn next_sequence(&mut self) -> Result<u64> {
+        self.sequence = self
+            .sequence
+            .checked_add(1)
+            .ok_or(FlowMintError::MathOverflow)?;
+        Ok(self.sequence)
+    }
 }
 
 /// Swap receipt account
 ///
 /// Stores information about a completed swap for tracking and auditing.
+/// Fields added after the first deployment go after `bump` (see `migrate_receipt`).
 #[account]
+#[derive(Default)]
 pub struct SwapReceipt {
     /// The user who initiated the swap
     pub user: Pubkey,
@@ -101,10 +734,10 @@ pub struct SwapReceipt {
     /// Output token mint
     pub output_mint: Pubkey,
 
-    /// Amount of input tokens
+    /// Amount of input tokens actually consumed
     pub amount_in: u64,
 
-    /// Amount of output tokens received
+    /// Amount of output tokens received, net of any protocol fee
     pub amount_out: u64,
 
     /// Slippage tolerance used
@@ -121,6 +754,19 @@ pub struct SwapReceipt {
 
     /// Bump seed for PDA derivation
     pub bump: u8,
+
+    /// Referrer credited for the swap (default pubkey if none)
+    pub referrer: Pubkey,
+
+    /// Fill quality of realized vs quoted amounts
+    pub status: FillStatus,
+
+    /// Caller-supplied order ID for reconciliation (zeroes if none; not unique)
+    pub client_order_id: [u8; 16],
+
+    /// Output per whole input token, decimals-normalized and scaled by
+    /// `EXECUTION_PRICE_SCALE` (0 if no input was consumed)
+    pub execution_price: u64,
 }
 
 impl SwapReceipt {
@@ -135,7 +781,348 @@ impl SwapReceipt {
         1 +  // protected_mode
         8 +  // timestamp
         32 + // tx_signature
+        1 +  // bump
+        32 + // referrer
+        1 +  // status
+        16 + // client_order_id
+        8;   // execution_price
+
+    /// Decimals-normalized price of a fill, scaled by `EXECUTION_PRICE_SCALE`
+    ///
+    /// `(amount_out / 10^output_decimals) / (amount_in / 10^input_decimals)`,
+    /// rounded down and saturating at `u64::MAX`; 0 when `amount_in` is 0.
+    pub fn execution_price(
+        amount_in: u64,
+        input_decimals: u8,
+        amount_out: u64,
+        output_decimals: u8,
+    ) -> u64 {
+        if amount_in == 0 {
+            return 0;
+        }
+        let numerator = (amount_out as u128)
+            .saturating_mul(EXECUTION_PRICE_SCALE as u128)
+            .saturating_mul(10u128.saturating_pow(input_decimals as u32));
+        let denominator =
+            (amount_in as u128).saturating_mul(10u128.saturating_pow(output_decimals as u32));
+        u64::try_from(numerator / denominator).unwrap_or(u64::MAX)
+    }
+}
+
+/// Fixed-point scale of `SwapReceipt::execution_price`
+pub const EXECUTION_PRICE_SCALE: u64 = 1_000_000;
+
+/// Fixed-point scale of `PriceTwap::twap_price`
+pub const TWAP_PRICE_SCALE: u128 = 1_000_000_000_000;
+
+/// Averaging window of the on-chain TWAP
+pub const TWAP_WINDOW_SECONDS: i64 = 300;
+
+/// Minimum gap between TWAP updates
+pub const MIN_TWAP_UPDATE_INTERVAL_SECONDS: i64 = 15;
+
+/// Samples needed before the TWAP is enforced
+pub const MIN_TWAP_SAMPLES: u32 = 3;
+
+/// Largest move of the TWAP in a single update, in bps of the average
+pub const MAX_TWAP_MOVE_BPS: u128 = 200;
+
+/// Swap notional (USD, 1e6 scale) at which a sample gets full weight
+pub const TWAP_FULL_WEIGHT_NOTIONAL_USD: u64 = 10_000_000_000;
+
+/// Notional (USD, 1e6 scale) assumed for samples from swaps that can't be
+/// valued, so they move the average at a tenth of the full weight
+pub const UNPRICED_TWAP_SAMPLE_NOTIONAL_USD: u64 = 1_000_000_000;
+
+/// Short time-weighted average of a pair's realized swap price
+///
+/// PDA seeds `[b"twap", input_mint, output_mint]`. Updated after every
+/// executed swap, at most once per `MIN_TWAP_UPDATE_INTERVAL_SECONDS`. The
+/// first `MIN_TWAP_SAMPLES` samples are averaged equally and the TWAP isn't
+/// enforced until then; afterwards each update moves the average toward the
+/// new price in proportion to the time elapsed (capped at
+/// `TWAP_WINDOW_SECONDS`) and the swap's notional (capped at
+/// `TWAP_FULL_WEIGHT_NOTIONAL_USD`), by at most `MAX_TWAP_MOVE_BPS`.
+#[account]
+#[derive(Default)]
+pub struct PriceTwap {
+    /// Input token mint
+    pub input_mint: Pubkey,
+
+    /// Output token mint
+    pub output_mint: Pubkey,
+
+    /// Average output per input (raw units, scaled by `TWAP_PRICE_SCALE`)
+    pub twap_price: u128,
+
+    /// Unix timestamp of the last update (0 = no samples yet)
+    pub last_update: i64,
+
+    /// Number of samples folded into the average
+    pub sample_count: u32,
+
+    /// Bump seed
+    pub bump: u8,
+}
+
+impl PriceTwap {
+    /// Size of the account in bytes
+    pub const SIZE: usize = 8 + // discriminator
+        32 + // input_mint
+        32 + // output_mint
+        16 + // twap_price
+        8 +  // last_update
+        4 +  // sample_count
+        1;   // bump
+
+    /// Read the TWAP at its PDA, or an empty one if no swap has created it yet
+    pub fn load(account: &AccountInfo) -> Result<Self> {
+        if account.data_is_empty() {
+            return Ok(Self::default());
+        }
+        require_keys_eq!(
+            *account.owner,
+            crate::ID,
+            ErrorCode::AccountOwnedByWrongProgram
+        );
+        PriceTwap::try_deserialize(&mut &account.try_borrow_data()?[..])
+    }
+
+    /// Scaled price of a fill, or `None` for zero amounts
+    pub fn implied_price(amount_in: u64, amount_out: u64) -> Option<u128> {
+        if amount_in == 0 || amount_out == 0 {
+            return None;
+        }
+        (amount_out as u128)
+            .checked_mul(TWAP_PRICE_SCALE)
+            .map(|scaled| scaled / amount_in as u128)
+    }
+
+    /// Fold a price sample from a swap worth `notional_usd` into the average
+    ///
+    /// Zero prices or notionals, and samples within
+    /// `MIN_TWAP_UPDATE_INTERVAL_SECONDS` of the last update, are ignored.
+    /// Returns whether the sample was recorded.
+    pub fn record_price(&mut self, price: u128, notional_usd: u64, current_timestamp: i64) -> bool {
+        if price == 0 || notional_usd == 0 {
+            return false;
+        }
+        let elapsed = current_timestamp.saturating_sub(self.last_update);
+        if self.last_update != 0 && elapsed < MIN_TWAP_UPDATE_INTERVAL_SECONDS {
+            return false;
+        }
+
+        self.twap_price = if self.sample_count < MIN_TWAP_SAMPLES {
+            // Warm up with an equal-weighted mean so no one sample seeds the average
+            let samples = self.sample_count as u128;
+            self.twap_price.saturating_mul(samples).saturating_add(price) / (samples + 1)
+        } else {
+            let time_weight = elapsed.min(TWAP_WINDOW_SECONDS) as u128;
+            let size_weight_bps = notional_usd.min(TWAP_FULL_WEIGHT_NOTIONAL_USD) as u128
+                * 10_000
+                / TWAP_FULL_WEIGHT_NOTIONAL_USD as u128;
+            let max_move = self.twap_price.saturating_mul(MAX_TWAP_MOVE_BPS) / 10_000;
+            let step = |distance: u128| {
+                (distance.saturating_mul(time_weight) / TWAP_WINDOW_SECONDS as u128)
+                    .saturating_mul(size_weight_bps)
+                    / 10_000
+            };
+            if price >= self.twap_price {
+                self.twap_price + step(price - self.twap_price).min(max_move)
+            } else {
+                self.twap_price - step(self.twap_price - price).min(max_move)
+            }
+        };
+        self.sample_count = self.sample_count.saturating_add(1);
+        self.last_update = current_timestamp;
+        true
+    }
+
+    /// Output the TWAP implies for `amount_in`, or `None` while warming up
+    pub fn fair_amount_out(&self, amount_in: u64) -> Option<u64> {
+        if self.sample_count < MIN_TWAP_SAMPLES {
+            return None;
+        }
+        let out = (amount_in as u128).checked_mul(self.twap_price)? / TWAP_PRICE_SCALE;
+        Some(u64::try_from(out).unwrap_or(u64::MAX))
+    }
+}
+
+/// Cumulative swap volume through a token, for analytics
+///
+/// PDA seeds `[b"token_volume", mint]`. Optional on swaps; at most the input
+/// and output mints' accounts are written per swap.
+#[account]
+#[derive(Default)]
+pub struct TokenVolume {
+    /// Token mint
+    pub mint: Pubkey,
+
+    /// Total amount swapped in as the input token (raw units)
+    pub volume_in: u64,
+
+    /// Total amount received as the output token (raw units)
+    pub volume_out: u64,
+
+    /// Number of recorded swaps touching the mint
+    pub swap_count: u64,
+
+    /// Timestamp of the last recorded swap
+    pub last_update: i64,
+
+    /// Bump seed
+    pub bump: u8,
+}
+
+impl TokenVolume {
+    /// Size of the account in bytes
+    pub const SIZE: usize = 8 + // discriminator
+        32 + // mint
+        8 +  // volume_in
+        8 +  // volume_out
+        8 +  // swap_count
+        8 +  // last_update
+        1;   // bump
+
+    /// Record a swap moving `amount_in` in and `amount_out` out of the mint
+    pub fn record_swap(&mut self, amount_in: u64, amount_out: u64, timestamp: i64) {
+        self.volume_in = self.volume_in.saturating_add(amount_in);
+        self.volume_out = self.volume_out.saturating_add(amount_out);
+        self.swap_count = self.swap_count.saturating_add(1);
+        self.last_update = timestamp;
+    }
+}
+
+/// Cumulative payment stats for a merchant
+///
+/// PDA seeds `[b"merchant_stats", merchant]`.
+#[account]
+#[derive(Default)]
+pub struct MerchantStats {
+    /// The merchant pubkey
+    pub merchant: Pubkey,
+
+    /// Total number of payments received
+    pub total_payments: u64,
+
+    /// Total settlement-mint amount received (raw units)
+    pub total_volume_usdc: u64,
+
+    /// Timestamp of the last payment received
+    pub last_payment: i64,
+
+    /// Bump seed
+    pub bump: u8,
+}
+
+impl MerchantStats {
+    /// Size of the account in bytes
+    pub const SIZE: usize = 8 + // discriminator
+        32 + // merchant
+        8 +  // total_payments
+        8 +  // total_volume_usdc
+        8 +  // last_payment
+        1;   // bump
+
+    /// Record a payment received by the merchant
+    pub fn record_payment(&mut self, usdc_amount: u64, timestamp: i64) {
+        self.total_payments = self.total_payments.saturating_add(1);
+        self.total_volume_usdc = self.total_volume_usdc.saturating_add(usdc_amount);
+        self.last_payment = timestamp;
+    }
+}
+
+/// Index from a merchant's payment reference to its payment record
+///
+/// PDA seeds `[b"payment_ref", merchant, reference]` let merchants derive a
+/// payment from its invoice reference. Created with `init`, so each
+/// reference can be used only once per merchant.
+#[account]
+pub struct PaymentReference {
+    /// The merchant/recipient
+    pub merchant: Pubkey,
+
+    /// Merchant-chosen payment reference (e.g. invoice ID)
+    pub reference: [u8; 32],
+
+    /// Payment record the reference points to
+    pub payment_record: Pubkey,
+
+    /// Bump seed
+    pub bump: u8,
+}
+
+impl PaymentReference {
+    /// Size of the account in bytes
+    pub const SIZE: usize = 8 + // discriminator
+        32 + // merchant
+        32 + // reference
+        32 + // payment_record
         1;   // bump
+
+    /// Derive the reference PDA for a merchant's payment reference
+    pub fn find_address(merchant: &Pubkey, reference: &[u8; 32]) -> (Pubkey, u8) {
+        Pubkey::find_program_address(
+            &[b"payment_ref", merchant.as_ref(), reference.as_ref()],
+            &crate::ID,
+        )
+    }
+}
+
+/// Fill quality recorded on a `SwapReceipt`
+///
+/// A fill is `Optimal` when it matched or beat the quote, `WithinTolerance`
+/// when it gave up at most half of the slippage allowance, and `Degraded`
+/// when it landed in the half of the allowance closest to the limit.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum FillStatus {
+    /// Matched or beat the quote
+    #[default]
+    Optimal,
+    /// Worse than quoted, but within half of the slippage allowance
+    WithinTolerance,
+    /// Barely met the slippage limit
+    Degraded,
+}
+
+impl FillStatus {
+    /// Classify an exact-in fill by realized output
+    ///
+    /// # Arguments
+    /// * `actual_out` - Output received
+    /// * `quoted_out` - Output quoted by the route
+    /// * `minimum_out` - Minimum acceptable output
+    pub fn from_output(actual_out: u64, quoted_out: u64, minimum_out: u64) -> Self {
+        if actual_out >= quoted_out {
+            return FillStatus::Optimal;
+        }
+        let shortfall = quoted_out - actual_out;
+        let allowance = quoted_out.saturating_sub(minimum_out);
+        Self::from_shortfall(shortfall, allowance)
+    }
+
+    /// Classify an exact-out fill by input spent
+    ///
+    /// # Arguments
+    /// * `actual_in` - Input spent
+    /// * `quoted_in` - Input quoted by the route
+    /// * `maximum_in` - Maximum acceptable input
+    pub fn from_input(actual_in: u64, quoted_in: u64, maximum_in: u64) -> Self {
+        if actual_in <= quoted_in {
+            return FillStatus::Optimal;
+        }
+        let overspend = actual_in - quoted_in;
+        let allowance = maximum_in.saturating_sub(quoted_in);
+        Self::from_shortfall(overspend, allowance)
+    }
+
+    fn from_shortfall(shortfall: u64, allowance: u64) -> Self {
+        if (shortfall as u128) * 2 <= allowance as u128 {
+            FillStatus::WithinTolerance
+        } else {
+            FillStatus::Degraded
+        }
+    }
 }
 
 /// Payment record account
@@ -183,11 +1170,81 @@ impl PaymentRecord {
         1 +  // memo_len
         8 +  // timestamp
         1;   // bump
+
+    /// Whether the record is between this payer and merchant
+    pub fn is_between(&self, payer: &Pubkey, merchant: &Pubkey) -> bool {
+        self.payer == *payer && self.merchant == *merchant
+    }
+}
+
+/// Allowlist of AMM programs route steps may touch
+///
+/// Enforced only while `ProtocolConfig::amm_allowlist_enabled` is set.
+#[account]
+pub struct AmmAllowlist {
+    /// Allowed AMM program IDs (default pubkey = empty slot)
+    pub programs: [Pubkey; MAX_ALLOWLISTED_AMMS],
+
+    /// Bump seed for PDA derivation
+    pub bump: u8,
+}
+
+impl AmmAllowlist {
+    /// Size of the account in bytes
+    pub const SIZE: usize = 8 + // discriminator
+        32 * MAX_ALLOWLISTED_AMMS + // programs
+        1;   // bump
+
+    /// Replace the allowlisted programs
+    pub fn set_programs(&mut self, programs: &[Pubkey]) -> Result<()> {
+        require!(
+            programs.len() <= MAX_ALLOWLISTED_AMMS
+                && programs.iter().all(|p| *p != Pubkey::default()),
+            FlowMintError::InvalidConfiguration
+        );
+        self.programs = [Pubkey::default(); MAX_ALLOWLISTED_AMMS];
+        self.programs[..programs.len()].copy_from_slice(programs);
+        Ok(())
+    }
+
+    /// Whether an AMM program is allowlisted
+    pub fn is_allowed(&self, program_id: &Pubkey) -> bool {
+        *program_id != Pubkey::default() && self.programs.contains(program_id)
+    }
+}
+
+/// Config changes staged for review before being applied
+///
+/// Holds a Borsh-serialized `UpdateConfigParams` written by `stage_config`
+/// and consumed atomically by `apply_staged_config`.
+#[account]
+pub struct PendingConfig {
+    /// Authority that staged the changes
+    pub staged_by: Pubkey,
+
+    /// Unix timestamp the changes were staged at
+    pub staged_at: i64,
+
+    /// Serialized `UpdateConfigParams`
+    pub params: Vec<u8>,
+
+    /// Bump seed for PDA derivation
+    pub bump: u8,
+}
+
+impl PendingConfig {
+    /// Size of the account in bytes
+    pub const SIZE: usize = 8 + // discriminator
+        32 + // staged_by
+        8 +  // staged_at
+        4 + MAX_STAGED_CONFIG_LEN + // params
+        1;   // bump
 }
 
 /// User stats account
 ///
 /// Tracks user-specific statistics for analytics.
+/// Fields added after the first deployment go after `bump` (see `migrate_user_stats`).
 #[account]
 #[derive(Default)]
 pub struct UserStats {
@@ -214,6 +1271,12 @@ pub struct UserStats {
 
     /// Bump seed
     pub bump: u8,
+
+    /// Force protected mode on every swap by this user
+    pub always_protected: bool,
+
+    /// Exempt from protocol fees (set by the authority for partners)
+    pub fee_exempt: bool,
 }
 
 impl UserStats {
@@ -226,5 +1289,767 @@ impl UserStats {
         8 +  // total_dca_orders
         8 +  // total_stop_loss_orders
         8 +  // last_activity
-        1;   // bump
+        1 +  // bump
+        1 +  // always_protected
+        1;   // fee_exempt
+
+    /// Reset volume-derived state if the user has been inactive too long
+    ///
+    /// Fee tiers are driven by `total_volume_usd`; a dormant user restarts
+    /// from zero. Disabled when `max_age_seconds` is 0. Returns whether the
+    /// stats were reset.
+    pub fn reset_if_dormant(&mut self, current_timestamp: i64, max_age_seconds: i64) -> bool {
+        if max_age_seconds == 0 || self.last_activity == 0 {
+            return false;
+        }
+        if current_timestamp.saturating_sub(self.last_activity) <= max_age_seconds {
+            return false;
+        }
+        self.total_volume_usd = 0;
+        true
+    }
+
+    /// Add USD volume (scaled by 1e6) to the user's lifetime total
+    pub fn record_volume(&mut self, volume_usd: u64) {
+        self.total_volume_usd = self.total_volume_usd.saturating_add(volume_usd);
+    }
+
+    /// Record a swap and return the user's 1-based receipt index
+    pub fn record_swap(&mut self, timestamp: i64) -> u64 {
+        self.total_swaps = self.total_swaps.saturating_add(1);
+        self.last_activity = timestamp;
+        self.total_swaps
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_is_protected_required() {
+        let mut config = ProtocolConfig::default();
+        assert!(!config.is_protected_required(false, false));
+
+        // Requested by the caller
+        assert!(config.is_protected_required(true, false));
+
+        // Forced by the user's flag even when not requested
+        assert!(config.is_protected_required(false, true));
+
+        // Forced globally
+        config.protected_mode_enabled = true;
+        assert!(config.is_protected_required(false, false));
+    }
+
+    #[test]
+    fn test_execution_price() {
+        // 2 SOL (9 dp) for 300 USDC (6 dp): 150 USDC per SOL
+        assert_eq!(
+            SwapReceipt::execution_price(2_000_000_000, 9, 300_000_000, 6),
+            150 * EXECUTION_PRICE_SCALE
+        );
+        // 300 USDC for 2 SOL: 0.00666... SOL per USDC
+        assert_eq!(SwapReceipt::execution_price(300_000_000, 6, 2_000_000_000, 9), 6_666);
+        // Same decimals
+        assert_eq!(SwapReceipt::execution_price(1_000_000, 6, 990_000, 6), 990_000);
+
+        // Nothing consumed
+        assert_eq!(SwapReceipt::execution_price(0, 6, 990_000, 6), 0);
+        // Saturates rather than wrapping
+        assert_eq!(SwapReceipt::execution_price(1, 0, u64::MAX, 0), u64::MAX);
+    }
+
+    #[test]
+    fn test_token_volume_accumulates() {
+        let mut volume = TokenVolume::default();
+
+        // The same mint swapped in twice, then received once
+        volume.record_swap(1_000, 0, 100);
+        volume.record_swap(2_500, 0, 200);
+        assert_eq!(volume.volume_in, 3_500);
+        assert_eq!(volume.volume_out, 0);
+        assert_eq!(volume.swap_count, 2);
+
+        volume.record_swap(0, 700, 300);
+        assert_eq!(volume.volume_in, 3_500);
+        assert_eq!(volume.volume_out, 700);
+        assert_eq!(volume.swap_count, 3);
+        assert_eq!(volume.last_update, 300);
+
+        volume.record_swap(u64::MAX, 0, 400);
+        assert_eq!(volume.volume_in, u64::MAX);
+    }
+
+    #[test]
+    fn test_remaining_accounts_cap() {
+        let mut config = ProtocolConfig {
+            max_remaining_accounts: DEFAULT_MAX_REMAINING_ACCOUNTS,
+            ..Default::default()
+        };
+        assert!(config.check_remaining_accounts(64).is_ok());
+        assert!(config.check_remaining_accounts(65).is_err());
+
+        config.max_remaining_accounts = 0;
+        assert!(config.check_remaining_accounts(1_000).is_ok());
+    }
+
+    #[test]
+    fn test_swap_receipt_client_order_id_round_trip() {
+        let client_order_id = *b"order-0000000042";
+        let receipt = SwapReceipt {
+            user: Pubkey::new_unique(),
+            input_mint: Pubkey::new_unique(),
+            output_mint: Pubkey::new_unique(),
+            amount_in: 1_000_000,
+            amount_out: 990_000,
+            slippage_bps: 50,
+            protected_mode: false,
+            timestamp: 1_000,
+            tx_signature: [0u8; 32],
+            referrer: Pubkey::default(),
+            status: FillStatus::Optimal,
+            client_order_id,
+            execution_price: 990_000,
+            bump: 255,
+        };
+
+        let bytes = receipt.try_to_vec().unwrap();
+        assert_eq!(bytes.len() + 8, SwapReceipt::SIZE);
+        let decoded = SwapReceipt::try_from_slice(&bytes).unwrap();
+        assert_eq!(decoded.client_order_id, client_order_id);
+    }
+
+    #[test]
+    fn test_memo_policy() {
+        let long = [b'a'; 65];
+
+        // Legacy: anything goes, the handler truncates
+        assert!(MemoPolicy::Truncate.validate(&long, 64).is_ok());
+        assert!(MemoPolicy::Truncate.validate(&[0xff, 0xfe], 64).is_ok());
+
+        for policy in [MemoPolicy::Utf8, MemoPolicy::PrintableAscii] {
+            assert!(policy.validate(b"Order #1234", 64).is_ok());
+            assert!(policy.validate(&long, 64).is_err());
+            assert!(policy.validate(&[0xff, 0xfe], 64).is_err());
+            assert!(policy.validate(b"line\nbreak", 64).is_err());
+        }
+
+        // Non-ASCII text is only accepted by the UTF-8 policy
+        assert!(MemoPolicy::Utf8.validate("café".as_bytes(), 64).is_ok());
+        assert!(MemoPolicy::PrintableAscii.validate("café".as_bytes(), 64).is_err());
+    }
+
+    #[test]
+    fn test_total_fees_collected() {
+        let mut config = ProtocolConfig::default();
+
+        // Two fee-bearing swaps
+        config.record_fee_collected(3_000);
+        config.record_fee_collected(1_250);
+        assert_eq!(config.total_fees_collected, 4_250);
+
+        config.record_fee_collected(u64::MAX);
+        assert_eq!(config.total_fees_collected, u64::MAX);
+    }
+
+    #[test]
+    fn test_direct_settlement_mints() {
+        let usdt = Pubkey::new_unique();
+        let mut config = ProtocolConfig::default();
+        assert!(!config.is_direct_settlement_mint(&usdt));
+        assert!(!config.is_direct_settlement_mint(&Pubkey::default()));
+
+        config.direct_settlement_mints[0] = usdt;
+        assert!(config.is_direct_settlement_mint(&usdt));
+        assert!(!config.is_direct_settlement_mint(&Pubkey::new_unique()));
+    }
+
+    #[test]
+    fn test_merchant_stats_accumulate() {
+        let mut stats = MerchantStats::default();
+
+        stats.record_payment(1_000_000, 100);
+        stats.record_payment(2_500_000, 200);
+
+        assert_eq!(stats.total_payments, 2);
+        assert_eq!(stats.total_volume_usdc, 3_500_000);
+        assert_eq!(stats.last_payment, 200);
+    }
+
+    #[test]
+    fn test_dormant_stats_reset() {
+        let day = 86_400;
+        let active = |last_activity| UserStats {
+            total_volume_usd: 5_000_000,
+            last_activity,
+            ..Default::default()
+        };
+
+        // Dormant user: volume resets
+        let mut stats = active(1_000);
+        assert!(stats.reset_if_dormant(1_000 + 31 * day, 30 * day));
+        assert_eq!(stats.total_volume_usd, 0);
+
+        // Recently active user: untouched
+        let mut stats = active(1_000);
+        assert!(!stats.reset_if_dormant(1_000 + day, 30 * day));
+        assert_eq!(stats.total_volume_usd, 5_000_000);
+
+        // Policy disabled
+        let mut stats = active(1_000);
+        assert!(!stats.reset_if_dormant(1_000 + 365 * day, 0));
+        assert_eq!(stats.total_volume_usd, 5_000_000);
+    }
+
+    #[test]
+    fn test_fee_exempt_user_pays_no_fee() {
+        let config = ProtocolConfig {
+            protocol_fee_bps: 30,
+            ..Default::default()
+        };
+        let mut stats = UserStats::default();
+        assert_eq!(config.fee_bps_for_user(&stats), 30);
+
+        stats.fee_exempt = true;
+        assert_eq!(config.fee_bps_for_user(&stats), 0);
+    }
+
+    #[test]
+    fn test_max_hops_protected() {
+        let mut config = ProtocolConfig::default();
+        assert!(config.is_hop_count_allowed(10, true));
+
+        config.max_hops_protected = 2;
+        assert!(config.is_hop_count_allowed(2, true));
+        assert!(!config.is_hop_count_allowed(3, true));
+
+        // Unprotected swaps are not capped
+        assert!(config.is_hop_count_allowed(3, false));
+    }
+
+    #[test]
+    fn test_treasury_timelock() {
+        let old_treasury = Pubkey::new_unique();
+        let new_treasury = Pubkey::new_unique();
+        let mut config = ProtocolConfig {
+            treasury: old_treasury,
+            treasury_timelock_seconds: 3_600,
+            ..Default::default()
+        };
+
+        assert!(config.apply_pending_treasury(0).is_err());
+
+        let eta = config.propose_treasury(new_treasury, 1_000).unwrap();
+        assert_eq!(eta, 4_600);
+
+        // Before the ETA the treasury is unchanged
+        assert!(config.apply_pending_treasury(4_599).is_err());
+        assert_eq!(config.treasury, old_treasury);
+
+        assert_eq!(config.apply_pending_treasury(4_600).unwrap(), new_treasury);
+        assert_eq!(config.treasury, new_treasury);
+        assert_eq!(config.pending_treasury, Pubkey::default());
+
+        // Proposal is consumed
+        assert!(config.apply_pending_treasury(10_000).is_err());
+    }
+
+    #[test]
+    fn test_price_twap_updates() {
+        let mut twap = PriceTwap {
+            input_mint: Pubkey::new_unique(),
+            output_mint: Pubkey::new_unique(),
+            twap_price: 0,
+            last_update: 0,
+            sample_count: 0,
+            bump: 0,
+        };
+        assert_eq!(twap.fair_amount_out(1_000), None);
+        let full = TWAP_FULL_WEIGHT_NOTIONAL_USD;
+
+        // One sample doesn't seed an enforceable average: 1 in -> 2 out, then
+        // 2.5 and 1.5 average back to 2
+        let price = |out| PriceTwap::implied_price(1_000, out).unwrap();
+        assert!(twap.record_price(price(2_000), full, 1_000));
+        assert_eq!(twap.fair_amount_out(1_000), None);
+        assert!(twap.record_price(price(2_500), full, 1_100));
+        assert!(twap.record_price(price(1_500), full, 1_200));
+        assert_eq!(twap.fair_amount_out(1_000), Some(2_000));
+
+        // A route quoting 1_000 -> 2_500 is 25% off the TWAP: outside a 1% band
+        let twap_out = twap.fair_amount_out(1_000).unwrap();
+        assert_eq!(crate::oracle::deviation_bps(2_500, twap_out).unwrap(), 2_500);
+        assert!(crate::oracle::deviation_bps(2_010, twap_out).unwrap() <= 100);
+
+        // Too soon, or no notional: ignored
+        assert!(!twap.record_price(price(4_000), full, 1_210));
+        assert!(!twap.record_price(price(4_000), 0, 2_000));
+        assert_eq!(twap.fair_amount_out(1_000), Some(2_000));
+
+        // A full window later, a spike moves the average by at most 2%
+        assert!(twap.record_price(price(4_000), full, 2_000));
+        assert_eq!(twap.fair_amount_out(1_000), Some(2_040));
+
+        // Half a window from a swap that can't be valued (a tenth of full
+        // weight): 5% of the way there
+        assert!(twap.record_price(price(2_440), UNPRICED_TWAP_SAMPLE_NOTIONAL_USD, 2_150));
+        assert_eq!(twap.fair_amount_out(1_000), Some(2_060));
+
+        assert_eq!(PriceTwap::implied_price(0, 1), None);
+    }
+
+    #[test]
+    fn test_price_twap_load() {
+        let key = Pubkey::new_unique();
+        let mut lamports = 0;
+
+        // A PDA no swap has created yet reads as an empty TWAP
+        let system = anchor_lang::system_program::ID;
+        let mut empty = vec![];
+        let account =
+            AccountInfo::new(&key, false, false, &mut lamports, &mut empty, &system, false, 0);
+        let loaded = PriceTwap::load(&account).unwrap();
+        assert_eq!((loaded.sample_count, loaded.twap_price), (0, 0));
+
+        let twap = PriceTwap {
+            twap_price: 7,
+            sample_count: 3,
+            ..PriceTwap::default()
+        };
+        let mut data = vec![];
+        twap.try_serialize(&mut data).unwrap();
+        let mut lamports = 0;
+        let account =
+            AccountInfo::new(&key, false, false, &mut lamports, &mut data, &crate::ID, false, 0);
+        let loaded = PriceTwap::load(&account).unwrap();
+        assert_eq!((loaded.sample_count, loaded.twap_price), (3, 7));
+
+        // Data owned by another program is never trusted
+        let mut lamports = 0;
+        let mut data = vec![];
+        twap.try_serialize(&mut data).unwrap();
+        let account =
+            AccountInfo::new(&key, false, false, &mut lamports, &mut data, &system, false, 0);
+        assert!(PriceTwap::load(&account).is_err());
+    }
+
+    #[test]
+    fn test_payment_reference_address() {
+        let merchant = Pubkey::new_unique();
+        let reference = [7u8; 32];
+
+        let (address, bump) = PaymentReference::find_address(&merchant, &reference);
+        let expected = Pubkey::create_program_address(
+            &[b"payment_ref", merchant.as_ref(), reference.as_ref(), &[bump]],
+            &crate::ID,
+        )
+        .unwrap();
+        assert_eq!(address, expected);
+
+        // Deterministic per merchant + reference
+        assert_eq!(PaymentReference::find_address(&merchant, &reference).0, address);
+        assert_ne!(PaymentReference::find_address(&merchant, &[8u8; 32]).0, address);
+        assert_ne!(
+            PaymentReference::find_address(&Pubkey::new_unique(), &reference).0,
+            address
+        );
+    }
+
+    #[test]
+    fn test_slippage_tiers_tighten_large_trades() {
+        let mut config = ProtocolConfig {
+            default_slippage_bps: 100,
+            protected_slippage_bps: 50,
+            ..Default::default()
+        };
+
+        // Flat mode by default
+        assert_eq!(config.slippage_limit_for_notional(Some(u64::MAX), false), 100);
+
+        // $1 and $100 tiers
+        config.slippage_tiers[0] = SlippageTier {
+            notional_usd_threshold: 1_000_000,
+            max_slippage_bps: 60,
+        };
+        config.slippage_tiers[1] = SlippageTier {
+            notional_usd_threshold: 100_000_000,
+            max_slippage_bps: 20,
+        };
+
+        assert_eq!(config.slippage_limit_for_notional(Some(999_999), false), 100);
+        assert_eq!(config.slippage_limit_for_notional(Some(1_000_000), false), 60);
+        assert_eq!(config.slippage_limit_for_notional(Some(500_000_000), false), 20);
+
+        // Same 80 bps request: fine for a small trade, rejected for a large one
+        assert!(config.validate_slippage_for_notional(80, false, Some(10_000)));
+        assert!(!config.validate_slippage_for_notional(80, false, Some(500_000_000)));
+
+        // Unverifiable notional falls back to the flat limit
+        assert_eq!(config.slippage_limit_for_notional(None, false), 100);
+
+        // Tiers never loosen the protected limit
+        assert_eq!(config.slippage_limit_for_notional(Some(1_000_000), true), 50);
+    }
+
+    #[test]
+    fn test_payment_record_parties() {
+        let payer = Pubkey::new_unique();
+        let merchant = Pubkey::new_unique();
+        let record = PaymentRecord {
+            payer,
+            merchant,
+            input_mint: Pubkey::new_unique(),
+            amount_in: 0,
+            usdc_amount: 0,
+            memo: [0u8; 64],
+            memo_len: 0,
+            timestamp: 0,
+            bump: 0,
+        };
+
+        assert!(record.is_between(&payer, &merchant));
+        // Both parties must match, in the right roles
+        assert!(!record.is_between(&merchant, &payer));
+        assert!(!record.is_between(&payer, &Pubkey::new_unique()));
+        assert!(!record.is_between(&Pubkey::new_unique(), &merchant));
+    }
+
+    #[test]
+    fn test_fill_status_from_output() {
+        // Quote 1_000_000, minimum 990_000 (1% allowance)
+        assert_eq!(FillStatus::from_output(1_000_000, 1_000_000, 990_000), FillStatus::Optimal);
+        assert_eq!(FillStatus::from_output(1_000_500, 1_000_000, 990_000), FillStatus::Optimal);
+        assert_eq!(FillStatus::from_output(995_000, 1_000_000, 990_000), FillStatus::WithinTolerance);
+        assert_eq!(FillStatus::from_output(990_001, 1_000_000, 990_000), FillStatus::Degraded);
+        assert_eq!(FillStatus::from_output(990_000, 1_000_000, 990_000), FillStatus::Degraded);
+    }
+
+    #[test]
+    fn test_fill_status_from_input() {
+        // Quote 1_000_000 in, maximum 1_010_000
+        assert_eq!(FillStatus::from_input(1_000_000, 1_000_000, 1_010_000), FillStatus::Optimal);
+        assert_eq!(FillStatus::from_input(1_004_000, 1_000_000, 1_010_000), FillStatus::WithinTolerance);
+        assert_eq!(FillStatus::from_input(1_009_999, 1_000_000, 1_010_000), FillStatus::Degraded);
+    }
+
+    #[test]
+    fn test_fee_policy_settlement() {
+        // Payer funds amount + fee, merchant nets the full amount
+        assert_eq!(
+            FeePolicy::PayerPays.settle(1_000_000, 3_000).unwrap(),
+            (1_003_000, 1_000_000)
+        );
+
+        // Payer funds the amount, merchant nets amount - fee
+        assert_eq!(
+            FeePolicy::MerchantPays.settle(1_000_000, 3_000).unwrap(),
+            (1_000_000, 997_000)
+        );
+
+        // No fee: both policies settle identically
+        assert_eq!(FeePolicy::PayerPays.settle(500, 0).unwrap(), (500, 500));
+        assert_eq!(FeePolicy::MerchantPays.settle(500, 0).unwrap(), (500, 500));
+
+        assert!(FeePolicy::PayerPays.settle(u64::MAX, 1).is_err());
+    }
+
+    #[test]
+    fn test_normalize_usd_volume() {
+        // 1 USD with 6 and 9 decimals lands on the same scale
+        assert_eq!(normalize_usd_volume(1_000_000, 6), 1_000_000);
+        assert_eq!(normalize_usd_volume(1_000_000_000, 9), 1_000_000);
+        assert_eq!(normalize_usd_volume(100, 2), 1_000_000);
+
+        assert_eq!(normalize_usd_volume(u64::MAX, 0), u64::MAX);
+    }
+
+    #[test]
+    fn test_record_swap_receipt_index() {
+        let mut stats = UserStats {
+            user: Pubkey::new_unique(),
+            total_swaps: 0,
+            total_volume_usd: 0,
+            total_payments: 0,
+            total_dca_orders: 0,
+            total_stop_loss_orders: 0,
+            last_activity: 0,
+            always_protected: false,
+            fee_exempt: false,
+            bump: 0,
+        };
+
+        assert_eq!(stats.record_swap(100), 1);
+        assert_eq!(stats.record_swap(200), 2);
+        assert_eq!(stats.total_swaps, 2);
+        assert_eq!(stats.last_activity, 200);
+    }
+
+    #[test]
+    fn test_protocol_config_size() {
+        let config = ProtocolConfig::default();
+        assert_eq!(config.try_to_vec().unwrap().len() + 8, ProtocolConfig::SIZE);
+    }
+
+    #[test]
+    fn test_invariants_reject_default_below_protected() {
+        let mut config = ProtocolConfig {
+            default_slippage_bps: 300,
+            protected_slippage_bps: 100,
+            ..Default::default()
+        };
+        assert!(config.validate_invariants().is_ok());
+
+        // Lowering default below the current protected value breaks the invariant
+        config.default_slippage_bps = 50;
+        assert!(config.validate_invariants().is_err());
+
+        config.default_slippage_bps = 100;
+        assert!(config.validate_invariants().is_ok());
+    }
+
+    #[test]
+    fn test_effective_slippage_limit() {
+        let mut config = ProtocolConfig {
+            default_slippage_bps: 300,
+            protected_slippage_bps: 100,
+            ..Default::default()
+        };
+
+        assert_eq!(config.effective_slippage_limit(false), 300);
+        assert_eq!(config.effective_slippage_limit(true), 100);
+        assert!(config.validate_slippage(200, false));
+        assert!(!config.validate_slippage(200, true));
+
+        // Global protected mode overrides the requested mode
+        config.protected_mode_enabled = true;
+        assert_eq!(config.effective_slippage_limit(false), 100);
+        assert!(!config.validate_slippage(200, false));
+    }
+
+    #[test]
+    fn test_quote_ttl_limit() {
+        let config = ProtocolConfig {
+            max_quote_ttl_seconds: 30,
+            ..Default::default()
+        };
+        assert!(config.is_quote_ttl_allowed(15));
+        assert!(config.is_quote_ttl_allowed(30));
+        assert!(!config.is_quote_ttl_allowed(3_600));
+    }
+
+    #[test]
+    fn test_operator_permissions() {
+        let authority = Pubkey::new_unique();
+        let operator = Pubkey::new_unique();
+        let mut config = ProtocolConfig {
+            authority,
+            ..Default::default()
+        };
+
+        assert!(!config.can_operate(&operator));
+        config.add_operator(operator).unwrap();
+
+        // Operator can perform operator-level actions (e.g. pausing)...
+        assert!(config.can_operate(&operator));
+        assert!(config.can_operate(&authority));
+        // ...but is not the authority required for treasury changes
+        assert_ne!(config.authority, operator);
+        assert!(!config.can_operate(&Pubkey::default()));
+
+        // Duplicates are rejected
+        assert!(config.add_operator(operator).is_err());
+
+        config.remove_operator(&operator).unwrap();
+        assert!(!config.can_operate(&operator));
+        assert!(config.remove_operator(&operator).is_err());
+    }
+
+    #[test]
+    fn test_operator_capacity() {
+        let mut config = ProtocolConfig::default();
+        for _ in 0..MAX_OPERATORS {
+            config.add_operator(Pubkey::new_unique()).unwrap();
+        }
+        assert!(config.add_operator(Pubkey::new_unique()).is_err());
+    }
+
+    #[test]
+    fn test_oracle_feed_registry() {
+        let mut config = ProtocolConfig::default();
+        let mint = Pubkey::new_unique();
+        assert_eq!(config.oracle_feed_id(&mint), None);
+
+        config.set_oracle_feed(mint, [1u8; 32]).unwrap();
+        assert_eq!(config.oracle_feed_id(&mint), Some([1u8; 32]));
+
+        // Re-registering replaces the feed in place
+        config.set_oracle_feed(mint, [2u8; 32]).unwrap();
+        assert_eq!(config.oracle_feed_id(&mint), Some([2u8; 32]));
+        assert_eq!(
+            config.oracle_feeds.iter().filter(|feed| feed.mint == mint).count(),
+            1
+        );
+
+        // Unset mints and feed IDs are rejected
+        assert!(config.set_oracle_feed(Pubkey::default(), [1u8; 32]).is_err());
+        assert!(config.set_oracle_feed(mint, [0u8; 32]).is_err());
+        assert_eq!(config.oracle_feed_id(&Pubkey::default()), None);
+
+        config.remove_oracle_feed(&mint).unwrap();
+        assert_eq!(config.oracle_feed_id(&mint), None);
+        assert!(config.remove_oracle_feed(&mint).is_err());
+
+        for _ in 0..MAX_ORACLE_FEEDS {
+            config.set_oracle_feed(Pubkey::new_unique(), [3u8; 32]).unwrap();
+        }
+        assert!(config.set_oracle_feed(Pubkey::new_unique(), [3u8; 32]).is_err());
+    }
+
+    #[test]
+    fn test_effective_fee_bps_tiers() {
+        let mut config = ProtocolConfig {
+            protocol_fee_bps: 30,
+            ..Default::default()
+        };
+
+        // No tiers: flat fee
+        assert_eq!(config.effective_fee_bps(0), 30);
+        assert_eq!(config.effective_fee_bps(u64::MAX), 30);
+
+        config.fee_tiers[0] = FeeTier {
+            volume_threshold_usd: 10_000_000_000,
+            fee_bps: 20,
+        };
+        config.fee_tiers[1] = FeeTier {
+            volume_threshold_usd: 100_000_000_000,
+            fee_bps: 10,
+        };
+        assert!(config.validate_invariants().is_ok());
+
+        assert_eq!(config.effective_fee_bps(9_999_999_999), 30);
+        assert_eq!(config.effective_fee_bps(10_000_000_000), 20);
+        assert_eq!(config.effective_fee_bps(100_000_000_000), 10);
+
+        // Tiers cannot charge more than the flat fee
+        config.fee_tiers[2] = FeeTier {
+            volume_threshold_usd: 1,
+            fee_bps: 50,
+        };
+        assert!(config.validate_invariants().is_err());
+    }
+
+    #[test]
+    fn test_quote_grace_bounds() {
+        let mut config = ProtocolConfig {
+            quote_grace_seconds: MAX_QUOTE_GRACE_SECONDS,
+            ..Default::default()
+        };
+        assert!(config.validate_invariants().is_ok());
+
+        config.quote_grace_seconds = MAX_QUOTE_GRACE_SECONDS + 1;
+        assert!(config.validate_invariants().is_err());
+
+        config.quote_grace_seconds = -1;
+        assert!(config.validate_invariants().is_err());
+    }
+
+    #[test]
+    fn test_amm_allowlist() {
+        let amm = Pubkey::new_unique();
+        let mut allowlist = AmmAllowlist {
+            programs: [Pubkey::default(); MAX_ALLOWLISTED_AMMS],
+            bump: 0,
+        };
+        assert_eq!(allowlist.try_to_vec().unwrap().len() + 8, AmmAllowlist::SIZE);
+
+        allowlist.set_programs(&[amm]).unwrap();
+        assert!(allowlist.is_allowed(&amm));
+        assert!(!allowlist.is_allowed(&Pubkey::new_unique()));
+        assert!(!allowlist.is_allowed(&Pubkey::default()));
+
+        assert!(allowlist
+            .set_programs(&[Pubkey::new_unique(); MAX_ALLOWLISTED_AMMS + 1])
+            .is_err());
+    }
+
+    #[test]
+    fn test_settlement_mint() {
+        let usdc: Pubkey = crate::instructions::payment::USDC_MINT_MAINNET.parse().unwrap();
+        let new_mint = Pubkey::new_unique();
+
+        // Unset: known USDC mints settle
+        let mut config = ProtocolConfig::default();
+        assert!(config.is_settlement_mint(&usdc));
+        assert!(!config.is_settlement_mint(&new_mint));
+
+        // After an update only the new mint settles
+        config.settlement_mint = new_mint;
+        assert!(config.is_settlement_mint(&new_mint));
+        assert!(!config.is_settlement_mint(&usdc));
+    }
+
+    #[test]
+    fn test_payment_cap() {
+        // Unlimited by default
+        let mut config = ProtocolConfig::default();
+        assert!(config.is_payment_allowed(u64::MAX));
+
+        config.max_payment_usdc = 10_000_000_000;
+        assert!(config.is_payment_allowed(10_000_000_000));
+        assert!(!config.is_payment_allowed(10_000_000_001));
+    }
+
+    #[test]
+    fn test_pause_features_independently() {
+        let mut config = ProtocolConfig::default();
+        assert!(!config.is_paused(PausableFeature::Swaps));
+        assert!(!config.is_paused(PausableFeature::Payments));
+
+        // Pausing payments still allows swaps
+        config.set_feature_paused(PausableFeature::Payments, true);
+        assert!(config.is_paused(PausableFeature::Payments));
+        assert!(!config.is_paused(PausableFeature::Swaps));
+
+        // And vice versa
+        config.set_feature_paused(PausableFeature::Payments, false);
+        config.set_feature_paused(PausableFeature::Swaps, true);
+        assert!(config.is_paused(PausableFeature::Swaps));
+        assert!(!config.is_paused(PausableFeature::Payments));
+    }
+
+    #[test]
+    fn test_split_referral_fee() {
+        let mut config = ProtocolConfig::default();
+
+        // No referral share: full fee to treasury
+        assert_eq!(config.split_referral_fee(1_000).unwrap(), (1_000, 0));
+
+        // 25% referral share
+        config.referral_share_bps = 2_500;
+        assert_eq!(config.split_referral_fee(1_000).unwrap(), (750, 250));
+        assert_eq!(config.split_referral_fee(0).unwrap(), (0, 0));
+    }
+
+    #[test]
+    fn test_sequence_increments_by_one() {
+        let mut config = ProtocolConfig::default();
+
+        // Two consecutive swaps
+        let first = config.next_sequence().unwrap();
+        let second = config.next_sequence().unwrap();
+
+        assert_eq!(first, 1);
+        assert_eq!(second, first + 1);
+        assert_eq!(config.sequence, second);
+    }
+
+    #[test]
+    fn test_sequence_overflow() {
+        let mut config = ProtocolConfig {
+            sequence: u64::MAX,
+            ..Default::default()
+        };
+        assert!(config.next_sequence().is_err());
+    }
 }
@@ -0,0 +1,336 @@
+//! Test Runtime
+//!
+//! Syscall stubs for tests that run instruction code off-chain.
+//!
+//! The stubs are process-wide, so every test installs the same ones: default
+//! sysvars, and CPIs and compute unit logs recorded per test thread. CPIs are
+//! recorded instead of executed, except that the system program's account
+//! creation and transfers, token transfers between initialized token
+//! accounts, and associated token account creation are applied, so tests can
+//! check the resulting accounts. Tests can stand in for other programs with
+//! `mock_program`.
+
+use std::cell::{Cell, RefCell};
+use std::rc::Rc;
+use std::sync::Once;
+
+use anchor_lang::prelude::*;
+use anchor_lang::solana_program::entrypoint::ProgramResult;
+use anchor_lang::solana_program::instruction::Instruction;
+use anchor_lang::solana_program::program_pack::Pack;
+use anchor_lang::solana_program::program_stubs::{set_syscall_stubs, SyscallStubs};
+use anchor_lang::system_program;
+use anchor_spl::associated_token;
+use anchor_spl::token::{self, spl_token};
+use anchor_spl::token_2022::{self, spl_token_2022};
+use spl_token_2022::extension::StateWithExtensionsMut;
+use spl_token_2022::instruction::TokenInstruction;
+
+/// Stand-in for a program's instructions
+type MockProgram = Rc<dyn Fn(&Instruction, &[AccountInfo]) -> ProgramResult>;
+
+thread_local! {
+    static INVOCATIONS: RefCell<Vec<Instruction>> = const { RefCell::new(Vec::new()) };
+    static COMPUTE_UNIT_LOGS: Cell<usize> = const { Cell::new(0) };
+    static MOCK_PROGRAMS: RefCell<Vec<(Pubkey, MockProgram)>> = const { RefCell::new(Vec::new()) };
+}
+
+struct TestRuntime;
+
+impl SyscallStubs for TestRuntime {
+    fn sol_invoke_signed(
+        &self,
+        instruction: &Instruction,
+        account_infos: &[AccountInfo],
+        _signers_seeds: &[&[&[u8]]],
+    ) -> ProgramResult {
+        INVOCATIONS.with(|invocations| invocations.borrow_mut().push(instruction.clone()));
+        let mock = MOCK_PROGRAMS.with(|mocks| {
+            let mocks = mocks.borrow();
+            let found = mocks.iter().find(|(program_id, _)| *program_id == instruction.program_id);
+            found.map(|(_, mock)| mock.clone())
+        });
+        if let Some(mock) = mock {
+            return mock(instruction, account_infos);
+        }
+        if instruction.program_id == system_program::ID {
+            return apply_system_instruction(instruction, account_infos);
+        }
+        if instruction.program_id == token::ID || instruction.program_id == token_2022::ID {
+            apply_token_transfer(instruction, account_infos);
+        } else if instruction.program_id == associated_token::ID {
+            apply_create_associated_account(instruction, account_infos);
+        }
+        Ok(())
+    }
+
+    fn sol_log_compute_units(&self) {
+        COMPUTE_UNIT_LOGS.with(|logs| logs.set(logs.get() + 1));
+    }
+
+    fn sol_get_rent_sysvar(&self, var_addr: *mut u8) -> u64 {
+        // SAFETY: the caller passes a pointer to a `Rent`
+        unsafe { *var_addr.cast::<Rent>() = Rent::default() };
+        0
+    }
+
+    fn sol_get_clock_sysvar(&self, var_addr: *mut u8) -> u64 {
+        // SAFETY: the caller passes a pointer to a `Clock`
+        unsafe { *var_addr.cast::<Clock>() = Clock::default() };
+        0
+    }
+}
+
+fn find_account<'a, 'info>(
+    account_infos: &'a [AccountInfo<'info>],
+    key: &Pubkey,
+) -> Option<&'a AccountInfo<'info>> {
+    account_infos.iter().find(|info| info.key == key)
+}
+
+/// `SystemError::AccountAlreadyInUse`
+const ACCOUNT_ALREADY_IN_USE: u32 = 0;
+
+/// Apply the system program's `create_account`, `assign`, `transfer` and
+/// `allocate`
+///
+/// As on-chain, only system-owned accounts without data can be created,
+/// allocated or assigned. Instructions for accounts that weren't passed are
+/// only recorded.
+fn apply_system_instruction(
+    instruction: &Instruction,
+    account_infos: &[AccountInfo],
+) -> ProgramResult {
+    let data = &instruction.data;
+    let u64_at = |offset: usize| u64::from_le_bytes(data[offset..offset + 8].try_into().unwrap());
+    let pubkey_at = |offset: usize| Pubkey::try_from(&data[offset..offset + 32]).unwrap();
+    let Some(accounts) = instruction
+        .accounts
+        .iter()
+        .map(|meta| find_account(account_infos, &meta.pubkey))
+        .collect::<Option<Vec<_>>>()
+    else {
+        return Ok(());
+    };
+    let unused =
+        |account: &AccountInfo| *account.owner == system_program::ID && account.data_is_empty();
+    let move_lamports = |from: &AccountInfo, to: &AccountInfo, lamports: u64| {
+        **from.lamports.borrow_mut() -= lamports;
+        **to.lamports.borrow_mut() += lamports;
+    };
+
+    match u32::from_le_bytes(data[..4].try_into().unwrap()) {
+        // CreateAccount { lamports, space, owner }
+        0 => {
+            let (from, to) = (accounts[0], accounts[1]);
+            if !unused(to) || to.lamports() > 0 {
+                return Err(ProgramError::Custom(ACCOUNT_ALREADY_IN_USE));
+            }
+            move_lamports(from, to, u64_at(4));
+            to.resize(u64_at(12) as usize)?;
+            to.assign(&pubkey_at(20));
+        }
+        // Assign { owner }
+        1 => {
+            if !unused(accounts[0]) {
+                return Err(ProgramError::Custom(ACCOUNT_ALREADY_IN_USE));
+            }
+            accounts[0].assign(&pubkey_at(4));
+        }
+        // Transfer { lamports }
+        2 => move_lamports(accounts[0], accounts[1], u64_at(4)),
+        // Allocate { space }
+        8 => {
+            if !unused(accounts[0]) {
+                return Err(ProgramError::Custom(ACCOUNT_ALREADY_IN_USE));
+            }
+            accounts[0].resize(u64_at(4) as usize)?;
+        }
+        _ => {}
+    }
+    Ok(())
+}
+
+/// Move the amount of a `transfer`/`transfer_checked` between token accounts
+///
+/// Transfers touching accounts without token account data are only recorded.
+fn apply_token_transfer(instruction: &Instruction, account_infos: &[AccountInfo]) {
+    let (amount, destination_index) = match TokenInstruction::unpack(&instruction.data) {
+        #[allow(deprecated)]
+        Ok(TokenInstruction::Transfer { amount }) => (amount, 1),
+        Ok(TokenInstruction::TransferChecked { amount, .. }) => (amount, 2),
+        _ => return,
+    };
+    let (Some(source), Some(destination)) = (
+        find_account(account_infos, &instruction.accounts[0].pubkey),
+        find_account(account_infos, &instruction.accounts[destination_index].pubkey),
+    ) else {
+        return;
+    };
+    move_tokens(source, destination, amount);
+}
+
+/// Move `amount` between two token accounts, if both hold token account data
+pub fn move_tokens(source: &AccountInfo, destination: &AccountInfo, amount: u64) {
+    if source.key == destination.key {
+        return;
+    }
+    let mut source_data = source.data.borrow_mut();
+    let mut destination_data = destination.data.borrow_mut();
+    let (Ok(mut from), Ok(mut to)) = (
+        StateWithExtensionsMut::<spl_token_2022::state::Account>::unpack(&mut source_data),
+        StateWithExtensionsMut::<spl_token_2022::state::Account>::unpack(&mut destination_data),
+    ) else {
+        return;
+    };
+    from.base.amount = from.base.amount.checked_sub(amount).expect("insufficient funds");
+    to.base.amount += amount;
+    from.pack_base();
+    to.pack_base();
+}
+
+/// Create the token account an associated token program `create` derives
+fn apply_create_associated_account(instruction: &Instruction, account_infos: &[AccountInfo]) {
+    let keys: Vec<&Pubkey> = instruction.accounts.iter().map(|meta| &meta.pubkey).collect();
+    let [payer, account, wallet, mint, _system_program, token_program] = keys[..] else {
+        return;
+    };
+    let (Some(payer), Some(account)) = (
+        find_account(account_infos, payer),
+        find_account(account_infos, account),
+    ) else {
+        return;
+    };
+    if !account.data_is_empty() {
+        return;
+    }
+
+    let lamports = Rent::default().minimum_balance(spl_token::state::Account::LEN);
+    **payer.lamports.borrow_mut() -= lamports;
+    **account.lamports.borrow_mut() += lamports;
+    account.resize(spl_token::state::Account::LEN).unwrap();
+    account.assign(token_program);
+    spl_token::state::Account {
+        mint: *mint,
+        owner: *wallet,
+        state: spl_token::state::AccountState::Initialized,
+        ..Default::default()
+    }
+    .pack_into_slice(&mut account.data.borrow_mut());
+}
+
+/// Install the test stubs (idempotent)
+pub fn install() {
+    static INSTALL: Once = Once::new();
+    INSTALL.call_once(|| {
+        set_syscall_stubs(Box::new(TestRuntime));
+    });
+}
+
+/// CPIs invoked on this thread since the last call
+pub fn take_invocations() -> Vec<Instruction> {
+    INVOCATIONS.with(|invocations| invocations.take())
+}
+
+/// Run `mock` for CPIs into `program_id` on this thread
+pub fn mock_program(
+    program_id: Pubkey,
+    mock: impl Fn(&Instruction, &[AccountInfo]) -> ProgramResult + 'static,
+) {
+    MOCK_PROGRAMS.with(|mocks| mocks.borrow_mut().push((program_id, Rc::new(mock))));
+}
+
+/// Compute unit logs on this thread since the last call
+#[cfg_attr(not(feature = "compute-logging"), allow(dead_code))]
+pub fn take_compute_unit_logs() -> usize {
+    COMPUTE_UNIT_LOGS.with(|logs| logs.take())
+}
+
+/// Account memory laid out as the runtime serializes it: the original data
+/// length sits just before the key and the current length just before the
+/// data, so instructions can resize the data in place
+#[repr(C)]
+pub struct RuntimeAccount {
+    _padding: u32,
+    original_data_len: u32,
+    pub key: Pubkey,
+    pub owner: Pubkey,
+    pub lamports: u64,
+    /// Data length followed by the data
+    pub data: Vec<u64>,
+}
+
+impl RuntimeAccount {
+    pub fn new(key: Pubkey, owner: Pubkey, lamports: u64, data: &[u8], capacity: usize) -> Self {
+        let mut account = Self {
+            _padding: 0,
+            original_data_len: capacity as u32,
+            key,
+            owner,
+            lamports,
+            data: vec![0u64; 1 + capacity.div_ceil(8)],
+        };
+        account.data[0] = data.len() as u64;
+        account.bytes()[..data.len()].copy_from_slice(data);
+        account
+    }
+
+    /// SPL token account holding `amount` of `mint` for `owner`
+    pub fn token_account(key: Pubkey, mint: Pubkey, owner: Pubkey, amount: u64) -> Self {
+        let mut data = vec![0u8; spl_token::state::Account::LEN];
+        spl_token::state::Account {
+            mint,
+            owner,
+            amount,
+            state: spl_token::state::AccountState::Initialized,
+            ..Default::default()
+        }
+        .pack_into_slice(&mut data);
+        Self::new(key, token::ID, 1, &data, data.len())
+    }
+
+    /// SPL token mint with `decimals`
+    pub fn mint(key: Pubkey, decimals: u8) -> Self {
+        let mut data = vec![0u8; spl_token::state::Mint::LEN];
+        spl_token::state::Mint {
+            decimals,
+            is_initialized: true,
+            ..Default::default()
+        }
+        .pack_into_slice(&mut data);
+        Self::new(key, token::ID, 1, &data, data.len())
+    }
+
+    /// Executable program account
+    pub fn program(key: Pubkey) -> Self {
+        Self::new(key, Pubkey::default(), 1, &[], 0)
+    }
+
+    pub fn bytes(&mut self) -> &mut [u8] {
+        let len = self.data[0] as usize;
+        // SAFETY: `data` holds the length word followed by `capacity` bytes
+        unsafe { std::slice::from_raw_parts_mut(self.data.as_mut_ptr().add(1).cast(), len) }
+    }
+
+    /// Token balance of an SPL token account
+    pub fn token_amount(&mut self) -> u64 {
+        spl_token::state::Account::unpack(self.bytes()).unwrap().amount
+    }
+
+    pub fn info(&mut self, is_signer: bool, executable: bool) -> AccountInfo<'_> {
+        let len = self.data[0] as usize;
+        // SAFETY: as in `bytes`; the length word precedes the slice as `resize` expects
+        let data =
+            unsafe { std::slice::from_raw_parts_mut(self.data.as_mut_ptr().add(1).cast(), len) };
+        AccountInfo::new(
+            &self.key,
+            is_signer,
+            true,
+            &mut self.lamports,
+            data,
+            &self.owner,
+            executable,
+            0,
+        )
+    }
+}
@@ -0,0 +1,246 @@
+//! Token Transfer Module
+//!
+//! Token transfers that work for both SPL Token and Token-2022 mints.
+//!
+//! ## Architecture
+//!
+//! Token-2022 mints with the transfer-hook extension require the hook
+//! program and its extra account metas to be passed along with the
+//! `transfer_checked` CPI. When the mint has a hook, the extra accounts are
+//! resolved from the instruction's remaining accounts; otherwise a plain
+//! `transfer_checked` CPI is used.
+
+use anchor_lang::prelude::*;
+use anchor_spl::token_2022::spl_token_2022::{
+    self,
+    extension::{transfer_hook, StateWithExtensions},
+};
+use anchor_spl::token_interface::{self, TransferChecked};
+
+use crate::errors::FlowMintError;
+
+/// Transfer hook program configured on a mint, if any
+pub fn transfer_hook_program_id(mint: &AccountInfo) -> Result<Option<Pubkey>> {
+    let data = mint.try_borrow_data()?;
+    parse_transfer_hook_program_id(mint.owner, &data)
+}
+
+/// Parse the transfer hook program ID from raw mint account data
+///
+/// Only Token-2022 mints can carry the transfer-hook extension.
+pub fn parse_transfer_hook_program_id(owner: &Pubkey, data: &[u8]) -> Result<Option<Pubkey>> {
+    if *owner != spl_token_2022::ID {
+        return Ok(None);
+    }
+
+    let mint = StateWithExtensions::<spl_token_2022::state::Mint>::unpack(data)
+        .map_err(|_| FlowMintError::InvalidMint)?;
+
+    Ok(transfer_hook::get_program_id(&mint))
+}
+
+/// Transfer tokens, invoking the mint's transfer hook when it has one
+///
+/// # Arguments
+/// * `token_program` - SPL Token or Token-2022 program
+/// * `from` - Source token account
+/// * `mint` - Mint of the transferred tokens
+/// * `to` - Destination token account
+/// * `authority` - Owner or delegate of the source account
+/// * `additional_accounts` - Accounts searched for the hook's extra account metas
+/// * `amount` - Amount to transfer
+/// * `decimals` - Decimals of the mint
+/// * `signer_seeds` - PDA signer seeds (empty when the authority signed)
+#[allow(clippy::too_many_arguments)]
+pub fn transfer_tokens<'info>(
+    token_program: &AccountInfo<'info>,
+    from: &AccountInfo<'info>,
+    mint: &AccountInfo<'info>,
+    to: &AccountInfo<'info>,
+    authority: &AccountInfo<'info>,
+    additional_accounts: &[AccountInfo<'info>],
+    amount: u64,
+    decimals: u8,
+    signer_seeds: &[&[&[u8]]],
+) -> Result<()> {
+    if transfer_hook_program_id(mint)?.is_some() {
+        spl_token_2022::onchain::invoke_transfer_checked(
+            token_program.key,
+            from.clone(),
+            mint.clone(),
+            to.clone(),
+            authority.clone(),
+            additional_accounts,
+            amount,
+            decimals,
+            signer_seeds,
+        )?;
+        return Ok(());
+    }
+
+    let cpi_ctx = CpiContext::new_with_signer(
+        token_program.clone(),
+        TransferChecked {
+            from: from.clone(),
+            mint: mint.clone(),
+            to: to.clone(),
+            authority: authority.clone(),
+        },
+        signer_seeds,
+    );
+    token_interface::transfer_checked(cpi_ctx, amount, decimals)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use anchor_lang::solana_program::instruction::{AccountMeta, Instruction};
+    use anchor_lang::solana_program::program_pack::Pack;
+    use spl_token_2022::extension::{
+        transfer_hook::TransferHook, BaseStateWithExtensionsMut, ExtensionType,
+        StateWithExtensionsMut,
+    };
+
+    fn token_2022_mint(hook_program_id: Option<Pubkey>) -> Vec<u8> {
+        let extensions = if hook_program_id.is_some() {
+            vec![ExtensionType::TransferHook]
+        } else {
+            vec![]
+        };
+        let len = ExtensionType::try_calculate_account_len::<spl_token_2022::state::Mint>(
+            &extensions,
+        )
+        .unwrap();
+        let mut data = vec![0u8; len];
+
+        let mut state =
+            StateWithExtensionsMut::<spl_token_2022::state::Mint>::unpack_uninitialized(&mut data)
+                .unwrap();
+        if let Some(program_id) = hook_program_id {
+            let hook = state.init_extension::<TransferHook>(true).unwrap();
+            hook.program_id = Some(program_id).try_into().unwrap();
+        }
+        state.base = spl_token_2022::state::Mint {
+            decimals: 6,
+            is_initialized: true,
+            ..Default::default()
+        };
+        state.pack_base();
+        if hook_program_id.is_some() {
+            state.init_account_type().unwrap();
+        }
+
+        data
+    }
+
+    #[test]
+    fn test_transfer_hook_detected() {
+        let hook_program_id = Pubkey::new_unique();
+        let data = token_2022_mint(Some(hook_program_id));
+
+        assert_eq!(
+            parse_transfer_hook_program_id(&spl_token_2022::ID, &data).unwrap(),
+            Some(hook_program_id)
+        );
+    }
+
+    #[test]
+    fn test_no_transfer_hook() {
+        // Token-2022 mint without extensions
+        let data = token_2022_mint(None);
+        assert_eq!(
+            parse_transfer_hook_program_id(&spl_token_2022::ID, &data).unwrap(),
+            None
+        );
+
+        // Classic SPL Token mints never have hooks
+        let mut data = vec![0u8; spl_token_2022::state::Mint::LEN];
+        spl_token_2022::state::Mint {
+            decimals: 6,
+            is_initialized: true,
+            ..Default::default()
+        }
+        .pack_into_slice(&mut data);
+        assert_eq!(
+            parse_transfer_hook_program_id(&anchor_spl::token::ID, &data).unwrap(),
+            None
+        );
+    }
+
+    /// Transfer 500 from `accounts` = [program, source, mint, destination,
+    /// authority], returning the CPIs it made
+    fn transfer_500<'info>(
+        accounts: &[AccountInfo<'info>],
+        additional_accounts: &[AccountInfo<'info>],
+    ) -> (Result<()>, Vec<Instruction>) {
+        let [program, source, mint, destination, authority] = accounts else {
+            panic!("expected five accounts");
+        };
+        let result = transfer_tokens(
+            program,
+            source,
+            mint,
+            destination,
+            authority,
+            additional_accounts,
+            500,
+            6,
+            &[],
+        );
+        (result, crate::test_runtime::take_invocations())
+    }
+
+    #[test]
+    fn test_hook_mint_transfers_through_hook_program() {
+        crate::test_runtime::install();
+        let hook_program_id = Pubkey::new_unique();
+        let keys = [
+            spl_token_2022::ID,
+            Pubkey::new_unique(),
+            Pubkey::new_unique(),
+            Pubkey::new_unique(),
+            Pubkey::new_unique(),
+            hook_program_id,
+        ];
+        let owners = [
+            Pubkey::default(),
+            spl_token_2022::ID,
+            spl_token_2022::ID,
+            spl_token_2022::ID,
+            Pubkey::default(),
+            Pubkey::default(),
+        ];
+        let mut lamports = [0u64; 6];
+        let mut data = vec![vec![]; 6];
+        data[2] = token_2022_mint(Some(hook_program_id));
+        let accounts: Vec<AccountInfo> = keys
+            .iter()
+            .zip(&owners)
+            .zip(lamports.iter_mut().zip(data.iter_mut()))
+            .enumerate()
+            .map(|(i, ((key, owner), (lamports, data)))| {
+                let executable = i == 0 || i == 5;
+                let (is_signer, is_writable) = (i == 4, i == 1 || i == 3);
+                AccountInfo::new(key, is_signer, is_writable, lamports, data, owner, executable, 0)
+            })
+            .collect();
+        let (transfer_accounts, hook_program) = accounts.split_at(5);
+
+        // The hook program must be among the additional accounts
+        let (result, invocations) = transfer_500(transfer_accounts, &[]);
+        assert!(result.is_err());
+        assert!(invocations.is_empty());
+
+        // `transfer_checked` is invoked with the hook program appended
+        let (result, invocations) = transfer_500(transfer_accounts, hook_program);
+        result.unwrap();
+        let mut expected = spl_token_2022::instruction::transfer_checked(
+            &keys[0], &keys[1], &keys[2], &keys[3], &keys[4], &[], 500, 6,
+        )
+        .unwrap();
+        expected
+            .accounts
+            .push(AccountMeta::new_readonly(hook_program_id, false));
+        assert_eq!(invocations, vec![expected]);
+    }
+}